@@ -0,0 +1,194 @@
+//! Spectral analysis of decoded audio.
+//!
+//! Lossy encoders discard everything above a lowpass frequency, usually
+//! between 16 kHz and 20 kHz, to save bits. A lossless file that was
+//! transcoded from a lossy source keeps this cutoff, so its spectrum falls
+//! off a cliff well below the Nyquist frequency.
+//!
+//! The cutoff is estimated from the average spectrum of the decoded audio,
+//! as the highest frequency where the spectrum drops steeply. This is a
+//! heuristic: band-limited recordings can have a low cutoff without ever
+//! having been lossy.
+
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{ErrorKind, Result};
+use std::path::Path;
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::{not_found, unsupported};
+
+/// The number of samples per analyzed frame.
+const FFT_SIZE: usize = 4096;
+
+/// The maximum number of frames to analyze, which is about three minutes
+/// of audio at 44.1 kHz. The lowpass of a lossy encoder is constant, so
+/// there is nothing to gain from decoding the rest of a long track.
+const MAX_FRAMES: usize = 2048;
+
+/// Frames quieter than this RMS level do not contribute to the spectrum.
+const SILENCE_RMS: f32 = 1e-4;
+
+/// The width of the bands compared to find the cutoff, in bins.
+const CLIFF_WIDTH: usize = 32;
+
+/// How much quieter a band must be than the band below it to be past the
+/// cutoff, in dB. Natural rolloff is much more gradual than a lowpass filter.
+const CLIFF_DEPTH_DB: f64 = 30.0;
+
+/// Accumulates the average power spectrum of mono frames.
+struct Spectrum {
+    fft: std::sync::Arc<dyn rustfft::Fft<f32>>,
+    window: Vec<f32>,
+    power: Vec<f64>,
+    frames: usize,
+}
+
+impl Spectrum {
+    fn new() -> Spectrum {
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / (FFT_SIZE - 1) as f32).cos())
+            .collect();
+        Spectrum {
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            power: vec![0.0; FFT_SIZE / 2],
+            frames: 0,
+        }
+    }
+
+    fn add_frame(&mut self, frame: &[f32]) {
+        let rms = (frame.iter().map(|sample| sample * sample).sum::<f32>() / frame.len() as f32).sqrt();
+        if rms < SILENCE_RMS {
+            return;
+        }
+
+        let mut buffer = frame
+            .iter()
+            .zip(self.window.iter())
+            .map(|(sample, window)| Complex::new(sample * window, 0.0))
+            .collect::<Vec<Complex<f32>>>();
+        self.fft.process(&mut buffer);
+        for (power, bin) in self.power.iter_mut().zip(buffer.iter()) {
+            *power += bin.norm_sqr() as f64;
+        }
+        self.frames += 1;
+    }
+
+    /// Estimates the cutoff frequency in Hz, or `None` if every frame was silent.
+    /// Audio without a cutoff is reported as cutting off at the Nyquist frequency.
+    fn cutoff(&self, sample_rate: u32) -> Option<i32> {
+        if self.frames == 0 {
+            return None;
+        }
+
+        // The power is only compared against itself, so it does not need
+        // to be normalized by the number of frames.
+        let levels = self
+            .power
+            .iter()
+            .map(|power| 10.0 * (power + 1e-20).log10())
+            .collect::<Vec<f64>>();
+        let mean = |band: &[f64]| band.iter().sum::<f64>() / band.len() as f64;
+        let to_hz = |bin: usize| (bin as u64 * sample_rate as u64 / FFT_SIZE as u64) as i32;
+
+        // Find the highest band that is much louder than the band above it,
+        // then the last bin in it that is louder than the midpoint of the cliff.
+        for bin in (CLIFF_WIDTH..levels.len() - CLIFF_WIDTH).rev() {
+            let below = mean(&levels[bin - CLIFF_WIDTH..bin]);
+            let above = mean(&levels[bin..bin + CLIFF_WIDTH]);
+            if below - above >= CLIFF_DEPTH_DB {
+                let midpoint = (below + above) / 2.0;
+                let cutoff_bin = (bin - CLIFF_WIDTH..bin)
+                    .rev()
+                    .find(|&i| levels[i] >= midpoint)
+                    .unwrap_or(bin);
+                return Some(to_hz(cutoff_bin + 1));
+            }
+        }
+        Some(to_hz(levels.len()))
+    }
+}
+
+/// Estimates the frequency in Hz above which the audio of the file at the
+/// given path carries no signal, by decoding up to the first few minutes.
+///
+/// Returns `Ok(None)` if the audio is silent or could not be decoded.
+pub fn spectral_cutoff(path: &Path) -> Result<Option<i32>> {
+    if !path.exists() {
+        return Err(not_found(path));
+    }
+
+    let file = File::open(path)?;
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let probed = symphonia::default::get_probe()
+        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|_| unsupported(path))?;
+    let mut format = probed.format;
+
+    let track = match format.tracks().iter().find(|track| track.codec_params.codec != CODEC_TYPE_NULL) {
+        Some(track) => track,
+        None => return Err(unsupported(path)),
+    };
+    let track_id = track.id;
+    let sample_rate = match track.codec_params.sample_rate {
+        Some(sample_rate) => sample_rate,
+        None => return Ok(None),
+    };
+    let mut decoder = match symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default()) {
+        Ok(decoder) => decoder,
+        Err(_) => return Ok(None),
+    };
+
+    let mut spectrum = Spectrum::new();
+    let mut mono = Vec::<f32>::with_capacity(FFT_SIZE * 2);
+    let mut frames = 0;
+    while frames < MAX_FRAMES {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(ref err)) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(DecodeError::IoError(err)) => return Err(err),
+            Err(_) => break,
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Skip over corrupt packets, the rest of the file may still be fine.
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(_) => break,
+        };
+
+        let channels = decoded.spec().channels.count().max(1);
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        samples.copy_interleaved_ref(decoded);
+        mono.extend(
+            samples
+                .samples()
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+
+        while mono.len() >= FFT_SIZE && frames < MAX_FRAMES {
+            spectrum.add_frame(&mono[..FFT_SIZE]);
+            mono.drain(..FFT_SIZE);
+            frames += 1;
+        }
+    }
+
+    Ok(spectrum.cutoff(sample_rate))
+}
@@ -0,0 +1,16 @@
+//! Tag reading backends.
+//!
+//! Every backend exposes the same two functions, `read_track` and
+//! `read_front_cover`, with the same contract as `Track::from_path` and
+//! `Track::front_cover_from_path`. Backends are selected with cargo features,
+//! and both may be compiled at once to compare their results.
+
+#[cfg(feature = "native")]
+pub mod native;
+#[cfg(feature = "taglib")]
+pub mod taglib;
+
+#[cfg(feature = "native")]
+pub(crate) use self::native as selected;
+#[cfg(all(feature = "taglib", not(feature = "native")))]
+pub(crate) use self::taglib as selected;
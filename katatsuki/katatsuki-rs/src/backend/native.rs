@@ -0,0 +1,1018 @@
+//! The native backend, which reads tags and audio properties in pure Rust
+//! with [symphonia](https://github.com/pdeljanov/Symphonia).
+//!
+//! This backend follows the behaviour of the taglib backend as closely as
+//! possible, so that both backends return the same `Track` for the same file.
+//! Bitrates of lossy and compressed files are estimated from the size of the
+//! file, and may differ slightly from taglib.
+//!
+//! Monkey's Audio and WavPack files are not supported.
+
+use std::fs::File;
+use std::io::{Cursor, Read, Result, Seek, SeekFrom};
+use std::path::Path;
+
+use chrono::Local;
+use symphonia::core::audio::Channels;
+use symphonia::core::codecs::*;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey, StandardVisualKey, Tag, Visual};
+use symphonia::core::probe::{Hint, Instantiate};
+
+use crate::{cover_dimensions, cover_mime_type, not_found, unsupported, COVER_HEADER_SIZE};
+use crate::mpeg::{self, id3v2_size, FrameHeader};
+use crate::{BitrateMode, CodecProfile, Track, TrackFileType};
+
+/// The container of a probed file, where the file type can not be
+/// determined from the codec alone.
+#[derive(PartialEq)]
+enum Container {
+    Aiff,
+    Wav,
+    Dsf,
+    Other,
+}
+
+impl Container {
+    fn from_head(head: &[u8]) -> Container {
+        match head {
+            [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', b'F', ..] => Container::Aiff,
+            [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', b'C', ..] => Container::Aiff,
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Container::Wav,
+            [b'D', b'S', b'D', b' ', ..] => Container::Dsf,
+            _ => Container::Other,
+        }
+    }
+}
+
+/// The tags, visuals and audio properties of a probed file.
+struct ProbedFile {
+    container: Container,
+    tags: Vec<Tag>,
+    visuals: Vec<Visual>,
+    codec_params: CodecParameters,
+}
+
+impl ProbedFile {
+    fn new(container: Container, codec_params: CodecParameters) -> ProbedFile {
+        ProbedFile {
+            container,
+            tags: Vec::new(),
+            visuals: Vec::new(),
+            codec_params,
+        }
+    }
+
+    fn collect(&mut self, revision: &MetadataRevision) {
+        self.tags.extend_from_slice(revision.tags());
+        self.visuals.extend_from_slice(revision.visuals());
+    }
+}
+
+fn probe(path: &Path) -> Result<ProbedFile> {
+    if !path.exists() {
+        return Err(not_found(path));
+    }
+
+    let container = Container::from_head(&read_head(path, 0, 12)?);
+    if container == Container::Dsf {
+        return probe_dsf(path);
+    }
+
+    let file = File::open(path)?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|_| unsupported(path))?;
+
+    let codec_params = probed
+        .format
+        .default_track()
+        .map(|track| track.codec_params.clone())
+        .ok_or_else(|| unsupported(path))?;
+    let mut probed_file = ProbedFile::new(container, codec_params);
+
+    // Like taglib, ID3v2 tags in AIFF and WAV chunks take precedence over
+    // RIFF INFO tags. Metadata in the container itself takes precedence
+    // over metadata found before the container, such as ID3v2 tags in MP3.
+    if let Some(revision) = read_id3_chunk(path, &probed_file.container)? {
+        probed_file.collect(&revision);
+    }
+    if let Some(revision) = probed.format.metadata().skip_to_latest() {
+        probed_file.collect(revision);
+    }
+    if let Some(mut metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.skip_to_latest() {
+            probed_file.collect(revision);
+        }
+    }
+
+    Ok(probed_file)
+}
+
+/// Reads an ID3v2 tag with the ID3v2 reader registered with symphonia.
+fn read_id3v2(tag: Vec<u8>) -> Option<MetadataRevision> {
+    let mut mss = MediaSourceStream::new(Box::new(Cursor::new(tag)), Default::default());
+    match symphonia::default::get_probe().next(&mut mss) {
+        Ok(Instantiate::Metadata(reader)) => reader(&MetadataOptions::default())
+            .read_all(&mut mss)
+            .ok(),
+        _ => None,
+    }
+}
+
+/// Reads the first chunk with one of the given IDs from an AIFF or WAV file.
+fn read_chunk(path: &Path, container: &Container, ids: &[&[u8]]) -> Result<Option<Vec<u8>>> {
+    let big_endian = match container {
+        Container::Aiff => true,
+        Container::Wav => false,
+        _ => return Ok(None),
+    };
+
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(12))?;
+    let mut chunk_header = [0; 8];
+    while file.read_exact(&mut chunk_header).is_ok() {
+        let len = [chunk_header[4], chunk_header[5], chunk_header[6], chunk_header[7]];
+        let len = if big_endian { u32::from_be_bytes(len) } else { u32::from_le_bytes(len) } as u64;
+        if !ids.contains(&&chunk_header[..4]) {
+            // Chunks are padded to an even length.
+            file.seek(SeekFrom::Current((len + len % 2) as i64))?;
+            continue;
+        }
+
+        // The length is not trusted to allocate up front, since a damaged file
+        // can claim a chunk far longer than the file.
+        let mut chunk = Vec::new();
+        (&mut file).take(len).read_to_end(&mut chunk)?;
+        return Ok(Some(chunk));
+    }
+    Ok(None)
+}
+
+/// Reads the ID3v2 tag stored in the `ID3 ` chunk of an AIFF or WAV file,
+/// which is not read by symphonia.
+fn read_id3_chunk(path: &Path, container: &Container) -> Result<Option<MetadataRevision>> {
+    Ok(read_chunk(path, container, &[b"ID3 ", b"id3 "])?.and_then(read_id3v2))
+}
+
+/// Reads the number of sample frames from the `COMM` chunk of an AIFF file.
+/// symphonia derives it from the length of the `SSND` chunk instead, which
+/// includes the offset and block size fields.
+fn read_aiff_sample_count(path: &Path) -> Result<Option<u64>> {
+    let comm = read_chunk(path, &Container::Aiff, &[b"COMM"])?;
+    Ok(comm
+        .as_ref()
+        .and_then(|comm| comm.get(2..6))
+        .map(|frames| u32::from_be_bytes([frames[0], frames[1], frames[2], frames[3]]) as u64))
+}
+
+/// Probes a DSF file, which is not supported by symphonia.
+///
+/// DSF files consist of a DSD chunk, a fmt chunk, a data chunk, and an
+/// ID3v2 tag at the end of the file. All values are little endian.
+fn probe_dsf(path: &Path) -> Result<ProbedFile> {
+    let header = read_head(path, 0, 80)?;
+    if header.len() != 80 || &header[28..32] != b"fmt " {
+        return Err(unsupported(path));
+    }
+    let u32_at = |offset: usize| {
+        u32::from_le_bytes([header[offset], header[offset + 1], header[offset + 2], header[offset + 3]])
+    };
+    let u64_at = |offset: usize| u32_at(offset) as u64 | (u32_at(offset + 4) as u64) << 32;
+
+    let metadata_offset = u64_at(20);
+    let channels = u32_at(28 + 24);
+    let sampling_frequency = u32_at(28 + 28);
+    let sample_count = u64_at(28 + 36);
+
+    let mut codec_params = CodecParameters::new();
+    codec_params
+        .with_sample_rate(sampling_frequency)
+        .with_n_frames(sample_count)
+        .with_bits_per_sample(1);
+    if channels > 0 && channels <= 32 {
+        codec_params.with_channels(Channels::from_bits_truncate(((1u64 << channels) - 1) as u32));
+    }
+
+    let mut probed_file = ProbedFile::new(Container::Dsf, codec_params);
+    if metadata_offset > 0 {
+        let tag = read_head(path, metadata_offset, u32::MAX as usize)?;
+        if let Some(revision) = read_id3v2(tag) {
+            probed_file.collect(&revision);
+        }
+    }
+    Ok(probed_file)
+}
+
+impl ProbedFile {
+    fn tag_values<'a>(&'a self, key: StandardTagKey) -> impl Iterator<Item = String> + 'a {
+        self.tags
+            .iter()
+            .filter(move |tag| tag.std_key == Some(key))
+            .map(|tag| tag.value.to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Gets all values of the given tag joined with a space, the same
+    /// way taglib joins multiple values.
+    fn tag(&self, key: StandardTagKey) -> String {
+        self.tag_values(key).collect::<Vec<String>>().join(" ")
+    }
+
+    /// Gets the leading number of the first value of the given tag,
+    /// such as the track number of `3/12`.
+    fn tag_number(&self, key: StandardTagKey) -> Option<u32> {
+        self.tag_values(key).next().and_then(|value| {
+            let digits: String = value
+                .trim()
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            digits.parse().ok()
+        })
+    }
+
+    /// Gets the front cover, settling for an `Other` picture if there is
+    /// no front cover, the same way libkatatsuki does.
+    fn front_cover(&self) -> Option<&Visual> {
+        self.visuals
+            .iter()
+            .find(|visual| visual.usage == Some(StandardVisualKey::FrontCover))
+            .or_else(|| self.visuals.iter().find(|visual| visual.usage.is_none()))
+            .filter(|visual| !visual.data.is_empty())
+    }
+
+    fn bits_per_sample(&self) -> u32 {
+        if let Some(bits_per_sample) = self.codec_params.bits_per_sample {
+            return bits_per_sample;
+        }
+
+        // The ALAC magic cookie stores the bit depth after the frame length
+        // and compatible version, optionally preceded by 'frma' and 'alac' atoms.
+        match self.codec_params.extra_data {
+            Some(ref cookie) if self.codec_params.codec == CODEC_TYPE_ALAC => {
+                let cookie = if cookie.len() >= 48 { &cookie[24..] } else { &cookie[..] };
+                cookie.get(5).map(|&bits| bits as u32).unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+}
+
+fn is_pcm(codec: CodecType) -> bool {
+    [
+        CODEC_TYPE_PCM_S8,
+        CODEC_TYPE_PCM_U8,
+        CODEC_TYPE_PCM_S16BE,
+        CODEC_TYPE_PCM_S16LE,
+        CODEC_TYPE_PCM_U16BE,
+        CODEC_TYPE_PCM_U16LE,
+        CODEC_TYPE_PCM_S24BE,
+        CODEC_TYPE_PCM_S24LE,
+        CODEC_TYPE_PCM_U24BE,
+        CODEC_TYPE_PCM_U24LE,
+        CODEC_TYPE_PCM_S32BE,
+        CODEC_TYPE_PCM_S32LE,
+        CODEC_TYPE_PCM_U32BE,
+        CODEC_TYPE_PCM_U32LE,
+        CODEC_TYPE_PCM_F32BE,
+        CODEC_TYPE_PCM_F32LE,
+        CODEC_TYPE_PCM_F64BE,
+        CODEC_TYPE_PCM_F64LE,
+    ]
+    .contains(&codec)
+}
+
+fn flac_type(bits_per_sample: u32) -> TrackFileType {
+    match bits_per_sample {
+        4 => TrackFileType::FLAC4,
+        8 => TrackFileType::FLAC8,
+        16 => TrackFileType::FLAC16,
+        24 => TrackFileType::FLAC24,
+        32 => TrackFileType::FLAC32,
+        _ => TrackFileType::FLAC,
+    }
+}
+
+fn alac_type(bits_per_sample: u32) -> TrackFileType {
+    match bits_per_sample {
+        16 => TrackFileType::ALAC16,
+        20 => TrackFileType::ALAC20,
+        24 => TrackFileType::ALAC24,
+        32 => TrackFileType::ALAC32,
+        _ => TrackFileType::ALAC,
+    }
+}
+
+fn aiff_type(bits_per_sample: u32) -> TrackFileType {
+    match bits_per_sample {
+        4 => TrackFileType::AIFF4,
+        8 => TrackFileType::AIFF8,
+        16 => TrackFileType::AIFF16,
+        24 => TrackFileType::AIFF24,
+        32 => TrackFileType::AIFF32,
+        _ => TrackFileType::AIFF,
+    }
+}
+
+fn wav_type(bits_per_sample: u32) -> TrackFileType {
+    match bits_per_sample {
+        8 => TrackFileType::WAV8,
+        16 => TrackFileType::WAV16,
+        24 => TrackFileType::WAV24,
+        32 => TrackFileType::WAV32,
+        _ => TrackFileType::WAV,
+    }
+}
+
+fn dsd_type(sampling_frequency: u32) -> TrackFileType {
+    match sampling_frequency {
+        2_822_400 => TrackFileType::DSD64,
+        5_644_800 => TrackFileType::DSD128,
+        11_289_600 => TrackFileType::DSD256,
+        22_579_200 => TrackFileType::DSD512,
+        _ => TrackFileType::DSD,
+    }
+}
+
+/// Reads up to `len` bytes of the file from the given offset.
+fn read_head(path: &Path, offset: u64, len: usize) -> Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut head = Vec::new();
+    file.take(len as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// The first MPEG audio frame of an MP3 file.
+struct MpegFrame {
+    /// Whether the frame carries a Xing, Info or VBRI header.
+    has_xing_header: bool,
+    /// The bitrate in kbps declared in the frame header.
+    bitrate: i32,
+    samples_per_frame: u64,
+    /// The number of audio frames declared by the Xing or VBRI header.
+    frame_count: Option<u32>,
+    /// The LAME tag following the Xing header.
+    lame_tag: Option<LameTag>,
+}
+
+struct LameTag {
+    encoder: String,
+    vbr_method: u8,
+    /// The number of samples of encoder delay at the start of the stream.
+    delay: u32,
+    /// The number of samples of padding at the end of the stream.
+    padding: u32,
+}
+
+impl LameTag {
+    /// Reads the LAME tag following the Xing or Info header at the start of the given bytes.
+    fn read(xing: &[u8]) -> Option<LameTag> {
+        let flags = u32::from_be_bytes([*xing.get(4)?, *xing.get(5)?, *xing.get(6)?, *xing.get(7)?]);
+        let offset = 8
+            + if flags & 0x01 != 0 { 4 } else { 0 }
+            + if flags & 0x02 != 0 { 4 } else { 0 }
+            + if flags & 0x04 != 0 { 100 } else { 0 }
+            + if flags & 0x08 != 0 { 4 } else { 0 };
+        let encoder = xing.get(offset..offset + 9)?;
+        if !encoder.starts_with(b"LAME") && !encoder.starts_with(b"Lavc") && !encoder.starts_with(b"Lavf") {
+            return None;
+        }
+        let encoder = encoder.split(|&b| b == 0).next().unwrap_or(encoder);
+        // The delay and padding are packed into 12 bits each after the
+        // lowpass, replay gain, flags and bitrate fields.
+        let (delay, padding) = match xing.get(offset + 21..offset + 24) {
+            Some(&[a, b, c]) => ((a as u32) << 4 | (b as u32) >> 4, (b as u32 & 0x0F) << 8 | c as u32),
+            _ => (0, 0),
+        };
+        Some(LameTag {
+            encoder: String::from_utf8_lossy(encoder).trim().to_owned(),
+            vbr_method: xing.get(offset + 9)? & 0x0F,
+            delay,
+            padding,
+        })
+    }
+
+    fn bitrate_mode(&self) -> BitrateMode {
+        match self.vbr_method {
+            1 | 8 => BitrateMode::Constant,
+            2 | 9 => BitrateMode::Average,
+            3..=6 => BitrateMode::Variable,
+            _ => BitrateMode::Unknown,
+        }
+    }
+}
+
+fn first_mpeg_frame(path: &Path) -> Result<Option<MpegFrame>> {
+    let offset = id3v2_size(&read_head(path, 0, 10)?);
+    let head = read_head(path, offset, 64 * 1024)?;
+
+    for i in 0..head.len().saturating_sub(40) {
+        let header = match FrameHeader::parse(&head[i..]) {
+            Some(header) => header,
+            None => continue,
+        };
+
+        let side_info_size = header.side_info_size();
+        let xing = &head[i + 4 + side_info_size..];
+        let has_xing = xing.starts_with(b"Xing") || xing.starts_with(b"Info");
+        let vbri = &head[i + 36..];
+        let has_vbri = vbri.starts_with(b"VBRI");
+        let u32_at = |bytes: &[u8], offset: usize| {
+            bytes.get(offset..offset + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        let frame_count = if has_xing {
+            u32_at(xing, 4).filter(|flags| flags & 0x01 != 0).and_then(|_| u32_at(xing, 8))
+        } else if has_vbri {
+            u32_at(vbri, 14)
+        } else {
+            None
+        };
+
+        return Ok(Some(MpegFrame {
+            has_xing_header: has_xing || has_vbri,
+            bitrate: header.bitrate,
+            samples_per_frame: header.samples_per_frame(),
+            frame_count,
+            lame_tag: if has_xing { LameTag::read(xing) } else { None },
+        }));
+    }
+    Ok(None)
+}
+
+/// The elementary stream descriptor of an MP4 file.
+struct Esds {
+    audio_object_type: u32,
+    max_bitrate: u32,
+    avg_bitrate: u32,
+}
+
+impl Esds {
+    fn codec_profile(&self) -> CodecProfile {
+        match self.audio_object_type {
+            1 => CodecProfile::AACMain,
+            2 => CodecProfile::AACLC,
+            3 => CodecProfile::AACSSR,
+            4 => CodecProfile::AACLTP,
+            5 => CodecProfile::HEAAC,
+            29 => CodecProfile::HEAACv2,
+            23 => CodecProfile::AACLD,
+            39 => CodecProfile::AACELD,
+            _ => CodecProfile::Unknown,
+        }
+    }
+
+    /// MP4 does not store the encoding mode, but constant bitrate
+    /// encoders write the same maximum and average bitrate.
+    fn bitrate_mode(&self) -> BitrateMode {
+        match (self.max_bitrate, self.avg_bitrate) {
+            (_, 0) => BitrateMode::Unknown,
+            (max, avg) if max == avg => BitrateMode::Constant,
+            _ => BitrateMode::Variable,
+        }
+    }
+}
+
+/// An MP4 atom, by offset and length including the header.
+struct Atom {
+    offset: u64,
+    length: u64,
+    header_length: u64,
+}
+
+/// Finds the first atom with the given name between the given offsets.
+fn find_atom(file: &mut File, mut offset: u64, end: u64, name: &[u8]) -> Result<Option<Atom>> {
+    while end.saturating_sub(offset) >= 8 {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0; 16];
+        file.read_exact(&mut header[..8])?;
+        let (length, header_length) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            1 => {
+                file.read_exact(&mut header[8..])?;
+                let mut length = [0; 8];
+                length.copy_from_slice(&header[8..]);
+                (u64::from_be_bytes(length), 16)
+            }
+            0 => (end - offset, 8),
+            length => (length as u64, 8),
+        };
+        if length < header_length {
+            return Ok(None);
+        }
+        if &header[4..8] == name {
+            return Ok(Some(Atom { offset, length, header_length }));
+        }
+        offset = match offset.checked_add(length) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+    }
+    Ok(None)
+}
+
+fn find_child(file: &mut File, parent: &Atom, name: &[u8]) -> Result<Option<Atom>> {
+    find_atom(file, parent.offset + parent.header_length, parent.offset.saturating_add(parent.length), name)
+}
+
+/// Reads the elementary stream descriptor of the first sound track of an MP4 file.
+fn read_esds(path: &Path) -> Result<Option<Esds>> {
+    let mut file = File::open(path)?;
+    let file_length = file.metadata()?.len();
+    let moov = match find_atom(&mut file, 0, file_length, b"moov")? {
+        Some(moov) => moov,
+        None => return Ok(None),
+    };
+
+    let mut trak_offset = moov.offset + moov.header_length;
+    while let Some(trak) = find_atom(&mut file, trak_offset, moov.offset.saturating_add(moov.length), b"trak")? {
+        trak_offset = trak.offset.saturating_add(trak.length);
+        let mdia = match find_child(&mut file, &trak, b"mdia")? {
+            Some(mdia) => mdia,
+            None => continue,
+        };
+        let hdlr = match find_child(&mut file, &mdia, b"hdlr")? {
+            Some(hdlr) => hdlr,
+            None => continue,
+        };
+        // The handler type follows the version, flags and predefined fields.
+        let mut handler_type = [0; 4];
+        file.seek(SeekFrom::Start(hdlr.offset + hdlr.header_length + 8))?;
+        file.read_exact(&mut handler_type)?;
+        if &handler_type != b"soun" {
+            continue;
+        }
+
+        let mut stsd = None;
+        if let Some(minf) = find_child(&mut file, &mdia, b"minf")? {
+            if let Some(stbl) = find_child(&mut file, &minf, b"stbl")? {
+                stsd = find_child(&mut file, &stbl, b"stsd")?;
+            }
+        }
+        let stsd = match stsd {
+            Some(stsd) => stsd,
+            None => return Ok(None),
+        };
+        let entries = read_head(path, stsd.offset + stsd.header_length, (stsd.length - stsd.header_length) as usize)?;
+        let esds_offset = match entries.windows(4).position(|window| window == b"esds") {
+            Some(offset) if offset >= 4 => offset,
+            _ => return Ok(None),
+        };
+        let esds_length = u32::from_be_bytes([
+            entries[esds_offset - 4],
+            entries[esds_offset - 3],
+            entries[esds_offset - 2],
+            entries[esds_offset - 1],
+        ]) as usize;
+        let end = (esds_offset - 4 + esds_length).min(entries.len());
+        return Ok(parse_esds(entries.get(esds_offset + 4..end).unwrap_or(&[])));
+    }
+    Ok(None)
+}
+
+/// Reads the length of an MPEG-4 descriptor, which is encoded 7 bits at a time.
+fn read_descriptor_length(data: &[u8], position: &mut usize) -> usize {
+    let mut length = 0;
+    for _ in 0..4 {
+        let byte = match data.get(*position) {
+            Some(&byte) => byte,
+            None => break,
+        };
+        *position += 1;
+        length = (length << 7) | (byte & 0x7F) as usize;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    length
+}
+
+fn parse_esds(data: &[u8]) -> Option<Esds> {
+    // Skip the version and flags.
+    let mut position = 4;
+    if *data.get(position)? != 0x03 {
+        return None;
+    }
+    position += 1;
+    read_descriptor_length(data, &mut position);
+    let flags = *data.get(position + 2)?;
+    position += 3;
+    if flags & 0x80 != 0 {
+        position += 2;
+    }
+    if flags & 0x40 != 0 {
+        position += 1 + *data.get(position)? as usize;
+    }
+    if flags & 0x20 != 0 {
+        position += 2;
+    }
+
+    if *data.get(position)? != 0x04 {
+        return None;
+    }
+    position += 1;
+    read_descriptor_length(data, &mut position);
+    let config = data.get(position..position + 13)?;
+    let max_bitrate = u32::from_be_bytes([config[5], config[6], config[7], config[8]]);
+    let avg_bitrate = u32::from_be_bytes([config[9], config[10], config[11], config[12]]);
+    position += 13;
+
+    let mut audio_object_type = 0;
+    if data.get(position) == Some(&0x05) {
+        position += 1;
+        let length = read_descriptor_length(data, &mut position);
+        let end = (position + length).min(data.len());
+        audio_object_type = read_audio_object_type(data.get(position..end).unwrap_or(&[]));
+    }
+    Some(Esds {
+        audio_object_type,
+        max_bitrate,
+        avg_bitrate,
+    })
+}
+
+/// Reads an MPEG-4 bitstream most significant bit first.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader { bytes, position: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() * 8 - self.position
+    }
+
+    fn read(&mut self, bits: usize) -> u32 {
+        let mut value = 0;
+        for _ in 0..bits {
+            if self.remaining() == 0 {
+                break;
+            }
+            let byte = self.bytes[self.position / 8];
+            value = (value << 1) | ((byte >> (7 - self.position % 8)) & 1) as u32;
+            self.position += 1;
+        }
+        value
+    }
+}
+
+/// Reads the audio object type from an AudioSpecificConfig, including SBR and PS
+/// extensions signalled after the GASpecificConfig.
+fn read_audio_object_type(config: &[u8]) -> u32 {
+    let mut reader = BitReader::new(config);
+    let mut audio_object_type = reader.read(5);
+    if audio_object_type == 31 {
+        audio_object_type = 32 + reader.read(6);
+    }
+    if reader.read(4) == 15 {
+        reader.read(24);
+    }
+    reader.read(4);
+    if audio_object_type != 2 {
+        return audio_object_type;
+    }
+
+    // GASpecificConfig: frame length, depends on core coder, extension flag.
+    reader.read(3);
+    if reader.remaining() >= 16 && reader.read(11) == 0x2b7 && reader.read(5) == 5 && reader.read(1) == 1 {
+        audio_object_type = 5;
+        reader.read(4);
+        if reader.remaining() >= 12 && reader.read(11) == 0x548 && reader.read(1) == 1 {
+            audio_object_type = 29;
+        }
+    }
+    audio_object_type
+}
+
+/// Reads the vendor string of the comment header of an Ogg Vorbis or Opus file.
+fn read_ogg_vendor(path: &Path, codec: CodecType) -> Result<Option<String>> {
+    let magic: &[u8] = if codec == CODEC_TYPE_OPUS { b"OpusTags" } else { b"\x03vorbis" };
+    let head = read_head(path, 0, 64 * 1024)?;
+    let offset = match head.windows(magic.len()).position(|window| window == magic) {
+        Some(offset) => offset + magic.len(),
+        None => return Ok(None),
+    };
+    let length = match head.get(offset..offset + 4) {
+        Some(length) => u32::from_le_bytes([length[0], length[1], length[2], length[3]]) as usize,
+        None => return Ok(None),
+    };
+    Ok(head
+        .get(offset + 4..offset + 4 + length)
+        .map(|vendor| String::from_utf8_lossy(vendor).into_owned())
+        .filter(|vendor| !vendor.is_empty()))
+}
+
+/// Gets the offset of the first audio frame of a FLAC file, after all
+/// metadata blocks.
+fn flac_audio_offset(path: &Path) -> Result<u64> {
+    let mut file = File::open(path)?;
+    let mut header = [0; 10];
+    file.read_exact(&mut header[..4])?;
+    let mut offset = 0;
+    if &header[..3] == b"ID3" {
+        file.read_exact(&mut header[4..])?;
+        offset = id3v2_size(&header);
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header[..4])?;
+    }
+    if &header[..4] != b"fLaC" {
+        return Ok(0);
+    }
+    offset += 4;
+
+    loop {
+        let mut block_header = [0; 4];
+        file.read_exact(&mut block_header)?;
+        let len = (block_header[1] as u64) << 16 | (block_header[2] as u64) << 8 | block_header[3] as u64;
+        offset += 4 + len;
+        if block_header[0] & 0x80 != 0 {
+            return Ok(offset);
+        }
+        file.seek(SeekFrom::Start(offset))?;
+    }
+}
+
+/// Estimates the bitrate in kbps from the given number of audio bytes.
+fn estimate_bitrate(audio_bytes: u64, duration: i64) -> i32 {
+    if duration <= 0 {
+        return 0;
+    }
+    (audio_bytes as f64 * 8.0 / duration as f64).round() as i32
+}
+
+/// Reads the file type and bitrate of the probed file.
+fn audio_properties(path: &Path, probed: &ProbedFile, duration: i64) -> Result<(TrackFileType, i32)> {
+    let codec = probed.codec_params.codec;
+    let bits_per_sample = probed.bits_per_sample();
+    let file_size = path.metadata()?.len();
+    let visual_bytes: u64 = probed.visuals.iter().map(|visual| visual.data.len() as u64).sum();
+    let estimated_bitrate = estimate_bitrate(file_size.saturating_sub(visual_bytes), duration);
+    let pcm_bitrate = {
+        let channels = probed.codec_params.channels.map(|c| c.count()).unwrap_or(0) as f64;
+        let sample_rate = probed.codec_params.sample_rate.unwrap_or(0) as f64;
+        (sample_rate * bits_per_sample as f64 * channels / 1000.0).round() as i32
+    };
+
+    let properties = match codec {
+        CODEC_TYPE_FLAC => {
+            let audio_bytes = file_size.saturating_sub(flac_audio_offset(path)?);
+            (flac_type(bits_per_sample), estimate_bitrate(audio_bytes, duration))
+        }
+        CODEC_TYPE_MP3 => match first_mpeg_frame(path)? {
+            // Mimics taglib-sharp behaviour, even though VBR files may not
+            // necessarily have a xing header.
+            Some(MpegFrame { has_xing_header: true, .. }) => (TrackFileType::MP3VBR, estimated_bitrate),
+            Some(MpegFrame { bitrate, .. }) => (TrackFileType::MP3CBR, bitrate),
+            None => return Err(unsupported(path)),
+        },
+        CODEC_TYPE_AAC => (TrackFileType::AAC, estimated_bitrate),
+        CODEC_TYPE_ALAC => (alac_type(bits_per_sample), estimated_bitrate),
+        CODEC_TYPE_VORBIS => (TrackFileType::Vorbis, estimated_bitrate),
+        CODEC_TYPE_OPUS => (TrackFileType::Opus, estimated_bitrate),
+        _ if probed.container == Container::Dsf => {
+            let sample_rate = probed.codec_params.sample_rate.unwrap_or(0);
+            (dsd_type(sample_rate), pcm_bitrate)
+        }
+        // libkatatsuki only supports uncompressed audio in AIFF and WAV containers.
+        codec if is_pcm(codec) && probed.container == Container::Aiff => (aiff_type(bits_per_sample), pcm_bitrate),
+        codec if is_pcm(codec) && probed.container == Container::Wav => (wav_type(bits_per_sample), pcm_bitrate),
+        _ => (TrackFileType::Unknown, 0),
+    };
+    Ok(properties)
+}
+
+/// Gets the bitrate mode of an Ogg Vorbis file from the maximum, nominal and
+/// minimum bitrates of its identification header.
+fn vorbis_bitrate_mode(ident: &[u8]) -> BitrateMode {
+    let i32_at = |offset: usize| {
+        ident
+            .get(offset..offset + 4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .unwrap_or(0)
+    };
+    let (maximum, nominal, minimum) = (i32_at(16), i32_at(20), i32_at(24));
+    if nominal > 0 && maximum == nominal && minimum == nominal {
+        BitrateMode::Constant
+    } else if nominal > 0 && maximum <= 0 && minimum <= 0 {
+        BitrateMode::Variable
+    } else {
+        BitrateMode::Average
+    }
+}
+
+/// Gets the codec profile, bitrate mode and encoder of the given file.
+fn codec_info(path: &Path, probed: &ProbedFile) -> Result<(CodecProfile, BitrateMode, Option<String>)> {
+    let codec = probed.codec_params.codec;
+    let (profile, mode, encoder) = match codec {
+        CODEC_TYPE_MP3 => match first_mpeg_frame(path)? {
+            Some(MpegFrame { lame_tag: Some(ref lame_tag), .. }) if lame_tag.bitrate_mode() != BitrateMode::Unknown => {
+                (CodecProfile::Unknown, lame_tag.bitrate_mode(), Some(lame_tag.encoder.clone()))
+            }
+            // Consistent with the MP3CBR and MP3VBR file types.
+            Some(frame) => {
+                let mode = if frame.has_xing_header { BitrateMode::Variable } else { BitrateMode::Constant };
+                (CodecProfile::Unknown, mode, frame.lame_tag.map(|lame_tag| lame_tag.encoder))
+            }
+            None => (CodecProfile::Unknown, BitrateMode::Unknown, None),
+        },
+        CODEC_TYPE_AAC => match read_esds(path)? {
+            Some(esds) => (esds.codec_profile(), esds.bitrate_mode(), None),
+            None => (CodecProfile::Unknown, BitrateMode::Unknown, None),
+        },
+        CODEC_TYPE_VORBIS => {
+            let mode = match probed.codec_params.extra_data {
+                Some(ref ident) => vorbis_bitrate_mode(ident),
+                None => BitrateMode::Unknown,
+            };
+            (CodecProfile::Unknown, mode, read_ogg_vendor(path, codec)?)
+        }
+        CODEC_TYPE_OPUS => (CodecProfile::Unknown, BitrateMode::Unknown, read_ogg_vendor(path, codec)?),
+        _ => (CodecProfile::Unknown, BitrateMode::Unknown, None),
+    };
+
+    // Fall back to the encoder tag, which is TSSE in ID3v2 and ©too in MP4.
+    let encoder = encoder.filter(|encoder| !encoder.is_empty()).or_else(|| {
+        probed
+            .tag_values(StandardTagKey::Encoder)
+            .chain(probed.tag_values(StandardTagKey::EncoderSettings))
+            .chain(probed.tag_values(StandardTagKey::EncodedBy))
+            .find(|encoder| !encoder.is_empty())
+    });
+    Ok((profile, mode, encoder))
+}
+
+/// Reads the granule position of the last page of an Ogg file, which is the
+/// number of samples per channel at the end of the stream.
+fn ogg_last_granule(path: &Path) -> Result<Option<u64>> {
+    // Ogg pages are at most 65307 bytes long.
+    let length = path.metadata()?.len();
+    let tail = read_head(path, length.saturating_sub(64 * 1024), 64 * 1024)?;
+    Ok(tail
+        .windows(4)
+        .enumerate()
+        .rev()
+        .filter(|&(_, window)| window == b"OggS")
+        .filter_map(|(offset, _)| tail.get(offset + 6..offset + 14))
+        .map(|granule| i64::from_le_bytes([
+            granule[0], granule[1], granule[2], granule[3], granule[4], granule[5], granule[6], granule[7],
+        ]))
+        // Pages on which no packet ends have a granule position of -1.
+        .find(|&granule| granule >= 0)
+        .map(|granule| granule as u64))
+}
+
+/// Reads the number of samples in the iTunSMPB tag written by iTunes and
+/// other AAC encoders, which excludes encoder delay and padding.
+fn itunes_sample_count(probed: &ProbedFile) -> Option<u64> {
+    let tag = probed.tags.iter().find(|tag| tag.key.ends_with("iTunSMPB"))?;
+    let value = tag.value.to_string();
+    let sample_count = value.split_whitespace().nth(3)?;
+    u64::from_str_radix(sample_count, 16).ok().filter(|&count| count > 0)
+}
+
+/// Gets the number of samples per channel of the given file, excluding
+/// encoder delay and padding where they are known, or 0 if it is unknown.
+fn sample_count(path: &Path, probed: &ProbedFile) -> Result<u64> {
+    let params = &probed.codec_params;
+    let sample_count = match params.codec {
+        CODEC_TYPE_MP3 => match first_mpeg_frame(path)? {
+            Some(MpegFrame { frame_count: Some(frame_count), samples_per_frame, ref lame_tag, .. }) => {
+                let (delay, padding) = lame_tag.as_ref().map(|tag| (tag.delay, tag.padding)).unwrap_or((0, 0));
+                (frame_count as u64 * samples_per_frame).saturating_sub(delay as u64 + padding as u64)
+            }
+            // Without a frame count, every frame has to be counted, but the
+            // Xing or VBRI frame itself does not hold any audio.
+            Some(frame) => {
+                let sample_count = mpeg::count_samples(&std::fs::read(path)?);
+                if frame.has_xing_header {
+                    sample_count.saturating_sub(frame.samples_per_frame)
+                } else {
+                    sample_count
+                }
+            }
+            None => 0,
+        },
+        // The granule position of Opus streams includes the pre-skip.
+        CODEC_TYPE_VORBIS | CODEC_TYPE_OPUS => ogg_last_granule(path)?
+            .map(|granule| granule.saturating_sub(params.delay.unwrap_or(0) as u64))
+            .unwrap_or(0),
+        // The duration of an MP4 track is in the timescale of its media header,
+        // which is usually, but not always, the sample rate.
+        CODEC_TYPE_AAC | CODEC_TYPE_ALAC => match itunes_sample_count(probed) {
+            Some(sample_count) => sample_count,
+            None => match (params.n_frames, params.time_base, params.sample_rate) {
+                (Some(duration), Some(time_base), Some(sample_rate)) if time_base.denom > 0 => {
+                    duration * time_base.numer as u64 * sample_rate as u64 / time_base.denom as u64
+                }
+                (Some(duration), None, _) => duration,
+                _ => 0,
+            },
+        },
+        _ if probed.container == Container::Aiff => read_aiff_sample_count(path)?.unwrap_or(0),
+        _ => params.n_frames.unwrap_or(0),
+    };
+    Ok(sample_count)
+}
+
+/// Gets the duration in milliseconds of the given number of samples,
+/// rounded to the nearest millisecond.
+fn duration_ms(sample_count: u64, sample_rate: u32) -> i64 {
+    if sample_rate == 0 {
+        return 0;
+    }
+    ((sample_count as u128 * 1000 + sample_rate as u128 / 2) / sample_rate as u128) as i64
+}
+
+/// Reads the embedded front cover of the track at the given path.
+pub fn read_front_cover(path: &Path) -> Result<Option<Vec<u8>>> {
+    let probed = probe(path)?;
+    Ok(probed.front_cover().map(|visual| visual.data.to_vec()))
+}
+
+/// Reads the track at the given path.
+pub fn read_track(path: &Path, source: Option<&str>) -> Result<Track> {
+    let probed = probe(path)?;
+    let sample_count = sample_count(path, &probed)?;
+    let duration = duration_ms(sample_count, probed.codec_params.sample_rate.unwrap_or(0));
+    let (file_type, bitrate) = audio_properties(path, &probed, duration)?;
+    if let TrackFileType::Unknown = file_type {
+        return Err(unsupported(path));
+    }
+    let (codec_profile, bitrate_mode, encoder) = codec_info(path, &probed)?;
+
+    let mut fcw = 0;
+    let mut fch = 0;
+    let mut fcmime = None;
+    let mut fcsize = 0;
+    let front_cover = probed.front_cover();
+    if let Some(cover) = front_cover {
+        let header = &cover.data[..cover.data.len().min(COVER_HEADER_SIZE)];
+        fcmime = cover_mime_type(header).map(|mime| mime.to_owned());
+        let (width, height) = cover_dimensions(header);
+        fcw = width;
+        fch = height;
+        fcsize = cover.data.len();
+    }
+
+    let year = probed
+        .tag_values(StandardTagKey::Date)
+        .next()
+        .and_then(|date| date.get(..4).and_then(|year| year.parse().ok()))
+        .unwrap_or(0);
+
+    let musicbrainz_track_id = probed
+        .tag_values(StandardTagKey::MusicBrainzTrackId)
+        .chain(probed.tag_values(StandardTagKey::MusicBrainzRecordingId))
+        .next();
+
+    Ok(Track {
+        file_path: path.to_owned(),
+        file_type,
+        title: probed.tag(StandardTagKey::TrackTitle),
+        artist: probed.tag(StandardTagKey::Artist),
+        album: probed.tag(StandardTagKey::Album),
+        album_artists: probed
+            .tag_values(StandardTagKey::AlbumArtist)
+            .collect::<Vec<String>>()
+            .join(";")
+            .split(';')
+            .map(|c| c.to_owned())
+            .collect::<Vec<String>>(),
+        year,
+        track_number: probed.tag_number(StandardTagKey::TrackNumber).unwrap_or(0) as i32,
+        musicbrainz_track_id,
+        has_front_cover: front_cover.is_some(),
+        front_cover_width: fcw,
+        front_cover_height: fch,
+        front_cover_mime_type: fcmime,
+        front_cover_size: fcsize as i32,
+        bitrate,
+        bitrate_mode,
+        codec_profile,
+        encoder,
+        channels: probed.codec_params.channels.map(|channels| channels.count()).unwrap_or(0) as i32,
+        sample_rate: probed.codec_params.sample_rate.unwrap_or(0) as i32,
+        source: source.unwrap_or("None").to_owned(),
+        disc_number: probed.tag_number(StandardTagKey::DiscNumber).unwrap_or(1) as i32,
+        duration,
+        sample_count: sample_count as i64,
+        updated: Local::now().format("%Y-%m-%d").to_string(),
+    })
+}
+
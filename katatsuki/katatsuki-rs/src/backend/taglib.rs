@@ -0,0 +1,272 @@
+//! The taglib backend, which wraps [taglib2](https://taglib.org/) through
+//! the C++ `libkatatsuki` shim.
+
+use libkatatsuki_sys as sys;
+
+use std::ffi::{CStr, CString, NulError};
+use std::io::{Error, ErrorKind, Result};
+use std::os::raw::{c_char, c_void};
+use std::path::Path;
+use std::slice::from_raw_parts;
+
+use chrono::Local;
+
+use crate::{cover_dimensions, cover_mime_type, not_found, unsupported, COVER_HEADER_SIZE};
+use crate::{BitrateMode, CodecProfile, FromPrimitive, Track, TrackFileType};
+
+fn c_str_to_str(c_str: *const c_char) -> Option<String> {
+    if c_str.is_null() {
+        return None;
+    }
+
+    let bytes = unsafe { CStr::from_ptr(c_str).to_bytes() };
+    let result = if bytes.is_empty() {
+        None
+    } else {
+        Some(String::from_utf8_lossy(bytes).to_string())
+    };
+
+    unsafe {
+        sys::free_allocated_data(c_str as *mut c_void);
+    }
+
+    result
+}
+
+struct TrackData {
+    raw: *mut sys::track_data,
+}
+
+/// Unsafe backing 
+impl TrackData {
+    // Dangerous access here, path not existing is UB.
+    /// Do not use `TrackData::new`, instead use `Track::from_path` to ensure 
+    /// safe access.
+    pub fn new(path: &CString) -> TrackData {
+        TrackData {
+            raw: unsafe { sys::create_track_data(path.to_owned().into_raw()) },
+        }
+    }
+
+    pub fn title(&self) -> String {
+        c_str_to_str(unsafe { sys::get_title(self.raw) }).unwrap_or("".to_owned())
+    }
+
+    pub fn artist(&self) -> String {
+        c_str_to_str(unsafe { sys::get_artist(self.raw) }).unwrap_or("".to_owned())
+    }
+
+    pub fn album(&self) -> String {
+        c_str_to_str(unsafe { sys::get_album(self.raw) }).unwrap_or("".to_owned())
+    }
+
+    pub fn album_artists(&self) -> String {
+        c_str_to_str(unsafe { sys::get_album_artist(self.raw) }).unwrap_or("".to_owned())
+    }
+
+    pub fn musicbrainz_track_id(&self) -> Option<String> {
+        c_str_to_str(unsafe { sys::get_musicbrainz_track_id(self.raw) })
+    }
+
+    pub fn year(&self) -> u32 {
+        unsafe { sys::get_year(self.raw) }
+    }
+
+    pub fn track_number(&self) -> u32 {
+        unsafe { sys::get_track_number(self.raw) }
+    }
+
+    pub fn bitrate(&self) -> i32 {
+        unsafe { sys::get_bitrate(self.raw) }
+    }
+
+    pub fn disc_number(&self) -> u32 {
+        unsafe { sys::get_disc_number(self.raw) }
+    }
+
+    pub fn duration(&self) -> i64 {
+        unsafe { sys::get_duration(self.raw) }
+    }
+
+    pub fn sample_count(&self) -> i64 {
+        unsafe { sys::get_sample_count(self.raw) }
+    }
+
+    pub fn sample_rate(&self) -> i32 {
+        unsafe { sys::get_sample_rate(self.raw) }
+    }
+
+    pub fn file_type(&self) -> TrackFileType {
+        let file_type = unsafe { sys::get_file_type(self.raw) };
+        TrackFileType::from_u32(file_type as u32).unwrap()
+    }
+
+    pub fn codec_profile(&self) -> CodecProfile {
+        let codec_profile = unsafe { sys::get_codec_profile(self.raw) };
+        CodecProfile::from_u32(codec_profile as u32).unwrap_or(CodecProfile::Unknown)
+    }
+
+    pub fn bitrate_mode(&self) -> BitrateMode {
+        let bitrate_mode = unsafe { sys::get_bitrate_mode(self.raw) };
+        BitrateMode::from_u32(bitrate_mode as u32).unwrap_or(BitrateMode::Unknown)
+    }
+
+    pub fn encoder(&self) -> Option<String> {
+        c_str_to_str(unsafe { sys::get_encoder(self.raw) })
+    }
+
+    pub fn channels(&self) -> i32 {
+        unsafe { sys::get_channels(self.raw) }
+    }
+
+    pub fn has_front_cover(&self) -> bool {
+        unsafe { sys::has_album_art(self.raw) }
+    }
+
+    pub fn cover_size(&self) -> usize {
+        unsafe { sys::get_album_art_size(self.raw) }
+    }
+
+    pub unsafe fn cover_bytes(&self, size: usize) -> CoverBytes {
+        CoverBytes {
+            raw: sys::get_album_art_bytes(self.raw, size),
+        }
+    }
+
+    pub unsafe fn all_cover_bytes(&self) -> CoverBytes {
+        CoverBytes {
+            raw: sys::get_album_art_all_bytes(self.raw),
+        }
+    }
+}
+
+struct CoverBytes {
+    raw: *const u8,
+}
+
+impl CoverBytes {
+    /// Copies the first `size` bytes of the cover. The caller must ensure that
+    /// taglib allocated at least `size` bytes.
+    unsafe fn to_vec(&self, size: usize) -> Vec<u8> {
+        if self.raw.is_null() || size == 0 {
+            return Vec::new();
+        }
+        from_raw_parts(self.raw, size).to_vec()
+    }
+}
+
+impl Drop for CoverBytes {
+    fn drop(&mut self) {
+        unsafe { sys::free_allocated_data(self.raw as *mut c_void) }
+    }
+}
+
+impl Drop for TrackData {
+    fn drop(&mut self) {
+        unsafe { sys::delete_track_data(self.raw) }
+    }
+}
+
+#[derive(Debug)]
+pub enum FileError {
+    OpenFailure,
+    SaveFailure,
+    PathAsString,
+    NullPathString(NulError),
+    InvalidTagFile,
+}
+
+fn path_to_cstring(path: &Path) -> std::result::Result<CString, FileError> {
+    path.to_owned()
+        .to_str()
+        .ok_or(FileError::PathAsString)
+        .and_then(|path| CString::new(path).map_err(FileError::NullPathString))
+}
+
+fn invalid_path() -> Error {
+    Error::new(
+        ErrorKind::UnexpectedEof,
+        "Path was invalid.".to_string(),
+    )
+}
+
+/// Reads the embedded front cover of the track at the given path.
+pub fn read_front_cover(path: &Path) -> Result<Option<Vec<u8>>> {
+    if !path.exists() {
+        return Err(not_found(path));
+    }
+    let path_ptr = path_to_cstring(path).map_err(|_| invalid_path())?;
+    let track: TrackData = TrackData::new(&path_ptr);
+    if let TrackFileType::Unknown = track.file_type() {
+        return Err(unsupported(path));
+    }
+    let size = track.cover_size();
+    if !track.has_front_cover() || size == 0 {
+        return Ok(None);
+    }
+    let bytes = unsafe { track.all_cover_bytes() };
+    if bytes.raw.is_null() {
+        return Ok(None);
+    }
+    let cover = unsafe { bytes.to_vec(size) };
+    Ok(Some(cover))
+}
+
+/// Reads the track at the given path.
+pub fn read_track(path: &Path, source: Option<&str>) -> Result<Track> {
+    if !path.exists() {
+        return Err(not_found(path));
+    }
+    let path_ptr = path_to_cstring(path).map_err(|_| invalid_path())?;
+    let track: TrackData = TrackData::new(&path_ptr);
+    if let TrackFileType::Unknown = track.file_type() {
+        return Err(unsupported(path));
+    }
+
+    let mut fcw = 0;
+    let mut fch = 0;
+    let mut fcmime = None;
+    let fcsize = track.cover_size();
+    if track.has_front_cover() {
+        // Only the header is needed to read the dimensions.
+        let header_size = fcsize.min(COVER_HEADER_SIZE);
+        let bytes = unsafe { track.cover_bytes(header_size) };
+        let header = unsafe { bytes.to_vec(header_size) };
+        fcmime = cover_mime_type(&header).map(|mime| mime.to_owned());
+        let (width, height) = cover_dimensions(&header);
+        fcw = width;
+        fch = height;
+    }
+
+    Ok(Track {
+        file_path: path.to_owned(),
+        file_type: track.file_type(),
+        title: track.title(),
+        artist: track.artist(),
+        album: track.album(),
+        album_artists: track
+            .album_artists()
+            .split(';')
+            .map(|c| c.to_owned())
+            .collect::<Vec<String>>(),
+        year: track.year() as i32,
+        track_number: track.track_number() as i32,
+        musicbrainz_track_id: track.musicbrainz_track_id(),
+        has_front_cover: track.has_front_cover(),
+        front_cover_width: fcw,
+        front_cover_height: fch,
+        front_cover_mime_type: fcmime,
+        front_cover_size: fcsize as i32,
+        bitrate: track.bitrate(),
+        bitrate_mode: track.bitrate_mode(),
+        codec_profile: track.codec_profile(),
+        encoder: track.encoder(),
+        channels: track.channels(),
+        sample_rate: track.sample_rate(),
+        source: source.unwrap_or("None").to_owned(),
+        disc_number: track.disc_number() as i32,
+        duration: track.duration(),
+        sample_count: track.sample_count(),
+        updated: Local::now().format("%Y-%m-%d").to_string(),
+    })
+}
@@ -1,225 +1,99 @@
 //! # Katatsuki
 //!
-//! `katatsuki` wraps [taglib2](https://taglib.org/) to allow safe access to 
-//! the metadata of various music files.
-
-
+//! `katatsuki` allows safe access to the metadata of various music files.
+//!
+//! Tags are read by one of two backends, selected with cargo features.
+//!
+//! * `taglib` (default) wraps [taglib2](https://taglib.org/) through the
+//!   C++ `libkatatsuki` shim.
+//! * `native` is a pure-Rust backend built on [symphonia](https://github.com/pdeljanov/Symphonia)
+//!   that does not require a C++ toolchain. Monkey's Audio and WavPack files
+//!   are not supported by this backend.
+//!
+//! If both features are enabled, the `native` backend is used.
+//!
+//! The `analysis` feature adds the [`analysis`](analysis/index.html) and
+//! [`verify`](verify/index.html) modules, which decode audio to estimate its
+//! spectral cutoff and to check it for corruption with either backend.
 
-use libkatatsuki_sys as sys;
+#[cfg(not(any(feature = "taglib", feature = "native")))]
+compile_error!("katatsuki requires either the `taglib` or the `native` feature to be enabled.");
 
-use std::ffi::{CStr, CString, NulError};
 use std::io::{Error, ErrorKind, Result};
-use std::os::raw::{c_char, c_void};
 use std::path::Path;
-use std::slice::from_raw_parts;
 
-use chrono::Local;
 use imagesize::blob_size;
 pub use num_traits::{FromPrimitive, ToPrimitive};
 
+pub use track::BitrateMode;
+pub use track::CodecProfile;
 pub use track::Track;
 pub use track::TrackFileType;
-
+#[cfg(feature = "taglib")]
+pub use backend::taglib::FileError;
+
+#[cfg(feature = "analysis")]
+pub mod analysis;
+pub mod backend;
+#[cfg(any(feature = "native", feature = "analysis"))]
+mod mpeg;
 mod track;
-
-fn c_str_to_str(c_str: *const c_char) -> Option<String> {
-    if c_str.is_null() {
-        return None;
-    }
-
-    let bytes = unsafe { CStr::from_ptr(c_str).to_bytes() };
-    let result = if bytes.is_empty() {
-        None
-    } else {
-        Some(String::from_utf8_lossy(bytes).to_string())
-    };
-
-    unsafe {
-        sys::free_allocated_data(c_str as *mut c_void);
+#[cfg(feature = "analysis")]
+pub mod verify;
+
+/// Sniffs the MIME type of an embedded cover from its header.
+///
+/// The MIME type stored in the picture frame is not trusted, since
+/// tagging tools frequently get it wrong.
+fn cover_mime_type(header: &[u8]) -> Option<&'static str> {
+    match header {
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'B', b'M', ..] => Some("image/bmp"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
     }
-
-    result
 }
 
-struct TrackData {
-    raw: *mut sys::track_data,
-}
-
-/// Unsafe backing 
-impl TrackData {
-    // Dangerous access here, path not existing is UB.
-    /// Do not use `TrackData::new`, instead use `Track::from_path` to ensure 
-    /// safe access.
-    pub fn new(path: &CString) -> TrackData {
-        TrackData {
-            raw: unsafe { sys::create_track_data(path.to_owned().into_raw()) },
-        }
-    }
-
-    pub fn title(&self) -> String {
-        c_str_to_str(unsafe { sys::get_title(self.raw) }).unwrap_or("".to_owned())
-    }
-
-    pub fn artist(&self) -> String {
-        c_str_to_str(unsafe { sys::get_artist(self.raw) }).unwrap_or("".to_owned())
-    }
-
-    pub fn album(&self) -> String {
-        c_str_to_str(unsafe { sys::get_album(self.raw) }).unwrap_or("".to_owned())
-    }
-
-    pub fn album_artists(&self) -> String {
-        c_str_to_str(unsafe { sys::get_album_artist(self.raw) }).unwrap_or("".to_owned())
-    }
-
-    pub fn musicbrainz_track_id(&self) -> Option<String> {
-        c_str_to_str(unsafe { sys::get_musicbrainz_track_id(self.raw) })
-    }
-
-    pub fn year(&self) -> u32 {
-        unsafe { sys::get_year(self.raw) }
-    }
-
-    pub fn track_number(&self) -> u32 {
-        unsafe { sys::get_track_number(self.raw) }
-    }
-
-    pub fn bitrate(&self) -> i32 {
-        unsafe { sys::get_bitrate(self.raw) }
-    }
-
-    pub fn disc_number(&self) -> u32 {
-        unsafe { sys::get_disc_number(self.raw) }
-    }
-
-    pub fn duration(&self) -> i64 {
-        unsafe { sys::get_duration(self.raw) }
-    }
-
-    pub fn sample_rate(&self) -> i32 {
-        unsafe { sys::get_sample_rate(self.raw) }
-    }
-
-    pub fn file_type(&self) -> TrackFileType {
-        let file_type = unsafe { sys::get_file_type(self.raw) };
-        TrackFileType::from_u32(file_type as u32).unwrap()
+/// Reads the width and height of an embedded cover from its header,
+/// or `(0, 0)` if they could not be read.
+fn cover_dimensions(header: &[u8]) -> (i32, i32) {
+    match blob_size(header) {
+        Ok(size) => (size.width as i32, size.height as i32),
+        Err(_) => (0, 0),
     }
+}
 
-    pub fn has_front_cover(&self) -> bool {
-        unsafe { sys::has_album_art(self.raw) }
-    }
+/// The number of bytes of a cover needed to read its dimensions.
+const COVER_HEADER_SIZE: usize = 384;
 
-    pub unsafe fn cover_bytes(&self, size: usize) -> CoverBytes {
-        CoverBytes {
-            raw: sys::get_album_art_bytes(self.raw, size) as *const u8,
-        }
-    }
+fn not_found(path: &Path) -> Error {
+    Error::new(ErrorKind::NotFound, format!("File {:?} not found.", path))
 }
 
-struct CoverBytes {
-    raw: *const u8,
+fn unsupported(path: &Path) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("File {:?} is unsupported", path),
+    )
 }
 
-impl Drop for CoverBytes {
-    fn drop(&mut self) {
-        unsafe { sys::free_allocated_data(self.raw as *mut c_void) }
+impl Track {
+    /// Reads the embedded front cover of the track at the given path.
+    ///
+    /// Returns `Ok(None)` if the file has no front cover, or if the cover
+    /// is not tagged as either a front cover or other picture.
+    pub fn front_cover_from_path(path: &Path) -> Result<Option<Vec<u8>>> {
+        backend::selected::read_front_cover(path)
     }
-}
 
-impl Drop for TrackData {
-    fn drop(&mut self) {
-        unsafe { sys::delete_track_data(self.raw) }
+    /// Reads the embedded front cover of this track from disk.
+    pub fn front_cover(&self) -> Result<Option<Vec<u8>>> {
+        Track::front_cover_from_path(&self.file_path)
     }
-}
-
-#[derive(Debug)]
-pub enum FileError {
-    OpenFailure,
-    SaveFailure,
-    PathAsString,
-    NullPathString(NulError),
-    InvalidTagFile,
-}
 
-impl Track {
     pub fn from_path(path: &Path, source: Option<&str>) -> Result<Track> {
-        if !path.exists() {
-            Err(Error::new(
-                ErrorKind::NotFound,
-                format!("File {:?} not found.", path),
-            ))
-        } else {
-            if let Ok(path_ptr) = path
-                .to_owned()
-                .to_str()
-                .ok_or(FileError::PathAsString)
-                .and_then(|path| CString::new(path).map_err(|err| FileError::NullPathString(err)))
-            {
-                let track: TrackData = TrackData::new(&path_ptr);
-                if let TrackFileType::Unknown = track.file_type() {
-                    Err(Error::new(
-                        ErrorKind::InvalidData,
-                        format!("File {:?} is unsupported", path),
-                    ))
-                } else {
-                    let mut fcw = 0;
-                    let mut fch = 0;
-                    if track.has_front_cover() {
-                        let bytes = unsafe { track.cover_bytes(384) };
-                        let slice = unsafe { from_raw_parts(bytes.raw, 384) };
-                        // match blob_size(slice) {
-                        //     Ok(size) => {
-                        //         fcw = size.width as i32;
-                        //         fch = size.height as i32;
-                        //     }
-                        //     Err(err) => println!("{:?}", err)
-                        // }
-                        if let Ok(size) = blob_size(slice) {
-                            fcw = size.width as i32;
-                            fch = size.height as i32;
-                            // println!("Width: {}, Height: {}", fcw, fch);
-                        }
-                        // } else {
-                        //     println!("Cover but unreadable");
-                        // }
-                        // } else {
-                        //     println!("No front cover");
-                        // }
-                    }
-
-                    let track = Ok(Track {
-                        file_path: path.to_owned(),
-                        file_type: track.file_type(),
-                        title: track.title(),
-                        artist: track.artist(),
-                        album: track.album(),
-                        album_artists: track
-                            .album_artists()
-                            .split(';')
-                            .map(|c| c.to_owned())
-                            .collect::<Vec<String>>(),
-                        year: track.year() as i32,
-                        track_number: track.track_number() as i32,
-                        musicbrainz_track_id: track.musicbrainz_track_id(),
-                        has_front_cover: track.has_front_cover(),
-                        front_cover_width: fcw,
-                        front_cover_height: fch,
-                        bitrate: track.bitrate(),
-                        sample_rate: track.sample_rate(),
-                        source: source.unwrap_or("None").to_owned(),
-                        disc_number: track.disc_number() as i32,
-                        duration: track.duration() as i32,
-                        updated: Local::now().format("%Y-%m-%d").to_string(),
-                    });
-                    drop(path_ptr);
-                    track
-                }
-            } else {
-                Err(Error::new(
-                    ErrorKind::UnexpectedEof,
-                    format!("Path was invalid."),
-                ))
-            }
-        }
+        backend::selected::read_track(path, source)
     }
 }
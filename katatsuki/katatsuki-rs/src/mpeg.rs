@@ -0,0 +1,174 @@
+//! MPEG audio frame headers, shared by the native backend and stream verification.
+
+use std::result;
+
+/// Gets the size of the ID3v2 tag at the start of the file, if any.
+pub(crate) fn id3v2_size(head: &[u8]) -> u64 {
+    match head {
+        [b'I', b'D', b'3', _, _, flags, a, b, c, d, ..] => {
+            let size = (*a as u64) << 21 | (*b as u64) << 14 | (*c as u64) << 7 | *d as u64;
+            // The footer flag adds another 10 bytes.
+            let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+            10 + size + footer
+        }
+        _ => 0,
+    }
+}
+
+const MPEG1_BITRATES: [i32; 15] = [
+    0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+const MPEG2_BITRATES: [i32; 15] = [
+    0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160,
+];
+const MPEG1_SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+/// The header of an MPEG-1, MPEG-2 or MPEG-2.5 Layer III frame.
+#[cfg_attr(not(feature = "analysis"), allow(dead_code))]
+pub(crate) struct FrameHeader {
+    pub(crate) is_mpeg1: bool,
+    pub(crate) is_mono: bool,
+    /// Whether the header is followed by a CRC-16 of the header and side information.
+    pub(crate) has_crc: bool,
+    /// The bitrate in kbps.
+    pub(crate) bitrate: i32,
+    pub(crate) sample_rate: u32,
+    pub(crate) has_padding: bool,
+}
+
+impl FrameHeader {
+    /// Parses the frame header at the start of the given bytes, if it is a
+    /// valid Layer III header. Free format frames are not supported.
+    pub(crate) fn parse(header: &[u8]) -> Option<FrameHeader> {
+        let header = header.get(..4)?;
+        let version = (header[1] >> 3) & 0x03;
+        let layer = (header[1] >> 1) & 0x03;
+        let bitrate_index = (header[2] >> 4) as usize;
+        let sample_rate_index = ((header[2] >> 2) & 0x03) as usize;
+        let is_frame = header[0] == 0xFF
+            && header[1] & 0xE0 == 0xE0
+            && version != 0x01
+            && layer == 0x01
+            && bitrate_index != 0
+            && bitrate_index != 0x0F
+            && sample_rate_index != 0x03;
+        if !is_frame {
+            return None;
+        }
+
+        let is_mpeg1 = version == 0x03;
+        let sample_rate = match version {
+            0x03 => MPEG1_SAMPLE_RATES[sample_rate_index],
+            0x02 => MPEG1_SAMPLE_RATES[sample_rate_index] / 2,
+            _ => MPEG1_SAMPLE_RATES[sample_rate_index] / 4,
+        };
+        let bitrates = if is_mpeg1 { &MPEG1_BITRATES } else { &MPEG2_BITRATES };
+        Some(FrameHeader {
+            is_mpeg1,
+            is_mono: header[3] >> 6 == 0x03,
+            has_crc: header[1] & 0x01 == 0,
+            bitrate: bitrates[bitrate_index],
+            sample_rate,
+            has_padding: header[2] & 0x02 != 0,
+        })
+    }
+
+    /// The size of the side information following the header and CRC.
+    pub(crate) fn side_info_size(&self) -> usize {
+        match (self.is_mpeg1, self.is_mono) {
+            (true, true) => 17,
+            (true, false) => 32,
+            (false, true) => 9,
+            (false, false) => 17,
+        }
+    }
+
+    /// The length of the frame in bytes, including the header.
+    pub(crate) fn frame_length(&self) -> usize {
+        let samples_per_byte = if self.is_mpeg1 { 144 } else { 72 };
+        let padding = if self.has_padding { 1 } else { 0 };
+        (samples_per_byte * self.bitrate as u32 * 1000 / self.sample_rate) as usize + padding
+    }
+
+    /// The number of samples per channel decoded from the frame.
+    #[cfg_attr(not(feature = "native"), allow(dead_code))]
+    pub(crate) fn samples_per_frame(&self) -> u64 {
+        if self.is_mpeg1 { 1152 } else { 576 }
+    }
+}
+
+/// Why walking the frames of an MP3 file stopped before the end of the stream.
+#[derive(Debug, PartialEq)]
+pub(crate) enum FrameError {
+    /// A frame is not followed by another frame or the end of the stream.
+    LostSync,
+    /// The last frame runs past the end of the stream.
+    Truncated,
+}
+
+/// Iterates over the frames of an MP3 file, yielding the offset and header
+/// of each frame. Iteration stops after the first error.
+pub(crate) struct Frames<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Frames<'a> {
+    /// Walks the frames of the given file, skipping the ID3v2 tag and any
+    /// padding before the first frame, and ignoring a trailing ID3v1 tag.
+    pub(crate) fn new(bytes: &'a [u8]) -> Frames<'a> {
+        let mut end = bytes.len();
+        if end >= 128 && &bytes[end - 128..end - 125] == b"TAG" {
+            end -= 128;
+        }
+        let bytes = &bytes[..end];
+
+        let mut offset = id3v2_size(bytes) as usize;
+        while offset < bytes.len() && FrameHeader::parse(&bytes[offset..]).is_none() {
+            offset += 1;
+        }
+        Frames { bytes, offset }
+    }
+
+    /// The bytes being walked, without a trailing ID3v1 tag.
+    #[cfg_attr(not(feature = "analysis"), allow(dead_code))]
+    pub(crate) fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = result::Result<(usize, FrameHeader), FrameError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        let rest = self.bytes.get(offset..).filter(|rest| !rest.is_empty())?;
+        let header = match FrameHeader::parse(rest) {
+            Some(header) => header,
+            // APEv2 and Lyrics3 tags can follow the last frame.
+            None if rest.starts_with(b"APETAGEX") || rest.starts_with(b"LYRICSBEGIN") => return None,
+            None => {
+                self.offset = self.bytes.len();
+                return Some(Err(FrameError::LostSync));
+            }
+        };
+
+        let frame_length = header.frame_length();
+        if offset + frame_length > self.bytes.len() {
+            self.offset = self.bytes.len();
+            return Some(Err(FrameError::Truncated));
+        }
+        self.offset += frame_length;
+        Some(Ok((offset, header)))
+    }
+}
+
+/// Counts the samples per channel of an MP3 file by walking all of its
+/// complete frames, for files without a Xing or VBRI frame count.
+#[cfg(feature = "native")]
+pub(crate) fn count_samples(bytes: &[u8]) -> u64 {
+    Frames::new(bytes)
+        .map_while(|frame| frame.ok())
+        .map(|(_, header)| header.samples_per_frame())
+        .sum()
+}
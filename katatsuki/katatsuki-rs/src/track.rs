@@ -57,7 +57,8 @@ pub enum TrackFileType {
     /// Opus audio with unspecified bitrate.
     Opus = 11,
 
-    // The Alac range is [12, 14]
+    // The Alac range is [12, 14], and is extended by the Hi-Res
+    // Alac range [40, 41].
     // Dummy for switching on.
     ALAC16 = 12,
     ALAC24 = 13,
@@ -79,11 +80,98 @@ pub enum TrackFileType {
     MonkeysAudio24 = 23,
     MonkeysAudio = 24,
 
+    // WavPack range is [25, 29]
+    WavPack8 = 25,
+    WavPack16 = 26,
+    WavPack24 = 27,
+
+    /// 32-bit WavPack. This may be either integral or floating point.
+    WavPack32 = 28,
+    WavPack = 29,
+
+    // WAV range is [30, 34]
+    WAV8 = 30,
+    WAV16 = 31,
+    WAV24 = 32,
+    WAV32 = 33,
+    WAV = 34,
+
+    // DSD range is [35, 39]. DSD files are discriminated by their
+    // sample rate as a multiple of 44.1kHz rather than their bitrate.
+
+    /// DSD sampled at 2.8224MHz.
+    DSD64 = 35,
+
+    /// DSD sampled at 5.6448MHz.
+    DSD128 = 36,
+
+    /// DSD sampled at 11.2896MHz.
+    DSD256 = 37,
+
+    /// DSD sampled at 22.5792MHz.
+    DSD512 = 38,
+
+    /// DSD with a non-standard sample rate.
+    DSD = 39,
+
+    // The Hi-Res Alac range is [40, 41]
+    ALAC20 = 40,
+    ALAC32 = 41,
+
     /// Generic for matching, this is not actually a valid return from katatsuki.
     /// Exists for 
     MP3 = 780,
 }
 
+#[derive(Debug, Primitive)]
+/// The codec profile of the Track.
+/// Only AAC has profiles that are distinguished.
+pub enum CodecProfile {
+    /// Unknown profile, or the codec does not have profiles.
+    Unknown = 0,
+
+    /// AAC Main profile.
+    AACMain = 1,
+
+    /// AAC Low Complexity. The most common AAC profile.
+    AACLC = 2,
+
+    /// AAC Scalable Sample Rate.
+    AACSSR = 3,
+
+    /// AAC Long Term Prediction.
+    AACLTP = 4,
+
+    /// High Efficiency AAC, which is AAC-LC with Spectral Band Replication.
+    HEAAC = 5,
+
+    /// High Efficiency AAC v2, which is HE-AAC with Parametric Stereo.
+    HEAACv2 = 6,
+
+    /// AAC Low Delay.
+    AACLD = 7,
+
+    /// AAC Enhanced Low Delay.
+    AACELD = 8,
+}
+
+#[derive(Debug, PartialEq, Primitive)]
+/// The bitrate mode the Track was encoded with.
+/// This is only known for lossy files.
+pub enum BitrateMode {
+    /// Unknown bitrate mode, or the file is lossless.
+    Unknown = 0,
+
+    /// Constant bitrate.
+    Constant = 1,
+
+    /// Variable bitrate.
+    Variable = 2,
+
+    /// Average bitrate, which is variable bitrate constrained to a target bitrate.
+    Average = 3,
+}
+
 #[derive(Debug)]
 /// Represents a Track.
 pub struct Track {
@@ -99,11 +187,22 @@ pub struct Track {
     pub has_front_cover: bool,
     pub front_cover_height: i32,
     pub front_cover_width: i32,
+    pub front_cover_mime_type: Option<String>,
+    pub front_cover_size: i32,
     pub bitrate: i32,
+    pub bitrate_mode: BitrateMode,
+    pub codec_profile: CodecProfile,
+    pub encoder: Option<String>,
+    pub channels: i32,
     pub sample_rate: i32,
     pub source: String,
     pub disc_number: i32,
-    pub duration: i32,
+    /// The duration in milliseconds, computed from the sample count where it is known.
+    pub duration: i64,
+    /// The number of samples per channel, excluding encoder delay and padding
+    /// where they are known, or 0 if the sample count is unknown.
+    /// Opus is always counted at 48kHz.
+    pub sample_count: i64,
     pub updated: String,
 }
 
@@ -122,7 +221,9 @@ impl FromStr for TrackFileType {
             "flac32" => Ok(TrackFileType::FLAC32),
             "alac" => Ok(TrackFileType::ALAC),
             "alac16" => Ok(TrackFileType::ALAC16),
+            "alac20" => Ok(TrackFileType::ALAC20),
             "alac24" => Ok(TrackFileType::ALAC24),
+            "alac32" => Ok(TrackFileType::ALAC32),
             "cbr" => Ok(TrackFileType::MP3CBR),
             "vbr" => Ok(TrackFileType::MP3VBR),
             "aac" => Ok(TrackFileType::AAC),
@@ -138,8 +239,58 @@ impl FromStr for TrackFileType {
             "ape8" => Ok(TrackFileType::MonkeysAudio8),
             "ape16" => Ok(TrackFileType::MonkeysAudio16),
             "ape24" => Ok(TrackFileType::MonkeysAudio24),
+            "wavpack" => Ok(TrackFileType::WavPack),
+            "wavpack8" => Ok(TrackFileType::WavPack8),
+            "wavpack16" => Ok(TrackFileType::WavPack16),
+            "wavpack24" => Ok(TrackFileType::WavPack24),
+            "wavpack32" => Ok(TrackFileType::WavPack32),
+            "wav" => Ok(TrackFileType::WAV),
+            "wav8" => Ok(TrackFileType::WAV8),
+            "wav16" => Ok(TrackFileType::WAV16),
+            "wav24" => Ok(TrackFileType::WAV24),
+            "wav32" => Ok(TrackFileType::WAV32),
+            "dsd" => Ok(TrackFileType::DSD),
+            "dsd64" => Ok(TrackFileType::DSD64),
+            "dsd128" => Ok(TrackFileType::DSD128),
+            "dsd256" => Ok(TrackFileType::DSD256),
+            "dsd512" => Ok(TrackFileType::DSD512),
             "mp3" => Ok(TrackFileType::MP3),
             _ => Ok(TrackFileType::Unknown),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Converts a lowercase string representation of a
+/// `CodecProfile` to its representation. If a
+/// string does not match, returns `CodecProfile::Unknown`
+impl FromStr for CodecProfile {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_lowercase().as_str() {
+            "main" | "aac-main" => Ok(CodecProfile::AACMain),
+            "lc" | "aac-lc" => Ok(CodecProfile::AACLC),
+            "ssr" | "aac-ssr" => Ok(CodecProfile::AACSSR),
+            "ltp" | "aac-ltp" => Ok(CodecProfile::AACLTP),
+            "he" | "he-aac" => Ok(CodecProfile::HEAAC),
+            "hev2" | "he-aacv2" => Ok(CodecProfile::HEAACv2),
+            "ld" | "aac-ld" => Ok(CodecProfile::AACLD),
+            "eld" | "aac-eld" => Ok(CodecProfile::AACELD),
+            _ => Ok(CodecProfile::Unknown),
+        }
+    }
+}
+
+/// Converts a lowercase string representation of a
+/// `BitrateMode` to its representation. If a
+/// string does not match, returns `BitrateMode::Unknown`
+impl FromStr for BitrateMode {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_lowercase().as_str() {
+            "cbr" | "constant" => Ok(BitrateMode::Constant),
+            "vbr" | "variable" => Ok(BitrateMode::Variable),
+            "abr" | "average" => Ok(BitrateMode::Average),
+            _ => Ok(BitrateMode::Unknown),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Verification of the audio stream of a file, by decoding it in full.
+//!
+//! Tag readers only look at the headers of a file, so a file that was cut
+//! short by an interrupted download or damaged on disk reads like any other.
+//! Decoding the whole stream catches these files before they are imported.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Result};
+use std::path::Path;
+
+use symphonia::core::codecs::*;
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::mpeg::{FrameError, Frames};
+use crate::not_found;
+
+/// A defect found in the audio stream of a file.
+#[derive(Debug, PartialEq)]
+pub enum StreamDefect {
+    /// The stream ends before all of its samples or frames.
+    Truncated,
+    /// The decoded audio does not match the MD5 signature of a FLAC file.
+    ChecksumMismatch,
+    /// An MP3 frame does not match its CRC.
+    FrameCrcMismatch,
+    /// An MP3 frame is not followed by another frame or the end of the stream.
+    LostSync,
+    /// A packet of the stream could not be decoded.
+    Undecodable,
+}
+
+impl fmt::Display for StreamDefect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            StreamDefect::Truncated => "The stream is truncated",
+            StreamDefect::ChecksumMismatch => "The decoded audio does not match its MD5 signature",
+            StreamDefect::FrameCrcMismatch => "A frame does not match its CRC",
+            StreamDefect::LostSync => "Lost frame sync",
+            StreamDefect::Undecodable => "The stream could not be decoded",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// Computes the CRC-16 of MPEG audio frames, with polynomial 0x8005.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xFFFF, |mut crc: u16, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// Walks every frame of an MP3 file, checking that each frame is followed
+/// by another frame, and that frames protected by a CRC match it.
+fn verify_mpeg_frames(path: &Path) -> Result<Option<StreamDefect>> {
+    let bytes = fs::read(path)?;
+    let frames = Frames::new(&bytes);
+    let bytes = frames.bytes();
+
+    for frame in frames {
+        let (offset, header) = match frame {
+            Ok(frame) => frame,
+            Err(FrameError::LostSync) => return Ok(Some(StreamDefect::LostSync)),
+            Err(FrameError::Truncated) => return Ok(Some(StreamDefect::Truncated)),
+        };
+
+        if header.has_crc {
+            // The CRC covers the last two bytes of the header and the side information.
+            let side_info = offset + 6..offset + 6 + header.side_info_size();
+            let mut protected = bytes[offset + 2..offset + 4].to_vec();
+            protected.extend_from_slice(&bytes[side_info]);
+            let crc = u16::from_be_bytes([bytes[offset + 4], bytes[offset + 5]]);
+            if crc16(&protected) != crc {
+                return Ok(Some(StreamDefect::FrameCrcMismatch));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Decodes the audio stream of the file at the given path in full, and
+/// checks it for defects.
+///
+/// The MD5 signature of FLAC files, and the frame sync and CRCs of MP3 files
+/// are checked. Files whose container declares the number of samples are
+/// checked for truncation.
+///
+/// Returns `Ok(None)` if the stream has no defects, or if the format or codec
+/// of the file can not be decoded.
+pub fn verify_stream(path: &Path) -> Result<Option<StreamDefect>> {
+    if !path.exists() {
+        return Err(not_found(path));
+    }
+
+    let file = File::open(path)?;
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    // Formats that can not be probed, such as DSF and APE, can not be checked.
+    let mut format = match symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    ) {
+        Ok(probed) => probed.format,
+        Err(_) => return Ok(None),
+    };
+
+    let track = match format.tracks().iter().find(|track| track.codec_params.codec != CODEC_TYPE_NULL) {
+        Some(track) => track,
+        None => return Ok(None),
+    };
+    let track_id = track.id;
+    let codec = track.codec_params.codec;
+
+    if codec == CODEC_TYPE_MP3 {
+        if let Some(defect) = verify_mpeg_frames(path)? {
+            return Ok(Some(defect));
+        }
+    }
+
+    // Lossy codecs have encoder delay and padding, so the number of decoded
+    // samples does not have to match the number declared by the container.
+    let is_lossy = [CODEC_TYPE_MP1, CODEC_TYPE_MP2, CODEC_TYPE_MP3, CODEC_TYPE_AAC, CODEC_TYPE_VORBIS, CODEC_TYPE_OPUS]
+        .contains(&codec);
+    let expected_frames = if is_lossy { None } else { track.codec_params.n_frames };
+
+    let options = DecoderOptions { verify: true };
+    let mut decoder = match symphonia::default::get_codecs().make(&track.codec_params, &options) {
+        Ok(decoder) => decoder,
+        Err(_) => return Ok(None),
+    };
+
+    let mut decoded_frames = 0;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(ref err)) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(DecodeError::IoError(err)) => return Err(err),
+            Err(_) => return Ok(Some(StreamDefect::Undecodable)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        match decoder.decode(&packet) {
+            Ok(decoded) => decoded_frames += decoded.frames() as u64,
+            Err(DecodeError::IoError(ref err)) if err.kind() == ErrorKind::UnexpectedEof => {
+                return Ok(Some(StreamDefect::Truncated))
+            }
+            Err(_) => return Ok(Some(StreamDefect::Undecodable)),
+        }
+    }
+
+    if let Some(expected_frames) = expected_frames {
+        if decoded_frames < expected_frames {
+            return Ok(Some(StreamDefect::Truncated));
+        }
+    }
+    if decoder.finalize().verify_ok == Some(false) {
+        return Ok(Some(StreamDefect::ChecksumMismatch));
+    }
+    Ok(None)
+}
@@ -0,0 +1,106 @@
+//! Runs the spectral analysis over generated noise.
+//!
+//! Run with `cargo test --features analysis`.
+#![cfg(feature = "analysis")]
+
+use std::f64::consts::PI;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use katatsuki::analysis::spectral_cutoff;
+use tempfile::{tempdir, TempDir};
+
+const SAMPLE_RATE: u32 = 44100;
+
+/// Generates two seconds of white noise with a xorshift generator, so that
+/// the noise is the same on every run.
+fn white_noise() -> Vec<f64> {
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    (0..SAMPLE_RATE * 2)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+        })
+        .collect()
+}
+
+/// Lowpasses the samples with a Blackman windowed sinc filter.
+fn lowpass(samples: &[f64], cutoff: f64) -> Vec<f64> {
+    const TAPS: usize = 255;
+    let fc = cutoff / SAMPLE_RATE as f64;
+    let taps = (0..TAPS)
+        .map(|i| {
+            let n = i as f64 - (TAPS - 1) as f64 / 2.0;
+            let sinc = if n == 0.0 { 2.0 * fc } else { (2.0 * PI * fc * n).sin() / (PI * n) };
+            let x = 2.0 * PI * i as f64 / (TAPS - 1) as f64;
+            sinc * (0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos())
+        })
+        .collect::<Vec<f64>>();
+    (0..samples.len())
+        .map(|i| {
+            taps.iter()
+                .enumerate()
+                .filter(|&(j, _)| i >= j)
+                .map(|(j, tap)| tap * samples[i - j])
+                .sum()
+        })
+        .collect()
+}
+
+/// Writes the samples as a mono 16-bit WAV file in a temporary directory,
+/// which is removed when the returned guard is dropped.
+fn write_wav(name: &str, samples: &[f64]) -> (TempDir, PathBuf) {
+    let data = samples
+        .iter()
+        .flat_map(|sample| ((sample * 32767.0) as i16).to_le_bytes().to_vec())
+        .collect::<Vec<u8>>();
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&data);
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(format!("{}.wav", name));
+    fs::write(&path, wav).unwrap();
+    (dir, path)
+}
+
+#[test]
+fn full_band_audio_cuts_off_at_nyquist() {
+    let (_dir, path) = write_wav("full-band", &white_noise());
+    let cutoff = spectral_cutoff(&path).unwrap();
+    assert_eq!(cutoff, Some(SAMPLE_RATE as i32 / 2));
+}
+
+#[test]
+fn lowpassed_audio_cuts_off_at_lowpass() {
+    let (_dir, path) = write_wav("lowpassed", &lowpass(&white_noise(), 16000.0));
+    let cutoff = spectral_cutoff(&path).unwrap().unwrap();
+    assert!(cutoff > 15500 && cutoff < 16500, "cutoff {} Hz", cutoff);
+}
+
+#[test]
+fn silent_audio_has_no_cutoff() {
+    let (_dir, path) = write_wav("silent", &[0.0; SAMPLE_RATE as usize]);
+    let cutoff = spectral_cutoff(&path).unwrap();
+    assert_eq!(cutoff, None);
+}
+
+#[test]
+fn missing_file_is_not_found() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("missing.flac");
+    assert_eq!(spectral_cutoff(&path).unwrap_err().kind(), ErrorKind::NotFound);
+}
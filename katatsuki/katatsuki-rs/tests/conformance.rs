@@ -0,0 +1,110 @@
+//! Runs the fixture files through both the taglib and native backends, and
+//! checks that both backends read the same track.
+//!
+//! Run with `cargo test --features native`.
+#![cfg(all(feature = "taglib", feature = "native"))]
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use katatsuki::backend::{native, taglib};
+use katatsuki::Track;
+
+fn fixtures() -> Vec<PathBuf> {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures");
+    let mut paths = fs::read_dir(fixtures)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    paths
+}
+
+/// Bitrates are estimated by the native backend, so allow them to be off by 2%.
+fn assert_bitrate_eq(path: &Path, taglib: i32, native: i32) {
+    let tolerance = (taglib / 50).max(1);
+    assert!(
+        (taglib - native).abs() <= tolerance,
+        "{:?}: bitrate {} (taglib) != {} (native)",
+        path,
+        taglib,
+        native
+    );
+}
+
+fn assert_track_eq(path: &Path, taglib: &Track, native: &Track) {
+    assert_eq!(
+        format!("{:?}", taglib.file_type),
+        format!("{:?}", native.file_type),
+        "{:?}: file type",
+        path
+    );
+    assert_eq!(taglib.title, native.title, "{:?}: title", path);
+    assert_eq!(taglib.artist, native.artist, "{:?}: artist", path);
+    assert_eq!(taglib.album, native.album, "{:?}: album", path);
+    assert_eq!(taglib.album_artists, native.album_artists, "{:?}: album artists", path);
+    assert_eq!(taglib.year, native.year, "{:?}: year", path);
+    assert_eq!(taglib.track_number, native.track_number, "{:?}: track number", path);
+    assert_eq!(taglib.disc_number, native.disc_number, "{:?}: disc number", path);
+    assert_eq!(
+        taglib.musicbrainz_track_id, native.musicbrainz_track_id,
+        "{:?}: musicbrainz track id",
+        path
+    );
+    assert_eq!(taglib.has_front_cover, native.has_front_cover, "{:?}: has front cover", path);
+    assert_eq!(taglib.front_cover_width, native.front_cover_width, "{:?}: cover width", path);
+    assert_eq!(taglib.front_cover_height, native.front_cover_height, "{:?}: cover height", path);
+    assert_eq!(
+        taglib.front_cover_mime_type, native.front_cover_mime_type,
+        "{:?}: cover mime type",
+        path
+    );
+    assert_eq!(taglib.front_cover_size, native.front_cover_size, "{:?}: cover size", path);
+    assert_eq!(taglib.sample_rate, native.sample_rate, "{:?}: sample rate", path);
+    assert_eq!(taglib.channels, native.channels, "{:?}: channels", path);
+    assert_eq!(taglib.bitrate_mode, native.bitrate_mode, "{:?}: bitrate mode", path);
+    assert_eq!(
+        format!("{:?}", taglib.codec_profile),
+        format!("{:?}", native.codec_profile),
+        "{:?}: codec profile",
+        path
+    );
+    assert_eq!(taglib.encoder, native.encoder, "{:?}: encoder", path);
+    assert_eq!(taglib.source, native.source, "{:?}: source", path);
+    assert_bitrate_eq(path, taglib.bitrate, native.bitrate);
+    assert_eq!(taglib.sample_count, native.sample_count, "{:?}: sample count", path);
+    assert_eq!(taglib.duration, native.duration, "{:?}: duration", path);
+}
+
+#[test]
+fn backends_read_same_track() {
+    for path in fixtures() {
+        let taglib = taglib::read_track(&path, Some("Fixture")).unwrap();
+        let native = native::read_track(&path, Some("Fixture")).unwrap();
+        assert_track_eq(&path, &taglib, &native);
+    }
+}
+
+#[test]
+fn backends_read_same_front_cover() {
+    for path in fixtures() {
+        assert_eq!(
+            taglib::read_front_cover(&path).unwrap(),
+            native::read_front_cover(&path).unwrap(),
+            "{:?}: front cover",
+            path
+        );
+    }
+}
+
+#[test]
+fn backends_reject_missing_file() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("missing.flac");
+    for result in [
+        taglib::read_track(&path, None),
+        native::read_track(&path, None),
+    ] {
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,65 @@
+//! Reads the sample count and duration of generated MP3 files with the
+//! native backend.
+//!
+//! Run with `cargo test --features native`.
+#![cfg(feature = "native")]
+
+use std::fs;
+use std::path::PathBuf;
+
+use katatsuki::backend::native;
+use tempfile::{tempdir, TempDir};
+
+/// The header of an MPEG-1 Layer III frame at 44.1kHz, stereo, without a CRC.
+fn frame(bitrate_index: u8) -> Vec<u8> {
+    const BITRATES: [usize; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+    let length = 144 * BITRATES[bitrate_index as usize] * 1000 / 44100;
+    let mut frame = vec![0; length];
+    frame[..4].copy_from_slice(&[0xFF, 0xFB, bitrate_index << 4, 0x00]);
+    frame
+}
+
+/// Writes the frames as an MP3 file in a temporary directory, which is
+/// removed when the returned guard is dropped.
+fn write_mp3(name: &str, frames: &[Vec<u8>]) -> (TempDir, PathBuf) {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(format!("{}.mp3", name));
+    fs::write(&path, frames.concat()).unwrap();
+    (dir, path)
+}
+
+#[test]
+fn vbr_mp3_without_xing_header_counts_every_frame() {
+    // Alternate between 128kbps and 192kbps, so that the length of the
+    // file does not match the bitrate of the first frame.
+    let frames = (0..40).map(|i| frame(if i % 2 == 0 { 9 } else { 11 })).collect::<Vec<Vec<u8>>>();
+    let (_dir, path) = write_mp3("vbr", &frames);
+    let track = native::read_track(&path, None).unwrap();
+
+    assert_eq!(track.sample_count, 40 * 1152);
+    assert_eq!(track.duration, 1045);
+}
+
+#[test]
+fn lame_tag_trims_delay_and_padding() {
+    let mut info = frame(9);
+    // The Info header follows the 32 bytes of side information, and holds
+    // only a frame count, followed by the LAME tag.
+    let xing = 4 + 32;
+    info[xing..xing + 4].copy_from_slice(b"Info");
+    info[xing + 4..xing + 8].copy_from_slice(&1u32.to_be_bytes());
+    info[xing + 8..xing + 12].copy_from_slice(&40u32.to_be_bytes());
+    let lame = xing + 12;
+    info[lame..lame + 9].copy_from_slice(b"LAME3.100");
+    info[lame + 9] = 0x01;
+    // 576 samples of delay and 1000 samples of padding, 12 bits each.
+    info[lame + 21..lame + 24].copy_from_slice(&[0x24, 0x03, 0xE8]);
+
+    let mut frames = vec![info];
+    frames.extend((0..40).map(|_| frame(9)));
+    let (_dir, path) = write_mp3("lame", &frames);
+    let track = native::read_track(&path, None).unwrap();
+
+    assert_eq!(track.sample_count, 40 * 1152 - 576 - 1000);
+    assert_eq!(track.duration, 1009);
+}
@@ -0,0 +1,85 @@
+//! Verifies the fixture files, and damaged copies of them.
+//!
+//! Run with `cargo test --features analysis`.
+#![cfg(feature = "analysis")]
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use katatsuki::verify::{verify_stream, StreamDefect};
+use tempfile::{tempdir, TempDir};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+}
+
+/// Writes a copy of the fixture, damaged by the given function, to a temporary
+/// directory, which is removed when the returned guard is dropped.
+fn damaged_copy<F: FnOnce(&mut Vec<u8>)>(name: &str, damage: F) -> (TempDir, PathBuf) {
+    let mut bytes = fs::read(fixture(name)).unwrap();
+    damage(&mut bytes);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(name);
+    fs::write(&path, bytes).unwrap();
+    (dir, path)
+}
+
+fn verify_damaged<F: FnOnce(&mut Vec<u8>)>(name: &str, damage: F) -> Option<StreamDefect> {
+    let (_dir, path) = damaged_copy(name, damage);
+    verify_stream(&path).unwrap()
+}
+
+#[test]
+fn fixtures_have_no_defects() {
+    for name in ["cbr.mp3", "tagged.aiff", "tagged.flac", "tagged.wav"] {
+        assert_eq!(verify_stream(&fixture(name)).unwrap(), None, "{}", name);
+    }
+}
+
+#[test]
+fn undecodable_format_has_no_defects() {
+    assert_eq!(verify_stream(&fixture("tagged.dsf")).unwrap(), None);
+}
+
+#[test]
+fn truncated_flac_is_truncated() {
+    let defect = verify_damaged("tagged.flac", |bytes| bytes.truncate(bytes.len() - 1000));
+    assert_eq!(defect, Some(StreamDefect::Truncated));
+}
+
+#[test]
+fn truncated_wav_is_truncated() {
+    let defect = verify_damaged("tagged.wav", |bytes| bytes.truncate(bytes.len() - 1000));
+    assert_eq!(defect, Some(StreamDefect::Truncated));
+}
+
+#[test]
+fn damaged_flac_has_defect() {
+    let defect = verify_damaged("tagged.flac", |bytes| {
+        let len = bytes.len();
+        bytes[len - 2000] ^= 0xFF;
+    });
+    assert!(defect.is_some());
+}
+
+#[test]
+fn truncated_mp3_is_truncated() {
+    let defect = verify_damaged("cbr.mp3", |bytes| bytes.truncate(bytes.len() - 100));
+    assert_eq!(defect, Some(StreamDefect::Truncated));
+}
+
+#[test]
+fn mp3_with_garbage_loses_sync() {
+    let defect = verify_damaged("cbr.mp3", |bytes| {
+        let len = bytes.len();
+        bytes.splice(len / 2..len / 2, vec![0u8; 64]);
+    });
+    assert_eq!(defect, Some(StreamDefect::LostSync));
+}
+
+#[test]
+fn missing_file_is_not_found() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("missing.flac");
+    assert_eq!(verify_stream(&path).unwrap_err().kind(), ErrorKind::NotFound);
+}
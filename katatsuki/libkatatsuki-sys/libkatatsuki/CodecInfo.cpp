@@ -0,0 +1,381 @@
+#include "CodecInfo.h"
+
+#include <tbytevector.h>
+
+using namespace std;
+
+namespace {
+    struct Atom {
+        long long offset;
+        long long length;
+        long long headerLength;
+    };
+
+    unsigned long long read_be(const TagLib::ByteVector &bytes, unsigned int offset, unsigned int size) {
+        unsigned long long value = 0;
+        for (unsigned int i = 0; i < size; i++) {
+            value = (value << 8) | static_cast<unsigned char>(bytes[offset + i]);
+        }
+        return value;
+    }
+
+    // Finds the first child atom with the given name between the given offsets.
+    optional<Atom> find_atom(TagLib::File *file, long long offset, long long end, const char *name) {
+        while (offset + 8 <= end) {
+            file->seek(offset);
+            TagLib::ByteVector header = file->readBlock(16);
+            if (header.size() < 8) {
+                return nullopt;
+            }
+            long long length = static_cast<long long>(read_be(header, 0, 4));
+            long long headerLength = 8;
+            if (length == 1 && header.size() == 16) {
+                length = static_cast<long long>(read_be(header, 8, 8));
+                headerLength = 16;
+            } else if (length == 0) {
+                length = end - offset;
+            }
+            if (length < headerLength) {
+                return nullopt;
+            }
+            if (header.mid(4, 4) == TagLib::ByteVector(name, 4)) {
+                return Atom { offset, length, headerLength };
+            }
+            offset += length;
+        }
+        return nullopt;
+    }
+
+    optional<Atom> find_child(TagLib::File *file, const Atom &parent, const char *name) {
+        return find_atom(file, parent.offset + parent.headerLength, parent.offset + parent.length, name);
+    }
+
+    class BitReader {
+    private:
+        const TagLib::ByteVector &bytes;
+        unsigned int position;
+    public:
+        BitReader(const TagLib::ByteVector &bytes) : bytes(bytes), position(0) {}
+
+        unsigned int Remaining() {
+            return bytes.size() * 8 - position;
+        }
+
+        unsigned int Read(unsigned int bits) {
+            unsigned int value = 0;
+            for (unsigned int i = 0; i < bits && Remaining() > 0; i++, position++) {
+                unsigned char byte = static_cast<unsigned char>(bytes[position / 8]);
+                value = (value << 1) | ((byte >> (7 - position % 8)) & 1);
+            }
+            return value;
+        }
+    };
+
+    // Reads the audio object type from an AudioSpecificConfig, including SBR and PS
+    // extensions signalled after the GASpecificConfig.
+    int read_audio_object_type(const TagLib::ByteVector &config) {
+        BitReader reader(config);
+        int audioObjectType = reader.Read(5);
+        if (audioObjectType == 31) {
+            audioObjectType = 32 + reader.Read(6);
+        }
+        if (reader.Read(4) == 15) {
+            reader.Read(24);
+        }
+        reader.Read(4);
+        if (audioObjectType != 2) {
+            return audioObjectType;
+        }
+
+        // GASpecificConfig: frame length, depends on core coder, extension flag.
+        reader.Read(3);
+        if (reader.Remaining() >= 16 && reader.Read(11) == 0x2b7) {
+            if (reader.Read(5) == 5 && reader.Read(1) == 1) {
+                audioObjectType = 5;
+                reader.Read(4);
+                if (reader.Remaining() >= 12 && reader.Read(11) == 0x548 && reader.Read(1) == 1) {
+                    audioObjectType = 29;
+                }
+            }
+        }
+        return audioObjectType;
+    }
+
+    // Reads the length of an MPEG-4 descriptor, which is encoded 7 bits at a time.
+    unsigned int read_descriptor_length(const TagLib::ByteVector &data, unsigned int &position) {
+        unsigned int length = 0;
+        for (int i = 0; i < 4 && position < data.size(); i++) {
+            unsigned char byte = static_cast<unsigned char>(data[position++]);
+            length = (length << 7) | (byte & 0x7f);
+            if (!(byte & 0x80)) {
+                break;
+            }
+        }
+        return length;
+    }
+
+    // Finds the media atom of the first sound track of an MP4 file.
+    optional<Atom> find_sound_media(TagLib::File *file) {
+        auto moov = find_atom(file, 0, file->length(), "moov");
+        if (!moov) {
+            return nullopt;
+        }
+
+        long long trakOffset = moov->offset + moov->headerLength;
+        while (auto trak = find_atom(file, trakOffset, moov->offset + moov->length, "trak")) {
+            trakOffset = trak->offset + trak->length;
+            auto mdia = find_child(file, *trak, "mdia");
+            if (!mdia) {
+                continue;
+            }
+            auto hdlr = find_child(file, *mdia, "hdlr");
+            if (!hdlr) {
+                continue;
+            }
+            // The handler type follows the version, flags and predefined fields.
+            file->seek(hdlr->offset + hdlr->headerLength + 8);
+            if (file->readBlock(4) == TagLib::ByteVector("soun", 4)) {
+                return mdia;
+            }
+        }
+        return nullopt;
+    }
+
+    const int mpeg1Bitrates[15] = { 0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320 };
+    const int mpeg2Bitrates[15] = { 0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160 };
+    const int mpeg1SampleRates[3] = { 44100, 48000, 32000 };
+
+    struct FrameHeader {
+        unsigned int samplesPerFrame;
+        unsigned int frameLength;
+    };
+
+    // Parses the MPEG Layer III frame header at the given offset. Free format frames are not supported.
+    optional<FrameHeader> parse_frame_header(const TagLib::ByteVector &bytes, unsigned int offset) {
+        if (offset + 4 > bytes.size()) {
+            return nullopt;
+        }
+        unsigned char b0 = static_cast<unsigned char>(bytes[offset]);
+        unsigned char b1 = static_cast<unsigned char>(bytes[offset + 1]);
+        unsigned char b2 = static_cast<unsigned char>(bytes[offset + 2]);
+        int version = (b1 >> 3) & 0x03;
+        int layer = (b1 >> 1) & 0x03;
+        int bitrateIndex = b2 >> 4;
+        int sampleRateIndex = (b2 >> 2) & 0x03;
+        if (b0 != 0xFF || (b1 & 0xE0) != 0xE0 || version == 0x01 || layer != 0x01
+            || bitrateIndex == 0 || bitrateIndex == 0x0F || sampleRateIndex == 0x03) {
+            return nullopt;
+        }
+
+        bool isMpeg1 = version == 0x03;
+        int sampleRate = mpeg1SampleRates[sampleRateIndex] / (isMpeg1 ? 1 : (version == 0x02 ? 2 : 4));
+        int bitrate = (isMpeg1 ? mpeg1Bitrates : mpeg2Bitrates)[bitrateIndex];
+        unsigned int padding = (b2 & 0x02) ? 1 : 0;
+        unsigned int frameLength = static_cast<unsigned int>((isMpeg1 ? 144 : 72) * bitrate * 1000 / sampleRate) + padding;
+        return FrameHeader { isMpeg1 ? 1152u : 576u, frameLength };
+    }
+
+    optional<CodecInfo::Esds> parse_esds(const TagLib::ByteVector &data) {
+        // Skip the version and flags.
+        unsigned int position = 4;
+        if (position >= data.size() || static_cast<unsigned char>(data[position++]) != 0x03) {
+            return nullopt;
+        }
+        read_descriptor_length(data, position);
+        if (position + 3 > data.size()) {
+            return nullopt;
+        }
+        unsigned char flags = static_cast<unsigned char>(data[position + 2]);
+        position += 3;
+        if (flags & 0x80) {
+            position += 2;
+        }
+        if (flags & 0x40 && position < data.size()) {
+            position += 1 + static_cast<unsigned char>(data[position]);
+        }
+        if (flags & 0x20) {
+            position += 2;
+        }
+
+        if (position >= data.size() || static_cast<unsigned char>(data[position++]) != 0x04) {
+            return nullopt;
+        }
+        read_descriptor_length(data, position);
+        if (position + 13 > data.size()) {
+            return nullopt;
+        }
+        CodecInfo::Esds esds { 0, 0, 0 };
+        esds.maxBitrate = static_cast<unsigned int>(read_be(data, position + 5, 4));
+        esds.avgBitrate = static_cast<unsigned int>(read_be(data, position + 9, 4));
+        position += 13;
+
+        if (position < data.size() && static_cast<unsigned char>(data[position++]) == 0x05) {
+            unsigned int length = read_descriptor_length(data, position);
+            esds.audioObjectType = read_audio_object_type(data.mid(position, length));
+        }
+        return esds;
+    }
+}
+
+optional<CodecInfo::Esds> CodecInfo::ReadEsds(TagLib::File *file) {
+    auto mdia = find_sound_media(file);
+    auto minf = mdia ? find_child(file, *mdia, "minf") : nullopt;
+    auto stbl = minf ? find_child(file, *minf, "stbl") : nullopt;
+    auto stsd = stbl ? find_child(file, *stbl, "stsd") : nullopt;
+    if (!stsd) {
+        return nullopt;
+    }
+    file->seek(stsd->offset + stsd->headerLength);
+    TagLib::ByteVector entries = file->readBlock(static_cast<size_t>(stsd->length - stsd->headerLength));
+    int esdsOffset = entries.find("esds");
+    if (esdsOffset < 4) {
+        return nullopt;
+    }
+    unsigned int esdsLength = static_cast<unsigned int>(read_be(entries, esdsOffset - 4, 4));
+    if (esdsLength < 8) {
+        return nullopt;
+    }
+    return parse_esds(entries.mid(esdsOffset + 4, esdsLength - 8));
+}
+
+optional<unsigned long long> CodecInfo::ReadMp4SampleCount(TagLib::File *file, int sampleRate) {
+    auto mdia = find_sound_media(file);
+    auto mdhd = mdia ? find_child(file, *mdia, "mdhd") : nullopt;
+    if (!mdhd || sampleRate <= 0) {
+        return nullopt;
+    }
+    file->seek(mdhd->offset + mdhd->headerLength);
+    TagLib::ByteVector header = file->readBlock(32);
+    if (header.size() < 24) {
+        return nullopt;
+    }
+
+    // Version 1 headers have 64-bit creation and modification times and duration.
+    unsigned long long timescale, duration;
+    if (static_cast<unsigned char>(header[0]) == 1) {
+        if (header.size() < 32) {
+            return nullopt;
+        }
+        timescale = read_be(header, 20, 4);
+        duration = read_be(header, 24, 8);
+    } else {
+        timescale = read_be(header, 12, 4);
+        duration = read_be(header, 16, 4);
+    }
+    if (timescale == 0) {
+        return nullopt;
+    }
+    return duration * static_cast<unsigned long long>(sampleRate) / timescale;
+}
+
+optional<CodecInfo::LameTag> CodecInfo::ReadLameTag(TagLib::File *file, long long firstFrameOffset) {
+    file->seek(firstFrameOffset);
+    TagLib::ByteVector frame = file->readBlock(256);
+    if (frame.size() < 4) {
+        return nullopt;
+    }
+
+    // The Xing header follows the side information, whose size depends
+    // on the MPEG version and channel mode.
+    bool isMpeg1 = ((static_cast<unsigned char>(frame[1]) >> 3) & 0x03) == 0x03;
+    bool isMono = (static_cast<unsigned char>(frame[3]) >> 6) == 0x03;
+    unsigned int xingOffset = 4 + (isMpeg1 ? (isMono ? 17 : 32) : (isMono ? 9 : 17));
+    if (frame.size() < xingOffset + 8) {
+        return nullopt;
+    }
+    TagLib::ByteVector xingId = frame.mid(xingOffset, 4);
+    if (xingId != TagLib::ByteVector("Xing", 4) && xingId != TagLib::ByteVector("Info", 4)) {
+        return nullopt;
+    }
+
+    unsigned long long flags = read_be(frame, xingOffset + 4, 4);
+    unsigned int lameOffset = xingOffset + 8;
+    lameOffset += (flags & 0x01) ? 4 : 0;
+    lameOffset += (flags & 0x02) ? 4 : 0;
+    lameOffset += (flags & 0x04) ? 100 : 0;
+    lameOffset += (flags & 0x08) ? 4 : 0;
+    if (frame.size() < lameOffset + 10) {
+        return nullopt;
+    }
+
+    TagLib::ByteVector encoder = frame.mid(lameOffset, 9);
+    if (!encoder.startsWith("LAME") && !encoder.startsWith("Lavc") && !encoder.startsWith("Lavf")) {
+        return nullopt;
+    }
+    int terminator = encoder.find(TagLib::ByteVector(1, '\0'));
+    if (terminator >= 0) {
+        encoder = encoder.mid(0, terminator);
+    }
+    int vbrMethod = static_cast<unsigned char>(frame[lameOffset + 9]) & 0x0f;
+
+    // The delay and padding are packed into 12 bits each after the
+    // lowpass, replay gain, flags and bitrate fields.
+    unsigned int delay = 0;
+    unsigned int padding = 0;
+    if (frame.size() >= lameOffset + 24) {
+        unsigned long long trim = read_be(frame, lameOffset + 21, 3);
+        delay = static_cast<unsigned int>(trim >> 12);
+        padding = static_cast<unsigned int>(trim & 0xfff);
+    }
+    return LameTag { TagLib::String(encoder, TagLib::String::Latin1).stripWhiteSpace(), vbrMethod, delay, padding };
+}
+
+unsigned long long CodecInfo::CountMpegSamples(TagLib::File *file, long long firstFrameOffset) {
+    file->seek(firstFrameOffset);
+    TagLib::ByteVector frame = file->readBlock(256);
+    auto header = parse_frame_header(frame, 0);
+    if (!header) {
+        return 0;
+    }
+
+    bool isMpeg1 = header->samplesPerFrame == 1152;
+    bool isMono = (static_cast<unsigned char>(frame[3]) >> 6) == 0x03;
+    unsigned int xingOffset = 4 + (isMpeg1 ? (isMono ? 17 : 32) : (isMono ? 9 : 17));
+    TagLib::ByteVector xingId = frame.mid(xingOffset, 4);
+    bool hasXing = xingId == TagLib::ByteVector("Xing", 4) || xingId == TagLib::ByteVector("Info", 4);
+    bool hasVbri = frame.mid(36, 4) == TagLib::ByteVector("VBRI", 4);
+
+    optional<unsigned long long> frameCount;
+    if (hasXing && frame.size() >= xingOffset + 12 && (read_be(frame, xingOffset + 4, 4) & 0x01)) {
+        frameCount = read_be(frame, xingOffset + 8, 4);
+    } else if (hasVbri && frame.size() >= 54) {
+        frameCount = read_be(frame, 50, 4);
+    }
+    if (frameCount) {
+        unsigned long long samples = *frameCount * header->samplesPerFrame;
+        if (auto lameTag = ReadLameTag(file, firstFrameOffset)) {
+            unsigned long long trim = lameTag->delay + lameTag->padding;
+            samples = samples > trim ? samples - trim : 0;
+        }
+        return samples;
+    }
+
+    // Without a frame count, walk every complete frame up to any trailing ID3v1 tag.
+    long long end = file->length();
+    if (end >= 128) {
+        file->seek(end - 128);
+        if (file->readBlock(3) == TagLib::ByteVector("TAG", 3)) {
+            end -= 128;
+        }
+    }
+    if (end <= firstFrameOffset) {
+        return 0;
+    }
+    file->seek(firstFrameOffset);
+    TagLib::ByteVector bytes = file->readBlock(static_cast<size_t>(end - firstFrameOffset));
+    unsigned long long samples = 0;
+    unsigned int offset = 0;
+    while (auto next = parse_frame_header(bytes, offset)) {
+        if (offset + next->frameLength > bytes.size()) {
+            break;
+        }
+        samples += next->samplesPerFrame;
+        offset += next->frameLength;
+    }
+
+    // The Xing or VBRI frame itself does not hold any audio.
+    if ((hasXing || hasVbri) && samples >= header->samplesPerFrame) {
+        samples -= header->samplesPerFrame;
+    }
+    return samples;
+}
@@ -0,0 +1,40 @@
+#pragma once
+
+#include "codec_info.h"
+
+#include <tfile.h>
+#include <tstring.h>
+
+#include <optional>
+
+// Codec details that taglib2 reads but does not expose.
+namespace CodecInfo {
+    struct Esds {
+        int audioObjectType;
+        unsigned int maxBitrate;
+        unsigned int avgBitrate;
+    };
+
+    struct LameTag {
+        TagLib::String encoder;
+        int vbrMethod;
+        // The encoder delay and padding in samples.
+        unsigned int delay;
+        unsigned int padding;
+    };
+
+    // Reads the elementary stream descriptor of the first sound track of an MP4 file.
+    std::optional<Esds> ReadEsds(TagLib::File *file);
+
+    // Reads the LAME tag following the Xing header in the first MPEG frame.
+    std::optional<LameTag> ReadLameTag(TagLib::File *file, long long firstFrameOffset);
+
+    // Counts the samples per channel of an MPEG file, excluding the encoder delay
+    // and padding of the LAME tag. Files without a frame count in their Xing or
+    // VBRI header have every frame counted.
+    unsigned long long CountMpegSamples(TagLib::File *file, long long firstFrameOffset);
+
+    // Reads the duration of the first sound track of an MP4 file, in samples
+    // at the given sample rate.
+    std::optional<unsigned long long> ReadMp4SampleCount(TagLib::File *file, int sampleRate);
+}
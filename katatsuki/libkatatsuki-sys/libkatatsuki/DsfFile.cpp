@@ -0,0 +1,85 @@
+#include "DsfFile.h"
+
+#include <tbytevector.h>
+
+using namespace std;
+
+// DSF chunks are little endian.
+static unsigned long long read_le(const TagLib::ByteVector &bytes, unsigned int offset, unsigned int size) {
+    unsigned long long value = 0;
+    for (unsigned int i = 0; i < size; i++) {
+        value |= static_cast<unsigned long long>(static_cast<unsigned char>(bytes[offset + i])) << (8 * i);
+    }
+    return value;
+}
+
+DSF::File::File(TagLib::FileName file) : TagLib::File(file), channels(0), samplingFrequency(0), sampleCount(0) {
+    if (isOpen()) {
+        read();
+    }
+}
+
+void DSF::File::read() {
+    // DSD chunk: 'DSD ', chunk size, total file size, pointer to metadata.
+    seek(0);
+    TagLib::ByteVector dsd = readBlock(28);
+    if (dsd.size() != 28 || !dsd.startsWith("DSD ")) {
+        setValid(false);
+        return;
+    }
+    unsigned long long metadataOffset = read_le(dsd, 20, 8);
+
+    // fmt chunk: 'fmt ', chunk size, version, format id, channel type, channel count,
+    // sampling frequency, bits per sample, sample count, block size, reserved.
+    TagLib::ByteVector fmt = readBlock(52);
+    if (fmt.size() != 52 || !fmt.startsWith("fmt ")) {
+        setValid(false);
+        return;
+    }
+    channels = static_cast<unsigned int>(read_le(fmt, 24, 4));
+    samplingFrequency = static_cast<unsigned int>(read_le(fmt, 28, 4));
+    sampleCount = read_le(fmt, 36, 8);
+
+    if (metadataOffset > 0 && metadataOffset < static_cast<unsigned long long>(length())) {
+        id3v2Tag = make_unique<TagLib::ID3v2::Tag>(this, static_cast<long>(metadataOffset));
+    } else {
+        id3v2Tag = make_unique<TagLib::ID3v2::Tag>();
+    }
+}
+
+TagLib::Tag *DSF::File::tag() const {
+    return id3v2Tag.get();
+}
+
+TagLib::AudioProperties *DSF::File::audioProperties() const {
+    return nullptr;
+}
+
+bool DSF::File::save() {
+    // katatsuki never writes tags.
+    return false;
+}
+
+const unsigned int DSF::File::GetChannels() {
+    return channels;
+}
+
+const unsigned int DSF::File::GetSamplingFrequency() {
+    return samplingFrequency;
+}
+
+const long long DSF::File::GetDuration() {
+    if (samplingFrequency == 0) {
+        return 0;
+    }
+    return static_cast<long long>(sampleCount * 1000 / samplingFrequency);
+}
+
+const long long DSF::File::GetSampleCount() {
+    return static_cast<long long>(sampleCount);
+}
+
+const int DSF::File::GetBitrate() {
+    // DSD is 1 bit per sample.
+    return static_cast<int>((static_cast<unsigned long long>(samplingFrequency) * channels + 500) / 1000);
+}
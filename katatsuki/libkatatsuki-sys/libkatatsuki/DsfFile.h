@@ -0,0 +1,33 @@
+#pragma once
+
+#include <tfile.h>
+#include <tag.h>
+#include <audioproperties.h>
+#include <id3v2tag.h>
+
+#include <memory>
+
+namespace DSF {
+    // taglib2 does not support DSF, so this reads the DSD and fmt chunks
+    // and the trailing ID3v2 tag itself. Audio properties are exposed
+    // directly rather than through TagLib::AudioProperties.
+    class File : public TagLib::File {
+    private:
+        std::unique_ptr<TagLib::ID3v2::Tag> id3v2Tag;
+        unsigned int channels;
+        unsigned int samplingFrequency;
+        unsigned long long sampleCount;
+        void read();
+    public:
+        explicit File(TagLib::FileName file);
+        virtual ~File() {};
+        TagLib::Tag *tag() const override;
+        TagLib::AudioProperties *audioProperties() const override;
+        bool save() override;
+        const unsigned int GetChannels();
+        const unsigned int GetSamplingFrequency();
+        const long long GetDuration();
+        const long long GetSampleCount();
+        const int GetBitrate();
+    };
+}
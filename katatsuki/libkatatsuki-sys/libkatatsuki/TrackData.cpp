@@ -1,4 +1,6 @@
 #include "TrackData.h"
+#include "CodecInfo.h"
+#include "DsfFile.h"
 #include "StringUtils.h"
 #include "track_file_type.h"
 
@@ -10,8 +12,11 @@
 #include <mpegfile.h>
 #include <mp4file.h>
 #include <oggfile.h>
+#include <oggpageheader.h>
 #include <vorbisfile.h>
 #include <opusfile.h>
+#include <wavfile.h>
+#include <wavpackfile.h>
 #include <oggflacfile.h>
 #include <tstring.h>
 #include <xiphcomment.h>
@@ -23,17 +28,55 @@
 #include <iostream>
 #include <utility>
 #include <memory>
+#include <sstream>
+#include <algorithm>
+#include <cstdlib>
 
 using namespace std;
 
+namespace {
+    // Reads the number of samples in the iTunSMPB tag written by iTunes and other
+    // AAC encoders, which excludes encoder delay and padding. The tag holds hex
+    // fields for the delay, padding and sample count after a leading zero field.
+    optional<unsigned long long> read_itunes_sample_count(TagLib::MP4::File *file) {
+        auto item = file->tag()->item("----:com.apple.iTunes:iTunSMPB");
+        if (!item.isValid() || item.toStringList().isEmpty()) {
+            return nullopt;
+        }
+        istringstream fields(item.toStringList().front().to8Bit());
+        string field;
+        for (int i = 0; i < 4; i++) {
+            if (!(fields >> field)) {
+                return nullopt;
+            }
+        }
+        unsigned long long sampleCount = strtoull(field.c_str(), nullptr, 16);
+        if (sampleCount == 0) {
+            return nullopt;
+        }
+        return sampleCount;
+    }
+}
+
 TrackData::TrackData(const char* track_path) {
     TagLib::String path(track_path, TagLib::String::UTF8);
     
     #ifdef _WIN32
-    f = make_shared<TagLib::FileRef>(TagLib::FileName(path.toWString().data()), true, TagLib::AudioProperties::Accurate);
+    TagLib::FileName fileName(path.toWString().data());
     #else
-    f = make_shared<TagLib::FileRef>(TagLib::FileName(path.to8Bit(true).data()), true, TagLib::AudioProperties::Accurate);
+    TagLib::FileName fileName(path.to8Bit(true).data());
     #endif
+    f = make_shared<TagLib::FileRef>(fileName, true, TagLib::AudioProperties::Accurate);
+
+    // taglib2 does not know about DSF, so try reading it ourselves.
+    if (f->isNull()) {
+        auto dsfFile = new DSF::File(fileName);
+        if (dsfFile->isValid()) {
+            f = make_shared<TagLib::FileRef>(dsfFile);
+        } else {
+            delete dsfFile;
+        }
+    }
 }
 
 const TagLib::String TrackData::GetTitle() {
@@ -106,10 +149,16 @@ const bool TrackData::HasAlbumArt() {
 }
 
 const int TrackData::GetBitrate() {
+    if (auto dsfFile = dynamic_cast<DSF::File *>(f->file())) {
+        return dsfFile->GetBitrate();
+    }
     return f->audioProperties()->bitrate();
 }
 
 const int TrackData::GetSampleRate() {
+    if (auto dsfFile = dynamic_cast<DSF::File *>(f->file())) {
+        return dsfFile->GetSamplingFrequency();
+    }
     return f->audioProperties()->sampleRate();
 }
 
@@ -122,9 +171,71 @@ const unsigned int TrackData::GetDiscNumber() {
 }
 
 const long long TrackData::GetDuration() {
+    if (auto dsfFile = dynamic_cast<DSF::File *>(f->file())) {
+        return dsfFile->GetDuration();
+    }
+    long long sampleCount = GetSampleCount();
+    // Opus is always decoded at 48kHz, whatever the sample rate of its source.
+    long long sampleRate = dynamic_cast<TagLib::Ogg::Opus::File *>(f->file()) ? 48000 : GetSampleRate();
+    if (sampleCount > 0 && sampleRate > 0) {
+        return (sampleCount * 1000 + sampleRate / 2) / sampleRate;
+    }
     return f->audioProperties()->lengthInMilliseconds();
 }
 
+const long long TrackData::GetSampleCount() {
+    if (auto dsfFile = dynamic_cast<DSF::File *>(f->file())) {
+        return dsfFile->GetSampleCount();
+    }
+    if (auto mp3File = dynamic_cast<TagLib::MPEG::File *>(f->file())) {
+        return static_cast<long long>(CodecInfo::CountMpegSamples(mp3File, mp3File->firstFrameOffset()));
+    }
+    if (auto mp4File = dynamic_cast<TagLib::MP4::File *>(f->file())) {
+        if (auto sampleCount = read_itunes_sample_count(mp4File)) {
+            return static_cast<long long>(*sampleCount);
+        }
+        // The duration of an MP4 track is in the timescale of its media header,
+        // which is usually, but not always, the sample rate.
+        if (auto sampleCount = CodecInfo::ReadMp4SampleCount(mp4File, GetSampleRate())) {
+            return static_cast<long long>(*sampleCount);
+        }
+        return 0;
+    }
+    if (auto flacFile = dynamic_cast<TagLib::FLAC::File *>(f->file())) {
+        return static_cast<long long>(flacFile->audioProperties()->sampleFrames());
+    }
+    if (auto oggFlacFile = dynamic_cast<TagLib::Ogg::FLAC::File *>(f->file())) {
+        return static_cast<long long>(oggFlacFile->audioProperties()->sampleFrames());
+    }
+    if (auto aiffFile = dynamic_cast<TagLib::RIFF::AIFF::File *>(f->file())) {
+        return static_cast<long long>(aiffFile->audioProperties()->sampleFrames());
+    }
+    if (auto wavFile = dynamic_cast<TagLib::RIFF::WAV::File *>(f->file())) {
+        return static_cast<long long>(wavFile->audioProperties()->sampleFrames());
+    }
+    if (auto apeFile = dynamic_cast<TagLib::APE::File *>(f->file())) {
+        return static_cast<long long>(apeFile->audioProperties()->sampleFrames());
+    }
+    if (auto wavPackFile = dynamic_cast<TagLib::WavPack::File *>(f->file())) {
+        return static_cast<long long>(wavPackFile->audioProperties()->sampleFrames());
+    }
+    if (auto vorbisFile = dynamic_cast<TagLib::Ogg::Vorbis::File *>(f->file())) {
+        auto lastPage = vorbisFile->lastPageHeader();
+        return lastPage ? max(lastPage->absoluteGranularPosition(), 0LL) : 0;
+    }
+    if (auto opusFile = dynamic_cast<TagLib::Ogg::Opus::File *>(f->file())) {
+        // The granule position of Opus streams includes the pre-skip.
+        auto lastPage = opusFile->lastPageHeader();
+        TagLib::ByteVector header = opusFile->packet(0);
+        if (!lastPage || header.size() < 12) {
+            return 0;
+        }
+        long long preSkip = header.toUShort(10, false);
+        return max(lastPage->absoluteGranularPosition() - preSkip, 0LL);
+    }
+    return 0;
+}
+
 const enum track_file_type TrackData::GetFileType() {
 
     if (auto mp3File = dynamic_cast<TagLib::MPEG::File *>(f->file())) {
@@ -162,6 +273,17 @@ const enum track_file_type TrackData::GetFileType() {
         int bitDepth = apeFile->audioProperties()->bitsPerSample();
         return get_monkeys_audio_type(bitDepth);
     }
+    if (auto wavPackFile = dynamic_cast<TagLib::WavPack::File *>(f->file())) {
+        int bitDepth = wavPackFile->audioProperties()->bitsPerSample();
+        return get_wavpack_type(bitDepth);
+    }
+    if (auto wavFile = dynamic_cast<TagLib::RIFF::WAV::File *>(f->file())) {
+        int bitDepth = wavFile->audioProperties()->bitsPerSample();
+        return get_wav_type(bitDepth);
+    }
+    if (auto dsfFile = dynamic_cast<DSF::File *>(f->file())) {
+        return get_dsd_type(dsfFile->GetSamplingFrequency());
+    }
     if (dynamic_cast<TagLib::Ogg::Vorbis::File *>(f->file())) {
         return track_file_type::Vorbis;
     }
@@ -188,3 +310,83 @@ const TagLib::String TrackData::GetMusicBrainzTrackId() {
     }
     return TagLib::String();
 }
+
+const enum codec_profile TrackData::GetCodecProfile() {
+    if (auto mp4File = dynamic_cast<TagLib::MP4::File *>(f->file())) {
+        if (mp4File->audioProperties()->codec() != TagLib::MP4::AudioProperties::Codec::AAC) {
+            return codec_profile::UnknownProfile;
+        }
+        if (auto esds = CodecInfo::ReadEsds(mp4File)) {
+            return get_aac_profile(esds->audioObjectType);
+        }
+    }
+    return codec_profile::UnknownProfile;
+}
+
+const enum bitrate_mode TrackData::GetBitrateMode() {
+    if (auto mp3File = dynamic_cast<TagLib::MPEG::File *>(f->file())) {
+        if (auto lameTag = CodecInfo::ReadLameTag(mp3File, mp3File->firstFrameOffset())) {
+            auto mode = get_lame_bitrate_mode(lameTag->vbrMethod);
+            if (mode != bitrate_mode::UnknownMode) {
+                return mode;
+            }
+        }
+        // Consistent with the MP3CBR and MP3VBR file types.
+        return mp3File->audioProperties()->xingHeader() ? bitrate_mode::Variable : bitrate_mode::Constant;
+    }
+    if (auto mp4File = dynamic_cast<TagLib::MP4::File *>(f->file())) {
+        // MP4 does not store the encoding mode, but constant bitrate
+        // encoders write the same maximum and average bitrate.
+        auto esds = CodecInfo::ReadEsds(mp4File);
+        if (!esds || esds->avgBitrate == 0) {
+            return bitrate_mode::UnknownMode;
+        }
+        return esds->maxBitrate == esds->avgBitrate ? bitrate_mode::Constant : bitrate_mode::Variable;
+    }
+    if (auto vorbisFile = dynamic_cast<TagLib::Ogg::Vorbis::File *>(f->file())) {
+        auto properties = vorbisFile->audioProperties();
+        int nominal = properties->bitrateNominal();
+        if (nominal > 0 && properties->bitrateMaximum() == nominal && properties->bitrateMinimum() == nominal) {
+            return bitrate_mode::Constant;
+        }
+        if (nominal > 0 && properties->bitrateMaximum() <= 0 && properties->bitrateMinimum() <= 0) {
+            return bitrate_mode::Variable;
+        }
+        return bitrate_mode::Average;
+    }
+    return bitrate_mode::UnknownMode;
+}
+
+const TagLib::String TrackData::GetEncoder() {
+    if (auto mp3File = dynamic_cast<TagLib::MPEG::File *>(f->file())) {
+        if (auto lameTag = CodecInfo::ReadLameTag(mp3File, mp3File->firstFrameOffset())) {
+            return lameTag->encoder;
+        }
+    }
+    if (auto vorbisFile = dynamic_cast<TagLib::Ogg::Vorbis::File *>(f->file())) {
+        if (!vorbisFile->tag()->vendorID().isEmpty()) {
+            return vorbisFile->tag()->vendorID();
+        }
+    }
+    if (auto opusFile = dynamic_cast<TagLib::Ogg::Opus::File *>(f->file())) {
+        if (!opusFile->tag()->vendorID().isEmpty()) {
+            return opusFile->tag()->vendorID();
+        }
+    }
+
+    // Fall back to the encoder tag, which is TSSE in ID3v2 and ©too in MP4.
+    auto properties = f->tag()->properties();
+    for (auto key : { "ENCODER", "ENCODING", "ENCODEDBY" }) {
+        if (!properties[key].isEmpty()) {
+            return properties[key].front();
+        }
+    }
+    return TagLib::String();
+}
+
+const int TrackData::GetChannels() {
+    if (auto dsfFile = dynamic_cast<DSF::File *>(f->file())) {
+        return dsfFile->GetChannels();
+    }
+    return f->audioProperties()->channels();
+}
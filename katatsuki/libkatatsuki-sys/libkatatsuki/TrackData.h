@@ -1,4 +1,5 @@
 #include "track_file_type.h"
+#include "codec_info.h"
 
 #include <tstring.h>
 #include <fileref.h>
@@ -25,5 +26,10 @@ public:
 	const int GetSampleRate();
 	const unsigned int GetDiscNumber();
 	const long long GetDuration();
+	const long long GetSampleCount();
+	const enum codec_profile GetCodecProfile();
+	const enum bitrate_mode GetBitrateMode();
+	const TagLib::String GetEncoder();
+	const int GetChannels();
 	std::unique_ptr<TagLib::ByteVector> GetAlbumArtBytes();
 };
\ No newline at end of file
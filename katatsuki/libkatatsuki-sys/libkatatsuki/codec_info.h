@@ -0,0 +1,65 @@
+#pragma once
+typedef enum codec_profile {
+    UnknownProfile = 0,
+
+    // The AAC profiles, by MPEG-4 audio object type.
+    AACMain = 1,
+    AACLC = 2,
+    AACSSR = 3,
+    AACLTP = 4,
+    HEAAC = 5,
+    HEAACv2 = 6,
+    AACLD = 7,
+    AACELD = 8,
+} codec_profile;
+
+typedef enum bitrate_mode {
+    UnknownMode = 0,
+    Constant = 1,
+    Variable = 2,
+    Average = 3,
+} bitrate_mode;
+
+static inline const enum codec_profile get_aac_profile(int audio_object_type) {
+    switch (audio_object_type)
+    {
+        case 1:
+            return codec_profile::AACMain;
+        case 2:
+            return codec_profile::AACLC;
+        case 3:
+            return codec_profile::AACSSR;
+        case 4:
+            return codec_profile::AACLTP;
+        case 5:
+            return codec_profile::HEAAC;
+        case 29:
+            return codec_profile::HEAACv2;
+        case 23:
+            return codec_profile::AACLD;
+        case 39:
+            return codec_profile::AACELD;
+        default:
+            return codec_profile::UnknownProfile;
+    }
+};
+
+// Maps the VBR method of a LAME tag.
+static inline const enum bitrate_mode get_lame_bitrate_mode(int vbr_method) {
+    switch (vbr_method)
+    {
+        case 1:
+        case 8:
+            return bitrate_mode::Constant;
+        case 2:
+        case 9:
+            return bitrate_mode::Average;
+        case 3:
+        case 4:
+        case 5:
+        case 6:
+            return bitrate_mode::Variable;
+        default:
+            return bitrate_mode::UnknownMode;
+    }
+};
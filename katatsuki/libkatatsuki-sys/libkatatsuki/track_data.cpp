@@ -11,6 +11,8 @@
 #include <iostream>
 #include <memory>
 #include <cstring>
+#include <cstdlib>
+#include <algorithm>
 
 extern "C" const int get_file_type(track_data* track_data) {
     auto* trackData = reinterpret_cast<TrackData*>(track_data);
@@ -71,11 +73,36 @@ extern "C" const long long get_duration(track_data* track_data) {
     return trackData->GetDuration();
 }
 
+extern "C" const long long get_sample_count(track_data* track_data) {
+    auto* trackData = reinterpret_cast<TrackData*>(track_data);
+    return trackData->GetSampleCount();
+}
+
 extern "C" const int get_bitrate(track_data* track_data) {
     auto* trackData = reinterpret_cast<TrackData*>(track_data);
     return trackData->GetBitrate();
 }
 
+extern "C" const int get_codec_profile(track_data* track_data) {
+    auto* trackData = reinterpret_cast<TrackData*>(track_data);
+    return trackData->GetCodecProfile();
+}
+
+extern "C" const int get_bitrate_mode(track_data* track_data) {
+    auto* trackData = reinterpret_cast<TrackData*>(track_data);
+    return trackData->GetBitrateMode();
+}
+
+extern "C" const char* get_encoder(track_data* track_data) {
+    auto* trackData = reinterpret_cast<TrackData*>(track_data);
+    return strdup(trackData->GetEncoder().to8Bit(true).c_str());
+}
+
+extern "C" const int get_channels(track_data* track_data) {
+    auto* trackData = reinterpret_cast<TrackData*>(track_data);
+    return trackData->GetChannels();
+}
+
 extern "C" const int get_sample_rate(track_data* track_data) {
     auto* trackData = reinterpret_cast<TrackData*>(track_data);
     return trackData->GetSampleRate();
@@ -85,19 +112,33 @@ extern "C" const unsigned char* get_album_art_all_bytes(track_data* track_data)
     auto* trackData = reinterpret_cast<TrackData*>(track_data);
     auto bytes = trackData->GetAlbumArtBytes();
     if (bytes) {
-        unsigned char *copy = new unsigned char[bytes->size()];
+        // Allocated with malloc so that free_allocated_data can release it.
+        auto *copy = static_cast<unsigned char *>(malloc(bytes->size()));
         memcpy(copy, bytes->data(), bytes->size());
         return copy;
     }
     return nullptr;
 }
 
+extern "C" const size_t get_album_art_size(track_data* track_data) {
+    auto* trackData = reinterpret_cast<TrackData*>(track_data);
+    auto bytes = trackData->GetAlbumArtBytes();
+    if (bytes) {
+        return bytes->size();
+    }
+    return 0;
+}
+
 extern "C" const unsigned char* get_album_art_bytes(track_data* track_data, size_t size) {
     auto* trackData = reinterpret_cast<TrackData*>(track_data);
     auto bytes = trackData->GetAlbumArtBytes();
     if (bytes) {
-        unsigned char *copy = new unsigned char[size];
-        memcpy(copy, bytes->data(), size);
+        // Allocated with calloc so that free_allocated_data can release it, and
+        // so that bytes past the end of a cover shorter than size read as zero.
+        auto *copy = static_cast<unsigned char *>(calloc(size, 1));
+        if (copy) {
+            memcpy(copy, bytes->data(), std::min(size, static_cast<size_t>(bytes->size())));
+        }
         return copy;
     }
     return nullptr;
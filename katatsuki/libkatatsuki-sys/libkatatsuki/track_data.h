@@ -33,14 +33,26 @@ const unsigned int get_disc_number(track_data *track_data);
 
 const long long get_duration(track_data *track_data);
 
+const long long get_sample_count(track_data *track_data);
+
 const int get_bitrate(track_data *track_data);
 
 const int get_sample_rate(track_data *track_data);
 
+const int get_codec_profile(track_data *track_data);
+
+const int get_bitrate_mode(track_data *track_data);
+
+const char *get_encoder(track_data *track_data);
+
+const int get_channels(track_data *track_data);
+
 const unsigned char *get_album_art_all_bytes(track_data *track_data);
 
 const unsigned char *get_album_art_bytes(track_data* track_data, size_t size);
 
+const size_t get_album_art_size(track_data *track_data);
+
 const int get_file_type(track_data *track_data);
 
 const bool has_album_art(track_data *track_data);
@@ -47,6 +47,31 @@ typedef enum track_file_type {
     MonkeysAudio24 = 23,
     MonkeysAudio = 24,
 
+    // WavPack range is [25, 29]
+    WavPack8 = 25,
+    WavPack16 = 26,
+    WavPack24 = 27,
+    WavPack32 = 28,
+    WavPack = 29,
+
+    // WAV range is [30, 34]
+    WAV8 = 30,
+    WAV16 = 31,
+    WAV24 = 32,
+    WAV32 = 33,
+    WAV = 34,
+
+    // DSD range is [35, 39], by multiple of the 44.1kHz sample rate.
+    DSD64 = 35,
+    DSD128 = 36,
+    DSD256 = 37,
+    DSD512 = 38,
+    DSD = 39,
+
+    // Hi-Res Alac, which extends the Alac range with [40, 41]
+    ALAC20 = 40,
+    ALAC32 = 41,
+
     /// Generic for matching, this is not actually a valid return from katatsuki.
     MP3 = 780,
 } track_file_type;
@@ -107,9 +132,61 @@ static inline const enum track_file_type get_alac_type(int bitdepth) {
     {
         case 16:
             return track_file_type::ALAC16;
+        case 20:
+            return track_file_type::ALAC20;
         case 24:
             return track_file_type::ALAC24;
+        case 32:
+            return track_file_type::ALAC32;
         default:
             return track_file_type::ALAC;
     }
+};
+
+static inline const enum track_file_type get_wavpack_type(int bitdepth) {
+    switch (bitdepth)
+    {
+        case 8:
+            return track_file_type::WavPack8;
+        case 16:
+            return track_file_type::WavPack16;
+        case 24:
+            return track_file_type::WavPack24;
+        case 32:
+            return track_file_type::WavPack32;
+        default:
+            return track_file_type::WavPack;
+    }
+};
+
+static inline const enum track_file_type get_wav_type(int bitdepth) {
+    switch (bitdepth)
+    {
+        case 8:
+            return track_file_type::WAV8;
+        case 16:
+            return track_file_type::WAV16;
+        case 24:
+            return track_file_type::WAV24;
+        case 32:
+            return track_file_type::WAV32;
+        default:
+            return track_file_type::WAV;
+    }
+};
+
+static inline const enum track_file_type get_dsd_type(unsigned int sampling_frequency) {
+    switch (sampling_frequency)
+    {
+        case 2822400:
+            return track_file_type::DSD64;
+        case 5644800:
+            return track_file_type::DSD128;
+        case 11289600:
+            return track_file_type::DSD256;
+        case 22579200:
+            return track_file_type::DSD512;
+        default:
+            return track_file_type::DSD;
+    }
 };
\ No newline at end of file
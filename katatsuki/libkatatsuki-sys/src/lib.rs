@@ -53,6 +53,10 @@ extern "C" {
     pub fn get_duration(track_data: *mut track_data)
      -> ::std::os::raw::c_longlong;
 }
+extern "C" {
+    pub fn get_sample_count(track_data: *mut track_data)
+     -> ::std::os::raw::c_longlong;
+}
 extern "C" {
     pub fn get_bitrate(track_data: *mut track_data) -> ::std::os::raw::c_int;
 }
@@ -60,6 +64,22 @@ extern "C" {
     pub fn get_sample_rate(track_data: *mut track_data)
      -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn get_codec_profile(track_data: *mut track_data)
+     -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn get_bitrate_mode(track_data: *mut track_data)
+     -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn get_encoder(track_data: *mut track_data)
+     -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn get_channels(track_data: *mut track_data)
+     -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn get_album_art_all_bytes(track_data: *mut track_data)
      -> *const ::std::os::raw::c_uchar;
@@ -68,6 +88,9 @@ extern "C" {
     pub fn get_album_art_bytes(track_data: *mut track_data, size: usize)
      -> *const ::std::os::raw::c_uchar;
 }
+extern "C" {
+    pub fn get_album_art_size(track_data: *mut track_data) -> usize;
+}
 extern "C" {
     pub fn get_file_type(track_data: *mut track_data)
      -> ::std::os::raw::c_int;
@@ -28,12 +28,16 @@ The following bangs accept a search term case insensitively, and can be capitali
 
 **!s** *Source*
 
+**!lbl** *Label* (added by the rule of a source)
+
+**!enc / !ENC** *Encoder* (for example LAME3.100)
+
 ## Format Bang
 The format bang (**!f**) accepts searching for the following formats.
 
 **flac / flac4 / flac8 / flac16/ flac24 / flac32** *FLAC (4/8/16/24/32-bit)* 
 
-**alac / alac16 / alac24** *ALAC (16/24-bit)* 
+**alac / alac16 / alac20 / alac24 / alac32** *ALAC (16/20/24/32-bit)* 
 
 **mp3 / cbr / vbr** *MP3 (Constant/Variable bitrate)*
 
@@ -45,6 +49,19 @@ The format bang (**!f**) accepts searching for the following formats.
 
 **ape / ape8 / ape16 / ape24** *Monkey's Audio (8/16/24-bit)*
 
+**wavpack / wavpack8 / wavpack16 / wavpack24 / wavpack32** *WavPack (8/16/24/32-bit)*
+
+**wav / wav8 / wav16 / wav24 / wav32** *WAV (8/16/24/32-bit)*
+
+**dsd / dsd64 / dsd128 / dsd256 / dsd512** *DSF (DSD64/128/256/512)*
+
+## Encoding Bangs
+The bitrate mode bang (**!brm**) accepts **cbr / vbr / abr** (Constant/Variable/Average bitrate), for MP3, AAC and Vorbis.
+
+The codec profile bang (**!prof**) accepts the AAC profiles **lc / he / hev2 / main / ssr / ltp / ld / eld**.
+
+The channels bang (**!chn**) accepts the number of audio channels, for example **!chn{1}** for mono tracks.
+
 ## Boolean Bangs
 The following bangs accept either a **true** or **false** value. You can also append a backtick (*\`*) as
 shorthand for **true**, for example **!dup\`** translates to **!dup{true}**.
@@ -55,6 +72,12 @@ shorthand for **true**, for example **!dup\`** translates to **!dup{true}**.
 
 **!c** *Tracks have cover art tag* 
 
+**!cns** *Cover art is not square* 
+
+**!cdiff** *Album has tracks with different cover art* 
+
+**!fake** *Lossless track was likely transcoded from a lossy file* 
+
 ## Numerical Tags
 These tags take a number, and are used to look up things that are greater than (**gt**) or less than (**lt**)
 a value.
@@ -65,6 +88,11 @@ a value.
 
 **!chlt / !chgt** *Cover Art Height (pixels)* 
 
+**!cslt / !csgt** *Cover Art File Size (bytes)* 
+
+## Cover Format Bang
+The cover format bang (**!cf**) accepts **jpg / png / gif / bmp / webp**.
+
 ## Duration Tags
 These tags take in a duration in the form **0h0m0s**, where **0** is a placeholder for any number. 
 
@@ -1,8 +1,10 @@
 import { Track } from "./types";
 
 interface Seiri {
-    queryTracks: (bang: string) => { tracks: Track[] };
-    refreshTracks: (filePaths: string[]) => void;
+    queryTracks: (bang: string, library?: string) => { tracks: Track[] };
+    refreshTracks: (filePaths: string[], library?: string) => void;
+    getCoverThumbnail: (filePath: string, library?: string) => string | null;
+    getLibraries: () => string[];
     openTrackFolder: (track: Track) => void;
     hideWindow: () => void;
 }
@@ -15,11 +15,18 @@ export interface Track {
   hasFrontCover: boolean;
   frontCoverHeight: number;
   frontCoverWidth: number;
+  frontCoverMimeType: string | null;
+  frontCoverSize: number;
   bitrate: number;
+  bitrateMode: BitrateMode;
+  codecProfile: CodecProfile;
+  encoder: string | null;
+  channels: number;
   sampleRate: number;
   source: string;
   discNumber: number;
   duration: number;
+  sampleCount: number;
   fileType: TrackFileType;
   updated: UpdateDate;
 }
@@ -41,7 +48,8 @@ export enum TrackFileType {
   Vorbis = 10,
   Opus = 11,
 
-  // The Alac range is [12, 14]
+  // The Alac range is [12, 14], and is extended by the Hi-Res
+  // Alac range [40, 41].
   // Dummy for switching on.
   ALAC16 = 12,
   ALAC24 = 13,
@@ -63,6 +71,52 @@ export enum TrackFileType {
   MonkeysAudio24 = 23,
   MonkeysAudio = 24,
 
+  // WavPack range is [25, 29]
+  WavPack8 = 25,
+  WavPack16 = 26,
+  WavPack24 = 27,
+  WavPack32 = 28,
+  WavPack = 29,
+
+  // WAV range is [30, 34]
+  WAV8 = 30,
+  WAV16 = 31,
+  WAV24 = 32,
+  WAV32 = 33,
+  WAV = 34,
+
+  // DSD range is [35, 39]
+  DSD64 = 35,
+  DSD128 = 36,
+  DSD256 = 37,
+  DSD512 = 38,
+  DSD = 39,
+
+  // The Hi-Res Alac range is [40, 41]
+  ALAC20 = 40,
+  ALAC32 = 41,
+
   /// Generic for matching, this is not actually a valid return from katatsuki.
   MP3 = 780,
-}
\ No newline at end of file
+}
+
+export enum CodecProfile {
+  Unknown = 0,
+
+  // The AAC profiles.
+  AACMain = 1,
+  AACLC = 2,
+  AACSSR = 3,
+  AACLTP = 4,
+  HEAAC = 5,
+  HEAACv2 = 6,
+  AACLD = 7,
+  AACELD = 8,
+}
+
+export enum BitrateMode {
+  Unknown = 0,
+  Constant = 1,
+  Variable = 2,
+  Average = 3,
+}
@@ -25,8 +25,12 @@ export const fileTypeString = (fileType: TrackFileType) => {
             return "Apple Lossless";
         case TrackFileType.ALAC16:
             return "Apple Lossless (16-bit)";
+        case TrackFileType.ALAC20:
+            return "Apple Lossless (20-bit Hi-Res)";
         case TrackFileType.ALAC24:
             return "Apple Lossless (24-bit Hi-Res)";
+        case TrackFileType.ALAC32:
+            return "Apple Lossless (32-bit Hi-Res)";
         case TrackFileType.AIFF:
             return "AIFF (PCM Audio)";
         case TrackFileType.AIFF4:
@@ -49,6 +53,36 @@ export const fileTypeString = (fileType: TrackFileType) => {
             return "Monkey's Audio (16-bit)";
         case TrackFileType.MonkeysAudio24:
             return "Monkey's Audio (24-bit)";
+        case TrackFileType.WavPack:
+            return "WavPack";
+        case TrackFileType.WavPack8:
+            return "WavPack (8-bit)";
+        case TrackFileType.WavPack16:
+            return "WavPack (16-bit)";
+        case TrackFileType.WavPack24:
+            return "WavPack (24-bit Hi-Res)";
+        case TrackFileType.WavPack32:
+            return "WavPack (32-bit)";
+        case TrackFileType.WAV:
+            return "WAV (PCM Audio)";
+        case TrackFileType.WAV8:
+            return "WAV (8-bit PCM)"
+        case TrackFileType.WAV16:
+            return "WAV (16-bit PCM)"
+        case TrackFileType.WAV24:
+            return "WAV (24-bit PCM)"
+        case TrackFileType.WAV32:
+            return "WAV (32-bit PCM)"
+        case TrackFileType.DSD:
+            return "DSD";
+        case TrackFileType.DSD64:
+            return "DSD64 (2.8 MHz)";
+        case TrackFileType.DSD128:
+            return "DSD128 (5.6 MHz)";
+        case TrackFileType.DSD256:
+            return "DSD256 (11.2 MHz)";
+        case TrackFileType.DSD512:
+            return "DSD512 (22.6 MHz)";
         case TrackFileType.Unknown:
             return "Unknown";
         default:
@@ -46,116 +46,132 @@ if (!gotLock) {
 
 /*
 
-| Code                          | Description                                            |
-| ----------------------------- | ------------------------------------------------------ |
-| `TRACKADDED(Artist||Title)`   | A track has successfully been added to the library     |
-| `!ETRACK`                      | Generic track error                                    |
-| !`ETRACKMOVE(Path)`            | The given track could not be moved to its library path |
-| `!ECREATEDIRECTORY(Directory)` | The given directory could not be created               |
-| `!ENONTRACK(Path)`             | The given path is not a track                          |
-| !`EMISSINGTAG(Track||Tag)`     | The given track is missing the given tag               |
-| !`EWATCHER`                    | Generic watcher error                                  |
-| !`EWATCHERDIED`                | The watcher died                                       |
-| !`EWATCHERNOACCESS(Path)`      | The watcher can not access the given folder            |
-| `ECONFIGINVALID`              | The configuration file is invalid                      |
-| `ECONFIGIO(Path)`             | The given configuration path can not be accessed       |
+| Type                      | Description                                            |
+| ------------------------- | ------------------------------------------------------ |
+| `track_added`             | A track has successfully been added to the library     |
+| !`track_error`            | Generic track error                                    |
+| !`track_move_error`       | The given track could not be moved to its library path |
+| !`create_directory_error` | The given directory could not be created               |
+| !`non_track`              | The given path is not a track                          |
+| !`missing_tag`            | The given track is missing the given tag               |
+| !`corrupt`                | The given track is corrupt                             |
+| !`rejected`               | The given track is rejected by the rule of its source  |
+| !`watcher_error`          | Generic watcher error                                  |
+| !`watcher_died`           | The watcher died                                       |
+| !`watcher_restart`        | Watcher is restarting                                  |
+| !`watcher_no_access`      | The watcher can not access the given folder            |
+| !`config_invalid`         | The configuration file is invalid, and was not reloaded |
+| !`config_io_error`        | The given configuration path can not be accessed       |
+| !`config_no_home`         | The home directory of the user can not be found        |
+| `no_lock`                 | Another watcher is already running for the data folder |
+| `control_error`           | The control socket could not be opened                 |
 */
 
-const expression = /^(TRACKADDED|E[A-Z]+)::(.*)$/;
-const twoparamexpr = /^(.*)\|\|(.*)$/;
+const EVENT_PROTOCOL_VERSION = 1;
 
-const processWatcherMessage = message => {
+const fileName = filePath => (filePath ? path.basename(filePath) : "");
+
+const processWatcherEvent = event => {
   try {
-    if (message === null || message === undefined || !!!message) {
-      log.warn("bad recv <" + _message + ">");
+    if (!event || typeof event.type !== "string") {
+      log.warn("bad recv <" + JSON.stringify(event) + ">");
       return;
     }
-
-    let _message = message.trim();
-    let matches = expression.exec(_message);
-    log.info("MsgRecv <" + _message + ">");
-
-    if (!!!matches || (!!matches && matches.length !== 3)) {
-      log.warn("bad recv <" + _message + ">");
-      return;
+    if (event.version !== EVENT_PROTOCOL_VERSION) {
+      log.warn("unknown event protocol version <" + event.version + ">");
     }
-    let messageType = matches[1];
-    let messagePayload = matches[2];
+    log.info("EventRecv <" + JSON.stringify(event) + ">");
 
-    switch (messageType) {
-      case "TRACKADDED":
-        log.info("TRACKADDED recv with payload <" + messagePayload + ">");
-        let trackdata = twoparamexpr.exec(messagePayload);
-        if (trackdata && trackdata.length === 3) {
-          newTracksAdded.push(trackdata[1] + " - " + trackdata[2]);
-        } else {
-          log.warn("TRACKADDED bad recv <" + _message + ">");
-        }
+    const details = event.details || {};
+    const errorMessage = event.error ? event.error.message : "";
+
+    switch (event.type) {
+      case "response":
+      case "watching":
+      case "http_listening":
+      case "import_queue":
+      case "album_added":
+      case "track_updated":
+      case "track_removed":
+      case "config_reloaded":
+      case "cover_cache_error":
+      case "analysis_error":
         break;
-      case "EMISSINGTAG":
-        log.info("EMISSINGTAG recv with payload <" + messagePayload + ">");
-        let tagdata = twoparamexpr.exec(messagePayload);
-        if (tagdata && tagdata.length === 3) {
-          notifier.notify({
-            title: "Track is missing tag.",
-            message:
-              "Track " + tagdata[1] + " is missing the " + tagdata[2] + " tag.",
-            appID: appId
-          });
-        } else {
-          log.info("EMISSINGTAG bad recv <" + _message + ">");
-        }
+      case "track_added":
+        newTracksAdded.push(details.artist + " - " + details.title);
         break;
-      case "ETRACKMOVE":
-        log.info("ETRACKMOVE recv");
+      case "missing_tag":
+        notifier.notify({
+          title: "Track is missing tag.",
+          message:
+            "Track " + fileName(event.path) + " is missing the " + details.tag + " tag.",
+          appID: appId
+        });
+        break;
+      case "track_move_error":
         notifier.notify({
           title: "Error when moving track",
-          message: "Error occurred when moving " + messagePayload,
+          message: "Error occurred when moving " + event.path,
           appID: appId
         });
         break;
-      case "ETRACK":
-        log.info("ETRACK recv");
+      case "track_error":
         notifier.notify({
           title: "Track error occurred.",
-          message: messagePayload,
+          message: errorMessage,
           appID: appId
         });
         break;
-      case "ECREATEDIRECTORY":
-        log.info("ECREATEDIRECTORY recv");
+      case "create_directory_error":
         notifier.notify({
           title: "Unable to create folder.",
-          message: "Unable to create folder " + messagePayload,
+          message: "Unable to create folder " + event.path,
+          appID: appId
+        });
+        break;
+      case "corrupt":
+        notifier.notify({
+          title: "Corrupt track found.",
+          message: fileName(event.path) + " is corrupt: " + details.defect + ".",
+          appID: appId
+        });
+        break;
+      case "rejected":
+        notifier.notify({
+          title: "Track rejected.",
+          message: fileName(event.path) + " is not accepted from " + details.source + ".",
+          appID: appId
+        });
+        break;
+      case "album_rejected":
+        notifier.notify({
+          title: "Album rejected.",
+          message: fileName(event.path) + " was not added: " + details.reason + ".",
           appID: appId
         });
         break;
-      case "ENONTRACK":
-        log.info("ENONTRACK recv");
+      case "non_track":
         notifier.notify({
           title: "Non-track file found.",
-          message: messagePayload + " is not a track.",
+          message: fileName(event.path) + " is not a track.",
           appID: appId
         });
         break;
-      case "EWATCHERDIED":
-        log.info("EWATCHERDIED recv");
+      case "watcher_died":
         notifier.notify({
           title: "Track watcher restarting.",
           message: "Restarting the track watcher due to an error.",
           appID: appId
         });
         break;
-      case "EWATCHERRESTART":
-        log.info("EWATCHERRESTART recv");
+      case "watcher_restart":
         notifier.notify({
           title: "Track watcher restarting.",
           message: "Restarting the track watcher.",
           appID: appId
         });
         break;
-      case "EWATCHERNOACCESS":
-        log.info("EWATCHERNOACCESS recv");
+      case "watcher_no_access":
         notifier.notify({
           title: "Can not access the track library folder.",
           message:
@@ -166,50 +182,51 @@ const processWatcherMessage = message => {
           runningWatcher.quit();
         }
         break;
-      case "EWATCHER":
-        log.info("EWATCHER recv");
+      case "watcher_error":
         notifier.notify({
           title: "Track watcher error.",
           message: "Unknown track watcher error occurred.",
           appID: appId
         });
         break;
-      case "ECONFIGINVALID":
-        log.info("ECONFIGINVALID recv");
+      case "config_invalid":
         notifier.notify({
           title: "Configuration error.",
           message:
-            "The configuration file is invalid. Fix it then restart the track watcher.",
+            "The configuration file is invalid. The track watcher will use it once it is fixed.",
           appID: appId
         });
-        if (runningWatcher) {
-          runningWatcher.quit();
-        }
         break;
-      case "ECONFIGIO":
-        log.info("ECONFIGIO recv");
+      case "config_io_error":
         notifier.notify({
           title: "Configuration error.",
           message:
-            "Can not write to configuration path " + messagePayload + ".",
+            "Can not write to configuration path " + event.path + ".",
           appID: appId
         });
         if (runningWatcher) {
           runningWatcher.quit();
         }
         break;
+      case "config_no_home":
+        notifier.notify({
+          title: "Configuration error.",
+          message: "Can not find the home directory to store the configuration in.",
+          appID: appId
+        });
+        break;
       default:
-        log.warn("EUNKNOWN recv");
+        log.warn("unknown event recv <" + event.type + ">");
 
         notifier.notify({
           title: "Error occurred.",
-          message: messagePayload + ": " + messageType,
+          message: (errorMessage || event.path || "") + ": " + event.type,
           appID: appId
         });
         break;
     }
   } catch(err) {
-    log.warn("bad err recv <" + _message + ">");
+    log.warn("bad err recv <" + JSON.stringify(event) + ">");
   }
 };
 
@@ -241,8 +258,8 @@ const startNewTracksNotifier = () => {
 const restartWatcher = () => {
   log.info("Starting watcher...");
   runningWatcher = watcher(
-    chunk => {
-      processWatcherMessage(chunk.toString("utf8"));
+    event => {
+      processWatcherEvent(event);
     },
     chunk => {
       if (!watcherShouldQuit) {
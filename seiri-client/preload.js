@@ -10,6 +10,8 @@ const remote = require("electron").remote;
 contextBridge.exposeInMainWorld('seiri', {
     queryTracks: seiri.queryTracks,
     refreshTracks: seiri.refreshTracks,
+    getCoverThumbnail: seiri.getCoverThumbnail,
+    getLibraries: seiri.getLibraries,
     openTrackFolder: (track) => {
         if (process.platform == 'win32') {
             child.spawn("explorer", [path.dirname(track.filePath)], { detached: true });
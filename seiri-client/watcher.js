@@ -1,16 +1,28 @@
 const child_proc = require("child_process");
 const isDev = require('electron-is-dev');
 const path = require("path");
+const readline = require("readline");
 
-const launch_watcher = (onStdErr, onQuit) => {
+// Events are written to stdout as JSON lines. See seiri-watcher/events.md.
+const launch_watcher = (onEvent, onQuit) => {
   let watcher = child_proc.spawn((isDev
     ? "seiri-watcher"
     : `${path.join(__dirname, "../app.asar.unpacked/seiri-watcher")}`), [], {
     stdio: "pipe"
   });
   watcher.addListener("close", onQuit);
-  watcher.stderr.addListener("data", onStdErr);
-  watcher.stdout.pipe(process.stdout);
+  readline
+    .createInterface({ input: watcher.stdout, crlfDelay: Infinity })
+    .on("line", line => {
+      let event;
+      try {
+        event = JSON.parse(line);
+      } catch (e) {
+        process.stdout.write(line + "\n");
+        return;
+      }
+      onEvent(event);
+    });
   watcher.stderr.pipe(process.stdout);
   return {
     quit: () => watcher.stdin.write("exit\r\n"),
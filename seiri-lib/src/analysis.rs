@@ -0,0 +1,60 @@
+//! Decode-based checks of imported tracks: spectral analysis of lossless
+//! tracks, to find lossless files that were transcoded from a lossy source,
+//! and verification of the audio stream, to find corrupt files.
+
+use crate::database;
+use crate::database::Connection;
+use crate::error::{Error, Result};
+use katatsuki::analysis::spectral_cutoff;
+use katatsuki::verify::verify_stream;
+use katatsuki::{Track, TrackFileType};
+
+/// Whether the track is in one of the lossless formats the `!fake` bang applies to.
+fn is_analyzed(file_type: &TrackFileType) -> bool {
+    matches!(
+        file_type,
+        TrackFileType::FLAC4
+            | TrackFileType::FLAC8
+            | TrackFileType::FLAC16
+            | TrackFileType::FLAC24
+            | TrackFileType::FLAC32
+            | TrackFileType::FLAC
+            | TrackFileType::ALAC16
+            | TrackFileType::ALAC20
+            | TrackFileType::ALAC24
+            | TrackFileType::ALAC32
+            | TrackFileType::ALAC
+            | TrackFileType::AIFF4
+            | TrackFileType::AIFF8
+            | TrackFileType::AIFF16
+            | TrackFileType::AIFF24
+            | TrackFileType::AIFF32
+            | TrackFileType::AIFF
+    )
+}
+
+/// Estimates the spectral cutoff of the track, and records it in the
+/// database. The track must already be added to the database.
+///
+/// Returns the cutoff in Hz, or `None` if the track is lossy or silent.
+pub fn update_spectral_cutoff(track: &Track, conn: &Connection) -> Result<Option<i32>> {
+    if !is_analyzed(&track.file_type) {
+        return Ok(None);
+    }
+    let cutoff = match spectral_cutoff(&track.file_path) {
+        Ok(cutoff) => cutoff,
+        Err(_) => return Err(Error::UnableToAnalyze(track.file_path.to_owned())),
+    };
+    database::set_spectral_cutoff(track, cutoff, conn);
+    Ok(cutoff)
+}
+
+/// Decodes the audio stream of the track in full, and returns
+/// `Error::CorruptFile` if it is truncated or damaged.
+pub fn verify_track(track: &Track) -> Result<()> {
+    match verify_stream(&track.file_path) {
+        Ok(None) => Ok(()),
+        Ok(Some(defect)) => Err(Error::CorruptFile(track.file_path.to_owned(), defect.to_string())),
+        Err(_) => Err(Error::FileIOError(track.file_path.to_owned())),
+    }
+}
@@ -1,6 +1,6 @@
 extern crate itertools;
 
-use katatsuki::TrackFileType;
+use katatsuki::{BitrateMode, CodecProfile, TrackFileType};
 use crate::error::{Result};
 use super::lexer::{lex_query};
 use super::parser::{parse_token_stream};
@@ -20,17 +20,29 @@ pub enum Bang {
     Artist(String),
     ArtistExact(String),
     Source(String),
+    Label(String),
     Format(TrackFileType),
     BitrateLessThan(i32), 
     BitrateGreaterThan(i32),
+    BitrateMode(BitrateMode),
+    CodecProfile(CodecProfile),
+    Encoder(String),
+    EncoderExact(String),
+    Channels(i32),
     CoverArtWidthLessThan(i32),
     CoverArtWidthGreaterThan(i32),
     CoverArtHeightLessThan(i32),
     CoverArtHeightGreaterThan(i32),
+    CoverArtSizeLessThan(i32),
+    CoverArtSizeGreaterThan(i32),
+    CoverArtFormat(String),
     DurationLessThan(i64),
     DurationGreaterThan(i64),
     HasCoverArt(bool),
+    HasNonSquareCoverArt(bool),
+    HasInconsistentCoverArt(bool),
     HasMusicbrainzId(bool),
+    IsSuspectedTranscode(bool),
     HasDuplicates(bool),
     LogicalAnd(Box<Bang>, Box<Bang>),
     LogicalOr(Box<Bang>, Box<Bang>),
@@ -2,7 +2,7 @@ use std::slice::Iter;
 use std::str::FromStr;
 use super::lexer::Token;
 use super::bangs::Bang;
-use katatsuki::TrackFileType;
+use katatsuki::{BitrateMode, CodecProfile, TrackFileType};
 use crate::error::{Error, Result};
 use humantime::Duration;
 use chrono::NaiveDate;
@@ -26,17 +26,29 @@ impl BangIdentifier for str {
             "ar" => BangType::Artist,
             "AR" => BangType::ArtistExact,
             "s" => BangType::Source,
+            "lbl" => BangType::Label,
             "f" => BangType::Format,
             "dlt" => BangType::DurationLessThan,
             "dgt" => BangType::DurationGreaterThan,
             "brlt" => BangType::BitrateLessThan,
             "brgt" => BangType::BitrateGreaterThan,
+            "brm" => BangType::BitrateMode,
+            "prof" => BangType::CodecProfile,
+            "enc" => BangType::Encoder,
+            "ENC" => BangType::EncoderExact,
+            "chn" => BangType::Channels,
             "cwlt" => BangType::CoverArtWidthLessThan,
             "cwgt" => BangType::CoverArtWidthGreaterThan,
             "chlt" => BangType::CoverArtHeightLessThan,
             "chgt" => BangType::CoverArtHeightGreaterThan,
+            "cslt" => BangType::CoverArtSizeLessThan,
+            "csgt" => BangType::CoverArtSizeGreaterThan,
+            "cf" => BangType::CoverArtFormat,
             "c" => BangType::HasCoverArt,
+            "cns" => BangType::HasNonSquareCoverArt,
+            "cdiff" => BangType::HasInconsistentCoverArt,
             "mb" => BangType::HasMusicbrainzId,
+            "fake" => BangType::IsSuspectedTranscode,
             "dup" => BangType::HasDuplicates,
             "ubf" => BangType::UpdatedBefore,
             "uaf" => BangType::UpdatedAfter,
@@ -58,17 +70,29 @@ enum BangType {
     Artist,
     ArtistExact,
     Source,
+    Label,
     Format,
     BitrateLessThan,
     BitrateGreaterThan,
+    BitrateMode,
+    CodecProfile,
+    Encoder,
+    EncoderExact,
+    Channels,
     DurationLessThan,
     DurationGreaterThan,
     CoverArtWidthLessThan,
     CoverArtWidthGreaterThan,
     CoverArtHeightLessThan,
     CoverArtHeightGreaterThan,
+    CoverArtSizeLessThan,
+    CoverArtSizeGreaterThan,
+    CoverArtFormat,
     HasCoverArt,
+    HasNonSquareCoverArt,
+    HasInconsistentCoverArt,
     HasMusicbrainzId,
+    IsSuspectedTranscode,
     HasDuplicates,
     UpdatedBefore,
     UpdatedAfter,
@@ -200,6 +224,10 @@ pub fn parse_token_stream(tokens: &mut Iter<Token>) -> Result<Bang> {
                 |search: String| Bang::Source(search),
                 extract_argument(tokens),
             ),
+            BangType::Label => parse_bang(
+                |label: String| Bang::Label(label),
+                extract_argument(tokens),
+            ),
             BangType::Format => parse_bang(
                 |format: TrackFileType| Bang::Format(format),
                 extract_argument(tokens),
@@ -220,6 +248,26 @@ pub fn parse_token_stream(tokens: &mut Iter<Token>) -> Result<Bang> {
                 |bitrate: i32| Bang::BitrateGreaterThan(bitrate),
                 extract_argument(tokens),
             ),
+            BangType::BitrateMode => parse_bang(
+                |mode: BitrateMode| Bang::BitrateMode(mode),
+                extract_argument(tokens),
+            ),
+            BangType::CodecProfile => parse_bang(
+                |profile: CodecProfile| Bang::CodecProfile(profile),
+                extract_argument(tokens),
+            ),
+            BangType::Encoder => parse_bang(
+                |encoder: String| Bang::Encoder(encoder),
+                extract_argument(tokens),
+            ),
+            BangType::EncoderExact => parse_bang(
+                |encoder: String| Bang::EncoderExact(encoder),
+                extract_argument(tokens),
+            ),
+            BangType::Channels => parse_bang(
+                |channels: i32| Bang::Channels(channels),
+                extract_argument(tokens),
+            ),
             BangType::CoverArtWidthLessThan => parse_bang(
                 |cw: i32| Bang::CoverArtWidthLessThan(cw),
                 extract_argument(tokens),
@@ -236,13 +284,37 @@ pub fn parse_token_stream(tokens: &mut Iter<Token>) -> Result<Bang> {
                 |ch: i32| Bang::CoverArtHeightGreaterThan(ch),
                 extract_argument(tokens),
             ),
+            BangType::CoverArtSizeLessThan => parse_bang(
+                |cs: i32| Bang::CoverArtSizeLessThan(cs),
+                extract_argument(tokens),
+            ),
+            BangType::CoverArtSizeGreaterThan => parse_bang(
+                |cs: i32| Bang::CoverArtSizeGreaterThan(cs),
+                extract_argument(tokens),
+            ),
+            BangType::CoverArtFormat => parse_bang(
+                |cf: String| Bang::CoverArtFormat(cf),
+                extract_argument(tokens),
+            ),
             BangType::HasCoverArt => {
                 parse_bang(|c: bool| Bang::HasCoverArt(c), extract_argument(tokens))
             }
+            BangType::HasNonSquareCoverArt => parse_bang(
+                |cns: bool| Bang::HasNonSquareCoverArt(cns),
+                extract_argument(tokens),
+            ),
+            BangType::HasInconsistentCoverArt => parse_bang(
+                |cdiff: bool| Bang::HasInconsistentCoverArt(cdiff),
+                extract_argument(tokens),
+            ),
             BangType::HasMusicbrainzId => parse_bang(
                 |mb: bool| Bang::HasMusicbrainzId(mb),
                 extract_argument(tokens),
             ),
+            BangType::IsSuspectedTranscode => parse_bang(
+                |fake: bool| Bang::IsSuspectedTranscode(fake),
+                extract_argument(tokens),
+            ),
             BangType::HasDuplicates => parse_bang(
                 |dup: bool| Bang::HasDuplicates(dup),
                 extract_argument(tokens),
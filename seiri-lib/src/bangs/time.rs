@@ -8,12 +8,12 @@ const NS_PER_TICK: i64 = 100;
 const SEC_PER_MS: i64 = 1000;
 use humantime::Duration;
 
-pub fn ticks_to_ms(ticks: i64) -> i32 {
-    (ticks / TICKS_PER_MS) as i32
+pub fn ticks_to_ms(ticks: i64) -> i64 {
+    ticks / TICKS_PER_MS
 }
 
-pub fn ms_to_ticks(secs: i32) -> i64 {
-    secs as i64 * TICKS_PER_MS
+pub fn ms_to_ticks(ms: i64) -> i64 {
+    ms * TICKS_PER_MS
 }
 
 pub trait TickRepr {
@@ -1,46 +1,543 @@
 use dirs::home_dir;
+use crate::database::get_database_location;
 use crate::error::{ConfigErrorType, Error, Result};
 use crate::paths::*;
 use serde_derive::{Serialize, Deserialize};
+use std::collections::BTreeMap;
 use std::default::Default;
+use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use toml;
 
+/// A tag that can be required of new tracks before they are added to the library.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RequiredTag {
+    Title,
+    Artist,
+    Album,
+    AlbumArtists,
+    Year,
+    TrackNumber,
+    MusicBrainzTrackId,
+}
+
+impl RequiredTag {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RequiredTag::Title => "Title",
+            RequiredTag::Artist => "Artist",
+            RequiredTag::Album => "Album",
+            RequiredTag::AlbumArtists => "AlbumArtists",
+            RequiredTag::Year => "Year",
+            RequiredTag::TrackNumber => "TrackNumber",
+            RequiredTag::MusicBrainzTrackId => "MusicBrainzTrackId",
+        }
+    }
+
+    /// Gets the tag with the given name, ignoring case.
+    pub fn from_name(name: &str) -> Option<RequiredTag> {
+        [
+            RequiredTag::Title,
+            RequiredTag::Artist,
+            RequiredTag::Album,
+            RequiredTag::AlbumArtists,
+            RequiredTag::Year,
+            RequiredTag::TrackNumber,
+            RequiredTag::MusicBrainzTrackId,
+        ]
+        .iter()
+        .find(|tag| tag.name().eq_ignore_ascii_case(name))
+        .copied()
+    }
+}
+
+/// How the watcher finds changes to the folders of the libraries.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    /// Polls folders on network filesystems, and uses the events of the
+    /// operating system for other folders.
+    #[default]
+    Auto,
+    /// Uses the events of the operating system, such as inotify on Linux.
+    Events,
+    /// Walks the folders every `poll_interval` seconds, comparing the size and
+    /// modification time of each file with the last walk.
+    Poll,
+}
+
+impl WatchMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatchMode::Auto => "auto",
+            WatchMode::Events => "events",
+            WatchMode::Poll => "poll",
+        }
+    }
+
+    /// Gets the mode with the given name, ignoring case.
+    pub fn from_name(name: &str) -> Option<WatchMode> {
+        [WatchMode::Auto, WatchMode::Events, WatchMode::Poll]
+            .iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+            .copied()
+    }
+}
+
+fn default_import_concurrency() -> usize {
+    4
+}
+
+fn default_import_queue_size() -> usize {
+    1000
+}
+
+fn default_settle_time() -> u64 {
+    10
+}
+
+fn default_album_quiet_time() -> u64 {
+    30
+}
+
+fn default_poll_interval() -> u64 {
+    30
+}
+
+fn default_required_tags() -> Vec<RequiredTag> {
+    vec![
+        RequiredTag::Title,
+        RequiredTag::Artist,
+        RequiredTag::Album,
+        RequiredTag::AlbumArtists,
+    ]
+}
+
+/// The tags a new track must have to be added to the library, and how
+/// missing tags are filled in before they are checked.
+#[derive(Debug, Clone)]
+pub struct TagPolicy {
+    pub required_tags: Vec<RequiredTag>,
+    /// Whether tracks without an AlbumArtists tag use their Artist tag instead.
+    pub album_artist_fallback: bool,
+}
+
+impl Default for TagPolicy {
+    fn default() -> TagPolicy {
+        TagPolicy {
+            required_tags: default_required_tags(),
+            album_artist_fallback: false,
+        }
+    }
+}
+
+/// The name of the library in `Config::music_folder`.
+pub const DEFAULT_LIBRARY: &str = "default";
+
+/// A library other than the default, keyed on its name in `Config::libraries`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LibraryConfig {
+    pub music_folder: String,
+    /// The folder new tracks are added from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_add_folder: Option<String>,
+    /// The folder files that are not tracks are moved into.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_added_folder: Option<String>,
+    /// The path of the database of the library.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database_path: Option<String>,
+}
+
+/// A library of tracks, with its own folders and its own database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Library {
+    pub name: String,
+    pub music_folder: String,
+    pub auto_add_folder: Option<String>,
+    pub not_added_folder: Option<String>,
+    pub database_path: Option<String>,
+}
+
+impl Library {
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_LIBRARY
+    }
+}
+
+/// Import rules for tracks added from a subfolder of the Automatically Add
+/// to Library folder, keyed on the name of the subfolder in `Config::sources`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct SourceRule {
+    /// Overrides the tags a new track must have.
+    pub required_tags: Option<Vec<RequiredTag>>,
+    /// Overrides whether tracks without an AlbumArtists tag use their Artist tag instead.
+    pub album_artist_fallback: Option<bool>,
+    /// The path of tracks relative to the library, without an extension.
+    /// See `paths::render_path_template` for the available placeholders.
+    pub path_template: Option<String>,
+    /// Extra labels stored with every track from the source.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Whether tracks in lossy formats are rejected.
+    #[serde(default)]
+    pub reject_lossy: bool,
+}
+
+/// The rules that apply to a track imported from a given source.
+#[derive(Debug, Clone)]
+pub struct ImportPolicy {
+    pub source: String,
+    pub tags: TagPolicy,
+    pub path_template: Option<String>,
+    pub labels: Vec<String>,
+    pub reject_lossy: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
     pub music_folder: String,
+    /// The folder new tracks are added from. Defaults to "Automatically Add to Library",
+    /// next to the music folder. Must be an absolute path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_add_folder: Option<String>,
+    /// The folder files that are not tracks are moved into, relative to the
+    /// Automatically Add folder. Defaults to `.notadded`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_added_folder: Option<String>,
+    /// The path of the database, relative to the application data path, or
+    /// `:memory:` to keep the database in memory. Defaults to tracks.db.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database_path: Option<String>,
+    /// Whether to decode new tracks in full before adding them to the library,
+    /// to catch corrupt files. Requires the `analysis` feature.
+    #[serde(default)]
+    pub verify_imports: bool,
+    /// The tags a new track must have to be added to the library.
+    #[serde(default = "default_required_tags")]
+    pub required_tags: Vec<RequiredTag>,
+    /// Whether tracks without an AlbumArtists tag use their Artist tag instead.
+    #[serde(default)]
+    pub album_artist_fallback: bool,
+    /// The port of the HTTP API of the watcher on localhost. The API is
+    /// disabled if no port is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_port: Option<u16>,
+    /// The number of tracks that are added to the library at once.
+    #[serde(default = "default_import_concurrency")]
+    pub import_concurrency: usize,
+    /// The number of tracks that can wait to be added to the library. Tracks
+    /// beyond this stay in the Automatically Add folder until the queue drains.
+    #[serde(default = "default_import_queue_size")]
+    pub import_queue_size: usize,
+    /// The number of seconds the size and modification time of a new file must
+    /// stay the same before it is added, so that files that are still being
+    /// written are not added.
+    #[serde(default = "default_settle_time")]
+    pub settle_time: u64,
+    /// Whether the music folders of the libraries are watched, so that tracks
+    /// that other programs retag, move or delete are updated in the database.
+    #[serde(default)]
+    pub watch_library: bool,
+    /// How changes to the folders are found. Network filesystems do not report
+    /// changes, so their folders are polled instead by default.
+    #[serde(default)]
+    pub watch_mode: WatchMode,
+    /// The number of seconds between walks of folders that are polled.
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+    /// Whether the tracks in each folder of the Automatically Add folder are
+    /// added together as an album, once the folder has gone quiet. Tracks
+    /// directly in the Automatically Add folder are still added one by one.
+    #[serde(default)]
+    pub album_staging: bool,
+    /// The number of seconds an album folder must go without changes before
+    /// its tracks are added.
+    #[serde(default = "default_album_quiet_time")]
+    pub album_quiet_time: u64,
+    /// Libraries other than the default, by name. Tables must be serialized
+    /// after plain values, so this and `sources` must remain the last fields.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub libraries: BTreeMap<String, LibraryConfig>,
+    /// Import rules by source.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sources: BTreeMap<String, SourceRule>,
 }
 
-impl Default for Config {
-    fn default() -> Config {
-        let mut home_dir = home_dir().unwrap();
-        home_dir.push("Music");
-        home_dir.push("seiri");
-        Config {
-            music_folder: home_dir.to_str().unwrap().to_owned(),
+impl Config {
+    /// Gets the library in `music_folder`.
+    pub fn default_library(&self) -> Library {
+        Library {
+            name: DEFAULT_LIBRARY.to_owned(),
+            music_folder: self.music_folder.clone(),
+            auto_add_folder: self.auto_add_folder.clone(),
+            not_added_folder: self.not_added_folder.clone(),
+            database_path: self.database_path.clone(),
+        }
+    }
+
+    /// Gets every library, starting with the default library.
+    pub fn libraries(&self) -> Vec<Library> {
+        let mut libraries = vec![self.default_library()];
+        libraries.extend(self.libraries.iter().map(|(name, library)| Library {
+            name: name.to_owned(),
+            music_folder: library.music_folder.clone(),
+            auto_add_folder: library.auto_add_folder.clone(),
+            not_added_folder: library.not_added_folder.clone(),
+            database_path: library.database_path.clone(),
+        }));
+        libraries
+    }
+
+    /// Gets the library with the given name.
+    pub fn library(&self, name: &str) -> Option<Library> {
+        self.libraries().into_iter().find(|library| library.name == name)
+    }
+
+    /// Checks that every library has a valid name and valid folders, and
+    /// that no two libraries share a folder or a database.
+    pub fn validate(&self) -> Result<()> {
+        for (name, value) in &[
+            ("import_concurrency", self.import_concurrency),
+            ("import_queue_size", self.import_queue_size),
+        ] {
+            if *value == 0 {
+                return Err(Error::ConfigError(ConfigErrorType::InvalidSetting((*name).to_owned())));
+            }
+        }
+        let libraries = self.libraries();
+        for (i, library) in libraries.iter().enumerate() {
+            let invalid = || Err(Error::ConfigError(ConfigErrorType::InvalidLibrary(library.name.clone())));
+            if i > 0 && (library.is_default() || !is_valid_folder_name(&library.name)) {
+                return invalid();
+            }
+            if !are_valid_folders(library) {
+                return invalid();
+            }
+            let folders = get_library_folders(library);
+            let database = get_database_location(library);
+            for other in &libraries[..i] {
+                if database == get_database_location(other) {
+                    return invalid();
+                }
+                let other = get_library_folders(other);
+                if folders.music_folder == other.music_folder || folders.auto_add_folder == other.auto_add_folder {
+                    return invalid();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn tag_policy(&self) -> TagPolicy {
+        TagPolicy {
+            required_tags: self.required_tags.clone(),
+            album_artist_fallback: self.album_artist_fallback,
+        }
+    }
+
+    /// Gets the import policy for tracks from the given source, applying
+    /// the rule for the source over the global settings.
+    pub fn import_policy(&self, source: &str) -> ImportPolicy {
+        let tags = self.tag_policy();
+        match self.sources.get(source) {
+            Some(rule) => ImportPolicy {
+                source: source.to_owned(),
+                tags: TagPolicy {
+                    required_tags: rule.required_tags.clone().unwrap_or(tags.required_tags),
+                    album_artist_fallback: rule.album_artist_fallback.unwrap_or(tags.album_artist_fallback),
+                },
+                path_template: rule.path_template.clone(),
+                labels: rule.labels.clone(),
+                reject_lossy: rule.reject_lossy,
+            },
+            None => ImportPolicy {
+                source: source.to_owned(),
+                tags,
+                path_template: None,
+                labels: Vec::new(),
+                reject_lossy: false,
+            },
         }
     }
 }
 
-fn write_default_config(path: &Path) -> Option<()> {
-    let default_config = toml::to_string(&Config::default()).unwrap();
-    fs::write(path.to_string_lossy().into_owned(), default_config).ok()
+/// The settings of `Config` that can be overridden with environment variables
+/// and the command line. The environment variable of a setting is its name in
+/// upper case, prefixed with `SEIRI_`, such as `SEIRI_MUSIC_FOLDER`.
+pub const OVERRIDABLE_SETTINGS: &[&str] = &[
+    "music_folder",
+    "auto_add_folder",
+    "not_added_folder",
+    "database_path",
+    "verify_imports",
+    "required_tags",
+    "album_artist_fallback",
+    "http_port",
+    "import_concurrency",
+    "import_queue_size",
+    "settle_time",
+    "watch_library",
+    "watch_mode",
+    "poll_interval",
+    "album_staging",
+    "album_quiet_time",
+];
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "1" => Some(true),
+        "false" | "no" | "0" => Some(false),
+        _ => None,
+    }
 }
 
-pub fn get_config() -> Result<Config> {
+impl Config {
+    /// Sets the setting with the given name from its string value. Required
+    /// tags are separated by commas.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let invalid = || Error::ConfigError(ConfigErrorType::InvalidSetting(name.to_owned()));
+        match name {
+            "music_folder" => self.music_folder = value.to_owned(),
+            "auto_add_folder" => self.auto_add_folder = Some(value.to_owned()),
+            "not_added_folder" => self.not_added_folder = Some(value.to_owned()),
+            "database_path" => self.database_path = Some(value.to_owned()),
+            "verify_imports" => self.verify_imports = parse_bool(value).ok_or_else(invalid)?,
+            "album_artist_fallback" => self.album_artist_fallback = parse_bool(value).ok_or_else(invalid)?,
+            "http_port" => self.http_port = Some(value.parse().map_err(|_| invalid())?),
+            "import_concurrency" => self.import_concurrency = value.parse().map_err(|_| invalid())?,
+            "import_queue_size" => self.import_queue_size = value.parse().map_err(|_| invalid())?,
+            "settle_time" => self.settle_time = value.parse().map_err(|_| invalid())?,
+            "watch_library" => self.watch_library = parse_bool(value).ok_or_else(invalid)?,
+            "watch_mode" => self.watch_mode = WatchMode::from_name(value).ok_or_else(invalid)?,
+            "poll_interval" => self.poll_interval = value.parse().map_err(|_| invalid())?,
+            "album_staging" => self.album_staging = parse_bool(value).ok_or_else(invalid)?,
+            "album_quiet_time" => self.album_quiet_time = value.parse().map_err(|_| invalid())?,
+            "required_tags" => {
+                self.required_tags = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(RequiredTag::from_name)
+                    .collect::<Option<Vec<RequiredTag>>>()
+                    .ok_or_else(invalid)?
+            }
+            _ => return Err(invalid()),
+        }
+        Ok(())
+    }
+
+    /// Overrides settings with the `SEIRI_` environment variables that are set.
+    pub fn apply_env_overrides(&mut self) -> Result<()> {
+        for name in OVERRIDABLE_SETTINGS {
+            if let Ok(value) = env::var(format!("SEIRI_{}", name.to_uppercase())) {
+                self.set(name, &value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Gets the default configuration, with the library in the Music folder of the user.
+pub fn default_config() -> Result<Config> {
+    let mut home_dir = home_dir().ok_or(Error::ConfigError(ConfigErrorType::NoHomeDirectory))?;
+    home_dir.push("Music");
+    home_dir.push("seiri");
+    Ok(Config {
+        music_folder: home_dir.to_string_lossy().into_owned(),
+        auto_add_folder: None,
+        not_added_folder: None,
+        database_path: None,
+        verify_imports: false,
+        required_tags: default_required_tags(),
+        album_artist_fallback: false,
+        http_port: None,
+        import_concurrency: default_import_concurrency(),
+        import_queue_size: default_import_queue_size(),
+        settle_time: default_settle_time(),
+        watch_library: false,
+        watch_mode: WatchMode::Auto,
+        poll_interval: default_poll_interval(),
+        album_staging: false,
+        album_quiet_time: default_album_quiet_time(),
+        libraries: BTreeMap::new(),
+        sources: BTreeMap::new(),
+    })
+}
+
+/// Checks that the Automatically Add folder is an absolute path that does not overlap
+/// the music folder, and that tracks moved into the not added folder are not picked up
+/// again from the Automatically Add folder.
+fn are_valid_folders(library: &Library) -> bool {
+    let folders = get_library_folders(library);
+    if library.auto_add_folder.as_ref().is_some_and(|folder| !Path::new(folder).is_absolute()) {
+        return false;
+    }
+    if folders.music_folder.starts_with(&folders.auto_add_folder)
+        || folders.auto_add_folder.starts_with(&folders.music_folder)
+        || folders.not_added_folder.starts_with(&folders.music_folder)
+    {
+        return false;
+    }
+    // Quarantined files are kept in a subfolder of the not added folder.
+    !folders.not_added_folder.starts_with(&folders.auto_add_folder)
+        || is_in_hidden_path(&folders.not_added_folder.join("quarantined"), &folders.auto_add_folder)
+}
+
+fn write_default_config(path: &Path) -> Result<()> {
+    let default_config = toml::to_string(&default_config()?).unwrap();
+    fs::write(path, default_config)
+        .map_err(|_| Error::ConfigError(ConfigErrorType::IOError(path.to_string_lossy().to_string())))
+}
+
+/// Gets the path of the configuration file, from the `SEIRI_CONFIG`
+/// environment variable, or config.toml in the application data path.
+pub fn get_config_path() -> Result<PathBuf> {
+    if let Some(config_path) = env::var_os("SEIRI_CONFIG") {
+        return Ok(PathBuf::from(config_path));
+    }
+    if find_appdata_path().is_none() {
+        return Err(Error::ConfigError(ConfigErrorType::NoHomeDirectory));
+    }
     let mut config_path = get_appdata_path();
     config_path.push("config.toml");
-    if !config_path.exists() && write_default_config(config_path.as_path()).is_none() {
-        return Err(Error::ConfigError(ConfigErrorType::IOError(
-            config_path.to_string_lossy().to_string(),
-        )));
+    Ok(config_path)
+}
+
+pub fn get_config() -> Result<Config> {
+    load_config(&get_config_path()?, &[])
+}
+
+/// Loads the configuration file at the given path, writing the default
+/// configuration if it does not exist. See `read_config`.
+pub fn load_config(config_path: &Path, overrides: &[(String, String)]) -> Result<Config> {
+    if !config_path.exists() {
+        write_default_config(config_path)?;
     }
+    read_config(config_path, overrides)
+}
 
-    // Should be safe to unwrap since
-    let config_string = fs::read_to_string(config_path).unwrap();
-    let config = toml::from_str(&config_string);
-    if let Ok(config) = config {
+/// Reads the configuration file at the given path, without writing the default
+/// configuration if it does not exist. Settings are overridden by environment
+/// variables, then by the given settings and values, before the configuration
+/// is validated.
+pub fn read_config(config_path: &Path, overrides: &[(String, String)]) -> Result<Config> {
+    let config_string = match fs::read_to_string(config_path) {
+        Ok(config_string) => config_string,
+        Err(_) => {
+            return Err(Error::ConfigError(ConfigErrorType::IOError(
+                config_path.to_string_lossy().to_string(),
+            )))
+        }
+    };
+    let config = toml::from_str::<Config>(&config_string);
+    if let Ok(mut config) = config {
+        config.apply_env_overrides()?;
+        for (name, value) in overrides {
+            config.set(name, value)?;
+        }
+        config.validate()?;
         Ok(config)
     } else {
         Err(Error::ConfigError(ConfigErrorType::Invalid))
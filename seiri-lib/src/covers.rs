@@ -0,0 +1,95 @@
+//! Thumbnail cache for embedded front cover art.
+//!
+//! Thumbnails are keyed by the SHA-1 hash of the embedded cover, so every
+//! track of an album that shares the same artwork shares a single thumbnail.
+
+use crate::database;
+use crate::database::Connection;
+use crate::error::{Error, Result};
+use crate::paths::get_cover_cache_path;
+use image::{DynamicImage, ImageFormat, ImageResult};
+use katatsuki::Track;
+use rand::{thread_rng, Rng};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The largest dimension of a cached thumbnail, in pixels.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Gets the cache key of the given cover image.
+pub fn get_cover_key(cover: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(cover))
+}
+
+/// Gets the path of the thumbnail with the given cover key.
+/// The thumbnail may not exist.
+pub fn get_thumbnail_path(cover_key: &str) -> PathBuf {
+    let mut thumbnail_path = get_cover_cache_path();
+    thumbnail_path.push(format!("{}.jpg", cover_key));
+    thumbnail_path
+}
+
+fn write_thumbnail(cover: &[u8], thumbnail_path: &Path) -> ImageResult<()> {
+    let thumbnail = image::load_from_memory(cover)?.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+
+    // Tracks of the same album are imported concurrently, so write to a
+    // temporary file first to never expose a partially written thumbnail.
+    let temp_path = thumbnail_path.with_extension(format!("{}.tmp", thread_rng().gen::<u32>()));
+    DynamicImage::ImageRgb8(thumbnail.to_rgb8()).save_with_format(&temp_path, ImageFormat::Jpeg)?;
+    if let Err(err) = fs::rename(&temp_path, thumbnail_path) {
+        fs::remove_file(&temp_path).unwrap_or(());
+        // Someone else cached the same cover in the meantime.
+        if !thumbnail_path.exists() {
+            return Err(err.into());
+        }
+    }
+    Ok(())
+}
+
+/// Extracts the front cover of the track, and writes its thumbnail to the
+/// cache if it is not already cached.
+///
+/// Returns the cover key, or `None` if the track has no front cover.
+pub fn cache_cover(track: &Track) -> Result<Option<String>> {
+    if !track.has_front_cover {
+        return Ok(None);
+    }
+
+    let cover = match track.front_cover() {
+        Ok(Some(cover)) => cover,
+        Ok(None) => return Ok(None),
+        Err(_) => return Err(Error::FileIOError(track.file_path.to_owned())),
+    };
+
+    let cover_key = get_cover_key(&cover);
+    let thumbnail_path = get_thumbnail_path(&cover_key);
+    if !thumbnail_path.exists() && write_thumbnail(&cover, &thumbnail_path).is_err() {
+        return Err(Error::UnableToCacheCover(track.file_path.to_owned()));
+    }
+    Ok(Some(cover_key))
+}
+
+/// Caches the front cover of the track, and records its cover key
+/// in the database. The track must already be added to the database.
+pub fn update_cover_cache(track: &Track, conn: &Connection) -> Result<Option<String>> {
+    let cover_key = cache_cover(track)?;
+    database::set_cover_key(track, cover_key.as_deref(), conn);
+    Ok(cover_key)
+}
+
+/// Gets the path to the cached thumbnail of the front cover of the track.
+///
+/// If the thumbnail has been removed from the cache, or the track was added
+/// before the cache existed, the thumbnail is regenerated.
+pub fn get_cover_thumbnail(track: &Track, conn: &Connection) -> Result<Option<PathBuf>> {
+    if let Ok(Some(cover_key)) = database::get_cover_key(track, conn) {
+        let thumbnail_path = get_thumbnail_path(&cover_key);
+        if thumbnail_path.exists() {
+            return Ok(Some(thumbnail_path));
+        }
+    }
+
+    let cover_key = update_cover_cache(track, conn)?;
+    Ok(cover_key.map(|cover_key| get_thumbnail_path(&cover_key)))
+}
@@ -3,21 +3,23 @@ extern crate rusqlite;
 use crate::bangs::{ms_to_ticks, ticks_to_ms, Bang};
 use r2d2::{CustomizeConnection, Pool};
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{Error, Result, NO_PARAMS, functions::FunctionFlags};
+use rusqlite::{Error, Result, Row, NO_PARAMS, OptionalExtension, functions::FunctionFlags};
 use rand::{thread_rng, Rng};
 use rand::distributions::Alphanumeric;
 use regex::Regex;
 use rusqlite::types::ToSql;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
 use katatsuki::Track;
-use katatsuki::TrackFileType;
+use katatsuki::{BitrateMode, CodecProfile, TrackFileType};
 use katatsuki::{ToPrimitive, FromPrimitive};
+use crate::config::{Library, DEFAULT_LIBRARY};
 use crate::paths::get_appdata_path;
 
 pub use rusqlite::Connection;
 
 pub type ConnectionPool = Pool<SqliteConnectionManager>;
+pub type PooledConnection = r2d2::PooledConnection<SqliteConnectionManager>;
 
 #[derive(Copy, Clone, Debug)]
 struct SeiriConnectionCustomizer;
@@ -30,20 +32,88 @@ impl CustomizeConnection<Connection, Error> for SeiriConnectionCustomizer {
     }
 }
 
-pub fn get_database_connection() -> Connection {
+/// The value of `database_path` that keeps the database of a library in memory.
+pub const IN_MEMORY_DATABASE: &str = ":memory:";
+
+/// Where the database of a library is kept.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DatabaseLocation {
+    File(PathBuf),
+    /// An in-memory database, shared by every connection to it in the process.
+    Memory(String),
+}
+
+/// Gets the path of the database of the library in the application data path.
+/// The default library is stored in tracks.db, and other libraries in tracks.<name>.db.
+fn get_database_path(library_name: &str) -> PathBuf {
     let mut database_path = get_appdata_path();
-    database_path.push("tracks.db");
-    let conn = Connection::open(database_path.as_path()).unwrap();
+    if library_name == DEFAULT_LIBRARY {
+        database_path.push("tracks.db");
+    } else {
+        database_path.push(format!("tracks.{}.db", library_name));
+    }
+    database_path
+}
+
+/// Gets where the database of the library is kept. A relative `database_path`
+/// is relative to the application data path.
+pub(crate) fn get_database_location(library: &Library) -> DatabaseLocation {
+    match library.database_path.as_deref() {
+        Some(IN_MEMORY_DATABASE) => DatabaseLocation::Memory(library.name.clone()),
+        Some(path) if Path::new(path).is_absolute() => DatabaseLocation::File(PathBuf::from(path)),
+        Some(path) => DatabaseLocation::File(get_appdata_path().join(path)),
+        None => DatabaseLocation::File(get_database_path(&library.name)),
+    }
+}
+
+/// Gets the URI of the shared in-memory database with the given name.
+fn get_in_memory_uri(name: &str) -> String {
+    let name: String = name.bytes().map(|b| format!("{:02x}", b)).collect();
+    format!("file:seiri-{}?mode=memory&cache=shared", name)
+}
+
+/// Opens the database of the default library.
+pub fn get_database_connection() -> Connection {
+    get_database_connection_at(&get_database_path(DEFAULT_LIBRARY))
+}
+
+pub fn get_library_connection(library: &Library) -> Connection {
+    match get_database_location(library) {
+        DatabaseLocation::File(path) => get_database_connection_at(&path),
+        DatabaseLocation::Memory(name) => get_in_memory_connection(&name),
+    }
+}
+
+/// Opens the database at the given path, creating it if it does not exist.
+pub fn get_database_connection_at(database_path: &Path) -> Connection {
+    let conn = Connection::open(database_path).unwrap();
     enable_wal_mode(&conn).unwrap();
     add_regexp_function(&conn).unwrap();
     create_database(&conn);
     conn
 }
 
+/// Opens the in-memory database with the given name. The database is shared by every
+/// connection to it, and lasts until the last connection to it is closed.
+pub fn get_in_memory_connection(name: &str) -> Connection {
+    get_database_connection_at(Path::new(&get_in_memory_uri(name)))
+}
+
+/// Opens a connection pool over the database of the default library.
 pub fn get_connection_pool() -> ConnectionPool {
-    let mut database_path = get_appdata_path();
-    database_path.push("tracks.db");
-    let manager = SqliteConnectionManager::file(&database_path);
+    get_connection_pool_at(&get_database_path(DEFAULT_LIBRARY))
+}
+
+pub fn get_library_connection_pool(library: &Library) -> ConnectionPool {
+    match get_database_location(library) {
+        DatabaseLocation::File(path) => get_connection_pool_at(&path),
+        DatabaseLocation::Memory(name) => get_in_memory_connection_pool(&name),
+    }
+}
+
+/// Opens a connection pool over the database at the given path.
+pub fn get_connection_pool_at(database_path: &Path) -> ConnectionPool {
+    let manager = SqliteConnectionManager::file(database_path);
     let pool = Pool::builder()
         .connection_customizer(Box::new(SeiriConnectionCustomizer))
         .build(manager)
@@ -51,6 +121,11 @@ pub fn get_connection_pool() -> ConnectionPool {
     pool
 }
 
+/// Opens a connection pool over the in-memory database with the given name.
+/// See `get_in_memory_connection`.
+pub fn get_in_memory_connection_pool(name: &str) -> ConnectionPool {
+    get_connection_pool_at(Path::new(&get_in_memory_uri(name)))
+}
 
 fn escape_regex_search(string: &str) -> String {
     string.replace('\\', r"\\")
@@ -115,10 +190,56 @@ pub fn create_database(conn: &Connection) {
         DiscNumber INTEGER,
         Duration INTEGER,
         FileType INTEGER,
-        Updated DATE
+        Updated DATE,
+        CoverKey TEXT,
+        FrontCoverMimeType TEXT,
+        FrontCoverSize INTEGER,
+        BitrateMode INTEGER,
+        CodecProfile INTEGER,
+        Encoder TEXT,
+        Channels INTEGER,
+        SpectralCutoff INTEGER,
+        SampleCount INTEGER,
+        Labels TEXT
     )",
         NO_PARAMS,
     ).unwrap();
+    migrate_database(conn);
+}
+
+/// Columns added to the tracks table after its initial schema, in the order
+/// they were introduced. These must also be kept at the end of the
+/// `CREATE TABLE` statement so that column indices are stable.
+const MIGRATED_COLUMNS: &[(&str, &str)] = &[
+    ("CoverKey", "TEXT"),
+    ("FrontCoverMimeType", "TEXT"),
+    ("FrontCoverSize", "INTEGER"),
+    ("BitrateMode", "INTEGER"),
+    ("CodecProfile", "INTEGER"),
+    ("Encoder", "TEXT"),
+    ("Channels", "INTEGER"),
+    ("SpectralCutoff", "INTEGER"),
+    ("SampleCount", "INTEGER"),
+    ("Labels", "TEXT"),
+];
+
+/// Adds any columns missing from a database created by an older version of seiri.
+fn migrate_database(conn: &Connection) {
+    let mut statement = conn.prepare("PRAGMA table_info(tracks)").unwrap();
+    let columns = statement
+        .query_map(NO_PARAMS, |row| row.get::<_, String>(1))
+        .unwrap()
+        .filter_map(|column| column.ok())
+        .collect::<Vec<String>>();
+    for (column, column_type) in MIGRATED_COLUMNS {
+        if !columns.iter().any(|c| c == column) {
+            // Another connection in the pool may have won the race to migrate.
+            conn.execute(
+                &format!("ALTER TABLE tracks ADD COLUMN {} {}", column, column_type),
+                NO_PARAMS,
+            ).ok();
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -145,20 +266,19 @@ pub fn query_tracks(
         )
     };
 
-    if let Some(limit) = limit {
-        query.push_str(&format!(" LIMIT {}", limit));
+    query.push_str(" ORDER BY CASE WHEN AlbumArtists = 'Various Artists' THEN 1 END, AlbumArtists,Album,TrackNumber");
+
+    // SQLite only accepts an offset after a limit, and a negative limit has no upper bound.
+    if limit.is_some() || offset.is_some() {
+        query.push_str(&format!(" LIMIT {}", limit.unwrap_or(-1)));
     }
 
     if let Some(offset) = offset {
         query.push_str(&format!(" OFFSET {}", offset));
     }
 
-    query.push_str(" ORDER BY CASE WHEN AlbumArtists = 'Various Artists' THEN 1 END, AlbumArtists,Album,TrackNumber");
-
     let mut tracks = Vec::<Track>::new();
-    println!("Executing query: {:?}", query);
     let mut statement = conn.prepare(&query)?;
-    println!("Preparing parameters: {:?}", params);
 
     let params = params
         .iter()
@@ -167,36 +287,231 @@ pub fn query_tracks(
 
     let mut rows = statement.query_named(params.as_slice())?;
     while let Ok(Some(row)) = rows.next() {
-        let track = Track {
-            file_path: PathBuf::from(&row.get::<_, String>(0)?),
-            title: row.get(1)?,
-            artist: row.get(2)?,
-            album_artists: row.get::<_, String>(3)?
-                .split(';')
-                .map(|c| c.to_owned())
-                .collect::<Vec<String>>(),
-            album: row.get(4)?,
-            year: row.get(5)?,
-            track_number: row.get(6)?,
-            musicbrainz_track_id: row.get(7).ok(),
-            has_front_cover: row.get(8)?,
-            front_cover_width: row.get(9).ok().unwrap_or(0),
-            front_cover_height: row.get(10).ok().unwrap_or(0),
-            bitrate: row.get(11)?,
-            sample_rate: row.get(12)?,
-            source: row.get(13).ok().unwrap_or("None".to_owned()),
-            disc_number: row.get(14)?,
-            duration: ticks_to_ms(row.get(15)?),
-            file_type: TrackFileType::from_i32(row.get::<_, i32>(16)?)
-                .unwrap_or(TrackFileType::Unknown),
-            updated: row.get::<_, String>(17)?
-        };
-        tracks.push(track)
+        tracks.push(track_from_row(row)?)
     }
 
     Ok(tracks)
 }
 
+/// Reads a track from a row of the tracks table.
+fn track_from_row(row: &Row) -> Result<Track> {
+    Ok(Track {
+        file_path: PathBuf::from(&row.get::<_, String>(0)?),
+        title: row.get(1)?,
+        artist: row.get(2)?,
+        album_artists: row.get::<_, String>(3)?
+            .split(';')
+            .map(|c| c.to_owned())
+            .collect::<Vec<String>>(),
+        album: row.get(4)?,
+        year: row.get(5)?,
+        track_number: row.get(6)?,
+        musicbrainz_track_id: row.get(7).ok(),
+        has_front_cover: row.get(8)?,
+        front_cover_width: row.get(9).ok().unwrap_or(0),
+        front_cover_height: row.get(10).ok().unwrap_or(0),
+        bitrate: row.get(11)?,
+        sample_rate: row.get(12)?,
+        source: row.get(13).ok().unwrap_or("None".to_owned()),
+        disc_number: row.get(14)?,
+        duration: ticks_to_ms(row.get(15)?),
+        sample_count: row.get(26).ok().unwrap_or(0),
+        file_type: TrackFileType::from_i32(row.get::<_, i32>(16)?)
+            .unwrap_or(TrackFileType::Unknown),
+        updated: row.get::<_, String>(17)?,
+        front_cover_mime_type: row.get(19).ok().unwrap_or(None),
+        front_cover_size: row.get(20).ok().unwrap_or(0),
+        bitrate_mode: row
+            .get::<_, i32>(21)
+            .ok()
+            .and_then(BitrateMode::from_i32)
+            .unwrap_or(BitrateMode::Unknown),
+        codec_profile: row
+            .get::<_, i32>(22)
+            .ok()
+            .and_then(CodecProfile::from_i32)
+            .unwrap_or(CodecProfile::Unknown),
+        encoder: row.get(23).ok().unwrap_or(None),
+        channels: row.get(24).ok().unwrap_or(0),
+    })
+}
+
+/// Gets the tracks whose files are in the folder or its subfolders.
+pub fn query_tracks_in_folder(folder: &Path, conn: &Connection) -> Result<Vec<Track>> {
+    let mut prefix = folder.to_string_lossy().into_owned();
+    if !prefix.ends_with(MAIN_SEPARATOR) {
+        prefix.push(MAIN_SEPARATOR);
+    }
+    // Comparing the start of the path avoids escaping wildcards for LIKE.
+    let mut statement = conn.prepare("SELECT * FROM tracks WHERE substr(FilePath, 1, length(?1)) = ?1")?;
+    let mut rows = statement.query(&[&prefix])?;
+    let mut tracks = Vec::<Track>::new();
+    while let Some(row) = rows.next()? {
+        tracks.push(track_from_row(row)?)
+    }
+    Ok(tracks)
+}
+
+/// Builds a query that selects the columns from the tracks that match the bang.
+fn select_matching(columns: &str, bang: Bang, params: &mut Vec<(String, String)>) -> String {
+    if let Bang::All = bang {
+        format!("SELECT {} FROM tracks", columns)
+    } else {
+        format!(
+            "SELECT {} FROM tracks WHERE ({})",
+            columns,
+            to_query_string(bang, params)
+        )
+    }
+}
+
+fn named_params(params: &[(String, String)]) -> Vec<(&str, &dyn ToSql)> {
+    params
+        .iter()
+        .map(|c| (c.0.as_ref(), &c.1 as &dyn ToSql))
+        .collect()
+}
+
+/// Counts the tracks that match the bang.
+pub fn count_tracks(bang: Bang, conn: &Connection) -> Result<i64> {
+    let mut params = Vec::<(String, String)>::new();
+    let query = select_matching("COUNT(*)", bang, &mut params);
+    let count = conn.query_row_named(&query, named_params(&params).as_slice(), |row| row.get(0))?;
+    Ok(count)
+}
+
+/// An album, aggregated from its tracks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlbumSummary {
+    pub album: String,
+    pub album_artists: Vec<String>,
+    /// The earliest year of the tracks of the album.
+    pub year: i32,
+    pub track_count: i64,
+    /// The total duration of the tracks in milliseconds.
+    pub duration: i64,
+}
+
+/// An album artist, aggregated from their tracks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtistSummary {
+    pub album_artists: Vec<String>,
+    pub album_count: i64,
+    pub track_count: i64,
+}
+
+/// The size of a library.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryStats {
+    pub track_count: i64,
+    pub album_count: i64,
+    pub artist_count: i64,
+    /// The total duration of the tracks in milliseconds.
+    pub duration: i64,
+}
+
+fn split_album_artists(album_artists: String) -> Vec<String> {
+    album_artists.split(';').map(|c| c.to_owned()).collect()
+}
+
+/// Gets the albums that have tracks that match the bang, in the order of `query_tracks`.
+pub fn query_albums(bang: Bang, conn: &Connection) -> Result<Vec<AlbumSummary>> {
+    let mut params = Vec::<(String, String)>::new();
+    let mut query = select_matching(
+        "Album, AlbumArtists, MIN(Year), COUNT(*), SUM(Duration)",
+        bang,
+        &mut params,
+    );
+    query.push_str(" GROUP BY AlbumArtists, Album ORDER BY CASE WHEN AlbumArtists = 'Various Artists' THEN 1 END, AlbumArtists, Album");
+
+    let mut statement = conn.prepare(&query)?;
+    let albums = statement
+        .query_map_named(named_params(&params).as_slice(), |row| {
+            Ok(AlbumSummary {
+                album: row.get(0)?,
+                album_artists: split_album_artists(row.get(1)?),
+                year: row.get(2)?,
+                track_count: row.get(3)?,
+                duration: ticks_to_ms(row.get(4)?),
+            })
+        })?
+        .collect::<Result<Vec<AlbumSummary>>>()?;
+    Ok(albums)
+}
+
+/// Gets the album artists that have tracks that match the bang.
+pub fn query_artists(bang: Bang, conn: &Connection) -> Result<Vec<ArtistSummary>> {
+    let mut params = Vec::<(String, String)>::new();
+    let mut query = select_matching(
+        "AlbumArtists, COUNT(DISTINCT Album), COUNT(*)",
+        bang,
+        &mut params,
+    );
+    query.push_str(" GROUP BY AlbumArtists ORDER BY CASE WHEN AlbumArtists = 'Various Artists' THEN 1 END, AlbumArtists");
+
+    let mut statement = conn.prepare(&query)?;
+    let artists = statement
+        .query_map_named(named_params(&params).as_slice(), |row| {
+            Ok(ArtistSummary {
+                album_artists: split_album_artists(row.get(0)?),
+                album_count: row.get(1)?,
+                track_count: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<ArtistSummary>>>()?;
+    Ok(artists)
+}
+
+/// Gets the number of tracks, albums and album artists in the library, and their total duration.
+pub fn get_library_stats(conn: &Connection) -> Result<LibraryStats> {
+    conn.query_row(
+        "SELECT COUNT(*),
+            (SELECT COUNT(*) FROM (SELECT 1 FROM tracks GROUP BY AlbumArtists, Album)),
+            COUNT(DISTINCT AlbumArtists),
+            COALESCE(SUM(Duration), 0)
+        FROM tracks",
+        NO_PARAMS,
+        |row| {
+            Ok(LibraryStats {
+                track_count: row.get(0)?,
+                album_count: row.get(1)?,
+                artist_count: row.get(2)?,
+                duration: ticks_to_ms(row.get(3)?),
+            })
+        },
+    )
+}
+
+/// Lossless tracks whose spectral cutoff is below this frequency in Hz are
+/// suspected to be transcoded from a lossy source. This is above the lowpass
+/// of lossy encoders at every bitrate, including 320k MP3 at about 20 kHz, and
+/// does not grow with the sample rate, as hi-res masters often have nothing
+/// above 22 kHz.
+const SUSPECTED_TRANSCODE_CUTOFF: i32 = 20500;
+
+/// Tracks with sample rates too low to reach `SUSPECTED_TRANSCODE_CUTOFF` are
+/// suspected if their cutoff is below this fraction of the Nyquist frequency.
+const SUSPECTED_TRANSCODE_NYQUIST_FRACTION: f64 = 0.95;
+
+/// Gets the SQL expression for the duration of a track in ticks, computed from
+/// its sample count where it is known, and from the rounded `Duration` otherwise.
+/// Opus is always counted at 48kHz.
+fn duration_in_ticks() -> String {
+    format!(
+        "(CASE WHEN SampleCount > 0 AND SampleRate > 0 THEN SampleCount * {} / (CASE WHEN FileType = {} THEN 48000 ELSE SampleRate END) ELSE Duration END)",
+        ms_to_ticks(1000),
+        TrackFileType::Opus.to_i32().unwrap(),
+    )
+}
+
+/// Converts a cover format as written in a bang to its MIME type.
+fn cover_format_to_mime_type(format: &str) -> String {
+    match format.to_lowercase().as_str() {
+        "jpg" | "jpeg" => "image/jpeg".to_owned(),
+        format => format!("image/{}", format),
+    }
+}
+
 #[allow(dead_code)]
 fn get_rand_param() -> String {
     format!(":{}", thread_rng().sample_iter(&Alphanumeric).take(10).collect::<String>()).to_owned()
@@ -269,6 +584,12 @@ fn to_query_string(bang: Bang, params: &mut Vec<(String, String)>) -> String {
             params.push((param_name, format!("{}", source)));
             format
         }
+        Bang::Label(label) => {
+            let param_name = get_rand_param();
+            let format = format!("(IFNULL(Labels, '') REGEXP {})", param_name);
+            params.push((param_name, format!("(?:^|;)((?i){})(?:;|$)", escape_regex_search(&label))));
+            format
+        }
         Bang::Format(filetype) => {
 
             match filetype {
@@ -291,9 +612,16 @@ fn to_query_string(bang: Bang, params: &mut Vec<(String, String)>) -> String {
                 TrackFileType::ALAC => {
                     let param_name_lesser = get_rand_param();
                     let param_name_greater = get_rand_param();
-                    let format = format!("(FileType BETWEEN {} AND {})", param_name_lesser, param_name_greater);
+                    let param_name_hires_lesser = get_rand_param();
+                    let param_name_hires_greater = get_rand_param();
+                    let format = format!(
+                        "(FileType BETWEEN {} AND {} OR FileType BETWEEN {} AND {})",
+                        param_name_lesser, param_name_greater, param_name_hires_lesser, param_name_hires_greater
+                    );
                     params.push((param_name_lesser, format!("{}", TrackFileType::ALAC16.to_i32().unwrap())));
                     params.push((param_name_greater, format!("{}", TrackFileType::ALAC.to_i32().unwrap())));
+                    params.push((param_name_hires_lesser, format!("{}", TrackFileType::ALAC20.to_i32().unwrap())));
+                    params.push((param_name_hires_greater, format!("{}", TrackFileType::ALAC32.to_i32().unwrap())));
                     format
                 }
                 TrackFileType::MonkeysAudio => {
@@ -304,6 +632,30 @@ fn to_query_string(bang: Bang, params: &mut Vec<(String, String)>) -> String {
                     params.push((param_name_greater, format!("{}", TrackFileType::MonkeysAudio.to_i32().unwrap())));
                     format
                 }
+                TrackFileType::WavPack => {
+                    let param_name_lesser = get_rand_param();
+                    let param_name_greater = get_rand_param();
+                    let format = format!("(FileType BETWEEN {} AND {})", param_name_lesser, param_name_greater);
+                    params.push((param_name_lesser, format!("{}", TrackFileType::WavPack8.to_i32().unwrap())));
+                    params.push((param_name_greater, format!("{}", TrackFileType::WavPack.to_i32().unwrap())));
+                    format
+                }
+                TrackFileType::WAV => {
+                    let param_name_lesser = get_rand_param();
+                    let param_name_greater = get_rand_param();
+                    let format = format!("(FileType BETWEEN {} AND {})", param_name_lesser, param_name_greater);
+                    params.push((param_name_lesser, format!("{}", TrackFileType::WAV8.to_i32().unwrap())));
+                    params.push((param_name_greater, format!("{}", TrackFileType::WAV.to_i32().unwrap())));
+                    format
+                }
+                TrackFileType::DSD => {
+                    let param_name_lesser = get_rand_param();
+                    let param_name_greater = get_rand_param();
+                    let format = format!("(FileType BETWEEN {} AND {})", param_name_lesser, param_name_greater);
+                    params.push((param_name_lesser, format!("{}", TrackFileType::DSD64.to_i32().unwrap())));
+                    params.push((param_name_greater, format!("{}", TrackFileType::DSD.to_i32().unwrap())));
+                    format
+                }
                 TrackFileType::MP3 => {
                     let param_name_lesser = get_rand_param();
                     let param_name_greater = get_rand_param();
@@ -332,6 +684,36 @@ fn to_query_string(bang: Bang, params: &mut Vec<(String, String)>) -> String {
             params.push((param_name, format!("{}", bitrate)));
             format
         }
+        Bang::BitrateMode(mode) => {
+            let param_name = get_rand_param();
+            let format = format!("(BitrateMode = {})", param_name);
+            params.push((param_name, format!("{}", mode.to_i32().unwrap())));
+            format
+        }
+        Bang::CodecProfile(profile) => {
+            let param_name = get_rand_param();
+            let format = format!("(CodecProfile = {})", param_name);
+            params.push((param_name, format!("{}", profile.to_i32().unwrap())));
+            format
+        }
+        Bang::Encoder(encoder) => {
+            let param_name = get_rand_param();
+            let format = format!("(Encoder LIKE {})", param_name);
+            params.push((param_name, format!("%{}%", encoder)));
+            format
+        }
+        Bang::EncoderExact(encoder) => {
+            let param_name = get_rand_param();
+            let format = format!("(Encoder = {})", param_name);
+            params.push((param_name, encoder));
+            format
+        }
+        Bang::Channels(channels) => {
+            let param_name = get_rand_param();
+            let format = format!("(Channels = {})", param_name);
+            params.push((param_name, format!("{}", channels)));
+            format
+        }
         Bang::CoverArtWidthGreaterThan(width) => {
             let param_name = get_rand_param();
             let format = format!("(FrontCoverWidth > {})", param_name);
@@ -356,15 +738,33 @@ fn to_query_string(bang: Bang, params: &mut Vec<(String, String)>) -> String {
             params.push((param_name, format!("{}", height)));
             format
         }
+        Bang::CoverArtSizeGreaterThan(size) => {
+            let param_name = get_rand_param();
+            let format = format!("(FrontCoverSize > {})", param_name);
+            params.push((param_name, format!("{}", size)));
+            format
+        }
+        Bang::CoverArtSizeLessThan(size) => {
+            let param_name = get_rand_param();
+            let format = format!("(FrontCoverSize < {})", param_name);
+            params.push((param_name, format!("{}", size)));
+            format
+        }
+        Bang::CoverArtFormat(format) => {
+            let param_name = get_rand_param();
+            let query = format!("(FrontCoverMimeType = {})", param_name);
+            params.push((param_name, cover_format_to_mime_type(&format)));
+            query
+        }
         Bang::DurationGreaterThan(duration) => {
             let param_name = get_rand_param();
-            let format = format!("(Duration > {})", param_name);
+            let format = format!("({} > CAST({} AS INTEGER))", duration_in_ticks(), param_name);
             params.push((param_name, format!("{}", duration)));
             format
         }
         Bang::DurationLessThan(duration) => {
             let param_name = get_rand_param();
-            let format = format!("(Duration < {})", param_name);
+            let format = format!("({} < CAST({} AS INTEGER))", duration_in_ticks(), param_name);
             params.push((param_name, format!("{}", duration)));
             format
         }
@@ -386,6 +786,41 @@ fn to_query_string(bang: Bang, params: &mut Vec<(String, String)>) -> String {
             params.push((param_name, format!("{}", has)));
             format
         }
+        Bang::HasNonSquareCoverArt(has) => (if has {
+            "(HasFrontCover = 1 AND FrontCoverWidth != FrontCoverHeight)"
+        } else {
+            "(HasFrontCover = 1 AND FrontCoverWidth = FrontCoverHeight)"
+        }).to_owned(),
+        // Tracks without cover art count as having a different cover.
+        Bang::HasInconsistentCoverArt(has) => (if has {
+            "(Album, AlbumArtists) in (select Album, AlbumArtists from tracks group by Album, AlbumArtists having count(distinct ifnull(CoverKey, '')) > 1)"
+        } else {
+            "(Album, AlbumArtists) not in (select Album, AlbumArtists from tracks group by Album, AlbumArtists having count(distinct ifnull(CoverKey, '')) > 1)"
+        }).to_owned(),
+        Bang::IsSuspectedTranscode(is) => {
+            let lossless = format!(
+                "(FileType BETWEEN {} AND {} OR FileType BETWEEN {} AND {} OR FileType BETWEEN {} AND {} OR FileType BETWEEN {} AND {})",
+                TrackFileType::FLAC4.to_i32().unwrap(),
+                TrackFileType::FLAC.to_i32().unwrap(),
+                TrackFileType::ALAC16.to_i32().unwrap(),
+                TrackFileType::ALAC.to_i32().unwrap(),
+                TrackFileType::ALAC20.to_i32().unwrap(),
+                TrackFileType::ALAC32.to_i32().unwrap(),
+                TrackFileType::AIFF4.to_i32().unwrap(),
+                TrackFileType::AIFF.to_i32().unwrap(),
+            );
+            // The Nyquist frequency is half the sample rate.
+            let threshold = format!(
+                "MIN(SampleRate * {}, {})",
+                SUSPECTED_TRANSCODE_NYQUIST_FRACTION / 2.0,
+                SUSPECTED_TRANSCODE_CUTOFF
+            );
+            if is {
+                format!("({} AND SpectralCutoff > 0 AND SpectralCutoff < {})", lossless, threshold)
+            } else {
+                format!("({} AND SpectralCutoff >= {})", lossless, threshold)
+            }
+        }
         Bang::HasMusicbrainzId(has) => (if has {
             "(MusicBrainzTrackId IS NOT NULL)"
         } else {
@@ -470,9 +905,17 @@ pub fn add_track(track: &Track, conn: &Connection) {
                 DiscNumber,
                 Duration,
                 FileType,
-                Updated) 
+                Updated,
+                FrontCoverMimeType,
+                FrontCoverSize,
+                BitrateMode,
+                CodecProfile,
+                Encoder,
+                Channels,
+                SampleCount) 
                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7,
-                        ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+                        ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18,
+                        ?19, ?20, ?21, ?22, ?23, ?24, ?25)",
         &[
             &track.file_path.as_os_str().to_string_lossy().into_owned() as &dyn ToSql,
             &track.title,
@@ -492,6 +935,70 @@ pub fn add_track(track: &Track, conn: &Connection) {
             &ms_to_ticks(track.duration),
             &track.file_type.to_i32().unwrap(),
             &track.updated,
+            &track.front_cover_mime_type,
+            &track.front_cover_size,
+            &track.bitrate_mode.to_i32().unwrap(),
+            &track.codec_profile.to_i32().unwrap(),
+            &track.encoder,
+            &track.channels,
+            &track.sample_count,
+        ],
+    ).unwrap();
+}
+
+#[allow(dead_code)]
+pub fn set_cover_key(track: &Track, cover_key: Option<&str>, conn: &Connection) {
+    conn.execute(
+        "UPDATE tracks SET CoverKey = ?1 WHERE FilePath = ?2",
+        &[
+            &cover_key as &dyn ToSql,
+            &track.file_path.to_string_lossy().into_owned(),
         ],
     ).unwrap();
 }
+
+#[allow(dead_code)]
+pub fn set_spectral_cutoff(track: &Track, spectral_cutoff: Option<i32>, conn: &Connection) {
+    conn.execute(
+        "UPDATE tracks SET SpectralCutoff = ?1 WHERE FilePath = ?2",
+        &[
+            &spectral_cutoff as &dyn ToSql,
+            &track.file_path.to_string_lossy().into_owned(),
+        ],
+    ).unwrap();
+}
+
+#[allow(dead_code)]
+pub fn get_spectral_cutoff(track: &Track, conn: &Connection) -> Result<Option<i32>> {
+    conn.query_row(
+        "SELECT SpectralCutoff FROM tracks WHERE FilePath = ?1",
+        &[&track.file_path.to_string_lossy().into_owned()],
+        |row| row.get::<_, Option<i32>>(0),
+    )
+    .optional()
+    .map(|spectral_cutoff| spectral_cutoff.and_then(|cutoff| cutoff))
+}
+
+/// Sets the labels of the given track, which are stored separated by `;` like album artists.
+#[allow(dead_code)]
+pub fn set_labels(track: &Track, labels: &[String], conn: &Connection) {
+    let labels = if labels.is_empty() { None } else { Some(labels.join(";")) };
+    conn.execute(
+        "UPDATE tracks SET Labels = ?1 WHERE FilePath = ?2",
+        &[
+            &labels as &dyn ToSql,
+            &track.file_path.to_string_lossy().into_owned(),
+        ],
+    ).unwrap();
+}
+
+#[allow(dead_code)]
+pub fn get_cover_key(track: &Track, conn: &Connection) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT CoverKey FROM tracks WHERE FilePath = ?1",
+        &[&track.file_path.to_string_lossy().into_owned()],
+        |row| row.get::<_, Option<String>>(0),
+    )
+    .optional()
+    .map(|cover_key| cover_key.and_then(|key| key))
+}
@@ -10,6 +10,13 @@ pub type Result<T> = result::Result<T, Error>;
 pub enum ConfigErrorType {
     IOError(String),
     Invalid,
+    /// The library with the given name has an invalid name or invalid folders,
+    /// or shares a folder or a database with another library.
+    InvalidLibrary(String),
+    /// The setting with the given name could not be overridden with the given value.
+    InvalidSetting(String),
+    /// The home directory of the user could not be found.
+    NoHomeDirectory,
 }
 
 quick_error! {
@@ -54,6 +61,21 @@ quick_error! {
         ParserInvalidInput(input: String) {
             display(r#"Invalid input "{}" when parsing bang"#, input)
         }
+        UnableToCacheCover(file_name: PathBuf) {
+            display(r#"The cover art of {:?} could not be cached."#, file_name)
+        }
+        RejectedBySource(file_name: PathBuf, source: String) {
+            display(r#"The track {:?} is not accepted by the rules of source {}"#, file_name, source)
+        }
+        CorruptFile(file_name: PathBuf, defect: String) {
+            display(r#"The audio of {:?} is corrupt: {}"#, file_name, defect)
+        }
+        InvalidAlbum(folder_name: PathBuf, reason: String) {
+            display(r#"The album in {:?} was not added: {}"#, folder_name, reason)
+        }
+        UnableToAnalyze(file_name: PathBuf) {
+            display(r#"The audio of {:?} could not be analyzed."#, file_name)
+        }
         ConfigError(error: ConfigErrorType) {
             display(r#"Error "{:?}" when parsing configuration"#, error)
         }
@@ -11,19 +11,26 @@ extern crate app_dirs;
 extern crate toml;
 extern crate katatsuki;
 extern crate dirs;
+extern crate image;
+extern crate sha1;
 
 mod bangs;
 mod error;
 
 
 pub use katatsuki::TrackFileType;
+pub use katatsuki::{BitrateMode, CodecProfile};
 pub use katatsuki::Track;
 pub use self::error::{Error, Result, ConfigErrorType};
 pub use self::bangs::Bang;
 
+#[cfg(feature = "analysis")]
+pub mod analysis;
 pub mod config;
+pub mod covers;
 pub mod database;
 pub mod paths;
+pub mod refresh;
 
 pub mod ticks {
     pub use crate::bangs::ms_to_ticks;
@@ -1,12 +1,16 @@
 use app_dirs::*;
 use chrono::prelude::*;
-use crate::error::{Error, Result};
-use katatsuki::Track;
+use crate::config::{ImportPolicy, Library, RequiredTag, TagPolicy};
+use crate::error::{ConfigErrorType, Error, Result};
+use katatsuki::{Track, TrackFileType};
 // use tree_magic;
+use std::collections::BTreeMap;
+use std::env;
 use std::fs;
 use std::io;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 
 trait InvalidChar {
     fn is_invalid_for_path(&self) -> bool;
@@ -21,36 +25,43 @@ impl InvalidChar for char {
     }
 }
 
-pub fn new_track_checked(track_path: &Path, source: Option<&str>) -> Result<Track> {
+/// Whether the track has a value for the given tag.
+fn has_tag(track: &Track, tag: RequiredTag) -> bool {
+    match tag {
+        RequiredTag::Title => !track.title.trim().is_empty(),
+        RequiredTag::Artist => !track.artist.trim().is_empty(),
+        RequiredTag::Album => !track.album.trim().is_empty(),
+        // Splitting an empty AlbumArtists tag still yields a single empty artist.
+        RequiredTag::AlbumArtists => track.album_artists.iter().any(|a| !a.trim().is_empty()),
+        RequiredTag::Year => track.year != 0,
+        RequiredTag::TrackNumber => track.track_number != 0,
+        RequiredTag::MusicBrainzTrackId => track
+            .musicbrainz_track_id
+            .as_ref()
+            .is_some_and(|id| !id.trim().is_empty()),
+    }
+}
+
+/// Reads the track at the given path, and checks that it has the tags
+/// required by the given policy.
+pub fn new_track_checked(track_path: &Path, source: Option<&str>, policy: &TagPolicy) -> Result<Track> {
 
     // let mimetype = tree_magic::from_filepath(track_path);
     // if !mimetype.starts_with("audio") {
     //     return Err(Error::UnsupportedFile(track_path.to_owned()));
     // } 
     match Track::from_path(track_path, source) {
-        Ok(track) => {
-            if track.title.is_empty() {
-                return Err(Error::MissingRequiredTag(
-                    track_path.to_str().unwrap().to_owned(),
-                    "Title",
-                ));
+        Ok(mut track) => {
+            if policy.album_artist_fallback
+                && !has_tag(&track, RequiredTag::AlbumArtists)
+                && has_tag(&track, RequiredTag::Artist)
+            {
+                track.album_artists = vec![track.artist.trim().to_owned()];
             }
-            if track.artist.is_empty() {
+            if let Some(tag) = policy.required_tags.iter().find(|&&tag| !has_tag(&track, tag)) {
                 return Err(Error::MissingRequiredTag(
                     track_path.to_str().unwrap().to_owned(),
-                    "Artist",
-                ));
-            }
-            if track.album.is_empty() {
-                return Err(Error::MissingRequiredTag(
-                    track_path.to_str().unwrap().to_owned(),
-                    "Album",
-                ));
-            }
-            if track.album_artists.len() == 0 {
-                return Err(Error::MissingRequiredTag(
-                    track_path.to_str().unwrap().to_owned(),
-                    "AlbumArtists",
+                    tag.name(),
                 ));
             }
             Ok(track)
@@ -62,16 +73,44 @@ pub fn new_track_checked(track_path: &Path, source: Option<&str>) -> Result<Trac
     }
 }
 
+/// The application data path set with `set_appdata_path`.
+static APPDATA_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Overrides the application data path for the rest of the process, such as
+/// with the `--data-dir` flag of seiri-watcher.
+pub fn set_appdata_path(path: PathBuf) {
+    *APPDATA_PATH.write().unwrap() = Some(path);
+}
+
+/// Finds the application data path. The path set with `set_appdata_path` is
+/// used first, then the `SEIRI_DATA_DIR` environment variable, then the
+/// `.seiri` folder in the configuration folder of the user.
+pub(crate) fn find_appdata_path() -> Option<PathBuf> {
+    if let Some(path) = APPDATA_PATH.read().unwrap().as_ref() {
+        return Some(path.clone());
+    }
+    if let Some(path) = env::var_os("SEIRI_DATA_DIR") {
+        return Some(PathBuf::from(path));
+    }
+    get_data_root(AppDataType::UserConfig)
+        .ok()
+        .map(|p| p.join(".seiri"))
+}
+
+/// Gets the application data path, creating it if it does not exist.
+pub fn try_get_appdata_path() -> Result<PathBuf> {
+    let appdata_path = find_appdata_path().ok_or(Error::ConfigError(ConfigErrorType::NoHomeDirectory))?;
+    if fs::create_dir_all(&appdata_path).is_err() {
+        let path = appdata_path.to_string_lossy().into_owned();
+        return Err(Error::ConfigError(ConfigErrorType::IOError(path)));
+    }
+    Ok(appdata_path)
+}
+
 /// Gets the application data path.
-/// Panics if unable to be created.
+/// Panics if unable to be found or created.
 pub fn get_appdata_path() -> PathBuf {
-    let appdata_path = get_data_root(AppDataType::UserConfig)
-        .ok()
-        .and_then(|mut p: PathBuf| {
-            p.push(".seiri");
-            Some(p)
-        })
-        .unwrap();
+    let appdata_path = find_appdata_path().expect("Unable to find the application directory");
     if let Err(_) = fs::create_dir_all(appdata_path.as_path()) {
         panic!(
             "Unable to create application directory at {:?}",
@@ -81,15 +120,69 @@ pub fn get_appdata_path() -> PathBuf {
     appdata_path
 }
 
-pub fn ensure_music_folder(folder_path: &str) -> io::Result<(PathBuf, PathBuf)> {
-    let music_folder = Path::new(folder_path);
-    let mut auto_add_folder = PathBuf::from(music_folder);
-    let music_folder = PathBuf::from(music_folder);
-    auto_add_folder.pop();
-    auto_add_folder.push("Automatically Add to Library");
-    fs::create_dir_all(music_folder.as_path())?;
-    fs::create_dir_all(auto_add_folder.as_path())?;
-    Ok((music_folder, auto_add_folder))
+/// Gets the path of the cover art thumbnail cache.
+/// Panics if unable to be created.
+pub fn get_cover_cache_path() -> PathBuf {
+    let mut cover_cache_path = get_appdata_path();
+    cover_cache_path.push("covers");
+    if fs::create_dir_all(cover_cache_path.as_path()).is_err() {
+        panic!(
+            "Unable to create cover cache directory at {:?}",
+            cover_cache_path
+        )
+    }
+    cover_cache_path
+}
+
+/// The folders of a library.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryFolders {
+    /// The folder tracks are moved into.
+    pub music_folder: PathBuf,
+    /// The folder new tracks are added from.
+    pub auto_add_folder: PathBuf,
+    /// The folder files that are not tracks are moved into.
+    pub not_added_folder: PathBuf,
+}
+
+/// Gets the folders of the library. Unless set in the configuration, the default
+/// library is added to from "Automatically Add to Library", and other libraries
+/// from "Automatically Add to <name>", next to their music folder. Files that are
+/// not tracks are moved to the `.notadded` folder of the Automatically Add folder,
+/// and a relative `not_added_folder` is relative to the Automatically Add folder.
+pub fn get_library_folders(library: &Library) -> LibraryFolders {
+    let music_folder = PathBuf::from(&library.music_folder);
+    let auto_add_folder = match &library.auto_add_folder {
+        Some(auto_add_folder) => PathBuf::from(auto_add_folder),
+        None => {
+            let mut auto_add_folder = music_folder.clone();
+            auto_add_folder.pop();
+            if library.is_default() {
+                auto_add_folder.push("Automatically Add to Library");
+            } else {
+                auto_add_folder.push(format!("Automatically Add to {}", library.name));
+            }
+            auto_add_folder
+        }
+    };
+    let not_added_folder = auto_add_folder.join(library.not_added_folder.as_deref().unwrap_or(".notadded"));
+    LibraryFolders {
+        music_folder,
+        auto_add_folder,
+        not_added_folder,
+    }
+}
+
+pub fn ensure_library_folders(library: &Library) -> io::Result<LibraryFolders> {
+    let folders = get_library_folders(library);
+    fs::create_dir_all(folders.music_folder.as_path())?;
+    fs::create_dir_all(folders.auto_add_folder.as_path())?;
+    Ok(folders)
+}
+
+/// Whether the name can be used as a folder or file name on its own.
+pub fn is_valid_folder_name(name: &str) -> bool {
+    !is_whitespace(name) && !name.chars().any(|c| c.is_invalid_for_path()) && !name.ends_with('.')
 }
 
 fn sanitize_file_name(path: &str) -> String {
@@ -99,10 +192,11 @@ fn sanitize_file_name(path: &str) -> String {
 pub fn get_track_directory(track: &Track, library_path: &Path) -> PathBuf {
     let mut track_path = PathBuf::from(library_path);
 
-    let artist_folder = if track.album_artists.len() > 0 {
+    // Tracks without album artists are filed under their artist.
+    let artist_folder = if has_tag(track, RequiredTag::AlbumArtists) {
         track.album_artists.join(", ")
     } else {
-        (&track.album_artists[0]).to_owned()
+        track.artist.to_owned()
     };
 
     let artist_folder = artist_folder.trim();
@@ -121,6 +215,70 @@ fn get_track_filename(track: &Track) -> String {
     sanitize_file_name(file_name)
 }
 
+/// Renders a path template for the given track, relative to the library
+/// path and without an extension.
+///
+/// The template is split into folders on `/`, and each folder is sanitized
+/// after its placeholders are replaced. The available placeholders are
+/// `{albumartists}`, `{artist}`, `{album}`, `{title}`, `{year}`, `{disc}`,
+/// `{track}`, which is padded to two digits, and `{source}`.
+/// Returns `None` if the template renders to an empty path.
+pub fn render_path_template(template: &str, track: &Track, source: &str) -> Option<PathBuf> {
+    let album_artists = if has_tag(track, RequiredTag::AlbumArtists) {
+        track.album_artists.join(", ")
+    } else {
+        track.artist.to_owned()
+    };
+    let placeholder = |name: &str| match name {
+        "albumartists" => Some(album_artists.trim().to_owned()),
+        "artist" => Some(track.artist.trim().to_owned()),
+        "album" => Some(track.album.trim().to_owned()),
+        "title" => Some(track.title.trim().to_owned()),
+        "year" => Some(track.year.to_string()),
+        "disc" => Some(track.disc_number.to_string()),
+        "track" => Some(format!("{:02}", track.track_number)),
+        "source" => Some(source.to_owned()),
+        _ => None,
+    };
+    let path = template
+        .split('/')
+        .map(|component| fill_placeholders(component, placeholder))
+        .map(|component| sanitize_file_name(component.trim()))
+        .filter(|component| !component.is_empty())
+        .collect::<PathBuf>();
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+/// Replaces each `{name}` in the text with the value of the placeholder in a
+/// single pass, so that values containing braces are not replaced in turn.
+/// Unknown placeholders are kept as they are.
+fn fill_placeholders(text: &str, placeholder: impl Fn(&str) -> Option<String>) -> String {
+    let mut filled = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        let value = rest[start..].find('}').and_then(|end| {
+            placeholder(&rest[start + 1..start + end]).map(|value| (value, start + end + 1))
+        });
+        match value {
+            Some((value, end)) => {
+                filled.push_str(&value);
+                rest = &rest[end..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
 fn get_iterative_filename(filename: &str, extension: &str, destination: &Path) -> PathBuf {
     let mut new_path = PathBuf::from(destination);
     let mut counter = 0;
@@ -143,7 +301,9 @@ fn is_whitespace(string: &str) -> bool {
     string.chars().all(char::is_whitespace)
 }
 
-fn get_source(track_file_path: &Path, relative_to: &Path) -> String {
+/// Gets the source of a track from the name of the first subfolder of the
+/// Automatically Add to Library path that it is in.
+pub fn get_source(track_file_path: &Path, relative_to: &Path) -> String {
     match track_file_path.parent().unwrap().strip_prefix(relative_to) {
         Ok(source) if is_whitespace(&source.to_string_lossy()) => "None".to_owned(),
         Ok(source) => sanitize_file_name(&source.to_string_lossy())
@@ -155,24 +315,25 @@ fn get_source(track_file_path: &Path, relative_to: &Path) -> String {
     }
 }
 
-fn ensure_not_added(auto_add_path: &Path) -> io::Result<PathBuf> {
-    let mut not_added = PathBuf::from(auto_add_path);
+/// Ensures today's folder in the given quarantine folder, such as the
+/// `.corrupt` folder of the Automatically Add to Library path.
+fn ensure_quarantine(quarantine: &Path) -> io::Result<PathBuf> {
+    let mut quarantine_path = PathBuf::from(quarantine);
     let local: DateTime<Local> = Local::now();
-    not_added.push(".notadded");
-    not_added.push(local.format("%Y-%m-%d").to_string());
-    match fs::create_dir_all(&not_added) {
-        Ok(_) => Ok(not_added),
+    quarantine_path.push(local.format("%Y-%m-%d").to_string());
+    match fs::create_dir_all(&quarantine_path) {
+        Ok(_) => Ok(quarantine_path),
         Err(err) => Err(err),
     }
 }
 
-pub fn move_non_track(path: &Path, auto_add_path: &Path) -> Result<()> {
-    if let Ok(notadded) = ensure_not_added(auto_add_path) {
+fn move_to_quarantine(path: &Path, quarantine: &Path) -> Result<()> {
+    if let Ok(quarantine_path) = ensure_quarantine(quarantine) {
         let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
         let filename = path.file_stem()
             .and_then(|s| s.to_str())
             .unwrap_or("unnamed file");
-        let new_file_name = get_iterative_filename(filename, ext, &notadded);
+        let new_file_name = get_iterative_filename(filename, ext, &quarantine_path);
         if let Err(_) = fs::rename(path, &new_file_name) {
             return Err(Error::UnableToMove(
                 new_file_name.to_string_lossy().into_owned(),
@@ -181,7 +342,135 @@ pub fn move_non_track(path: &Path, auto_add_path: &Path) -> Result<()> {
             return Ok(());
         }
     }
-    Err(Error::UnableToMove("not added folder".to_owned()))
+    Err(Error::UnableToMove(format!("{} folder", quarantine.display())))
+}
+
+/// Moves a file that is not a track out of the way, into the not added folder of the library.
+pub fn move_non_track(path: &Path, not_added_path: &Path) -> Result<()> {
+    move_to_quarantine(path, not_added_path)
+}
+
+/// Moves a track whose audio stream is corrupt out of the way, into the
+/// `.corrupt` folder of the Automatically Add to Library path.
+pub fn move_corrupt_track(path: &Path, auto_add_path: &Path) -> Result<()> {
+    move_to_quarantine(path, &auto_add_path.join(".corrupt"))
+}
+
+/// Moves a track rejected by the rules of its source out of the way, into the
+/// `.rejected` folder of the Automatically Add to Library path.
+pub fn move_rejected_track(path: &Path, auto_add_path: &Path) -> Result<()> {
+    move_to_quarantine(path, &auto_add_path.join(".rejected"))
+}
+
+/// Moves an album that could not be added out of the way as a whole, into the
+/// `.rejected` folder of the Automatically Add to Library path. A folder with
+/// subfolders, which may hold other albums, is left in place, and only the
+/// files directly in it are moved.
+pub fn move_rejected_album(folder: &Path, auto_add_path: &Path) -> Result<()> {
+    let rejected = auto_add_path.join(".rejected");
+    let quarantine_path = ensure_quarantine(&rejected)
+        .map_err(|_| Error::UnableToMove(format!("{} folder", rejected.display())))?;
+    let name = folder
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("unnamed album");
+    let mut new_path = quarantine_path.join(name);
+    let mut counter = 0;
+    while new_path.exists() {
+        counter += 1;
+        new_path = quarantine_path.join(format!("{} ({})", name, counter));
+    }
+    let unable_to_move = |path: &Path| Error::UnableToMove(path.to_string_lossy().into_owned());
+    let entries = fs::read_dir(folder)
+        .map_err(|_| unable_to_move(folder))?
+        .filter_map(|entry| entry.ok())
+        .collect::<Vec<fs::DirEntry>>();
+    if !entries.iter().any(|entry| entry.path().is_dir()) {
+        return fs::rename(folder, &new_path).map_err(|_| unable_to_move(&new_path));
+    }
+    fs::create_dir(&new_path).map_err(|_| unable_to_move(&new_path))?;
+    for entry in entries.iter().filter(|entry| !entry.path().is_dir()) {
+        let destination = new_path.join(entry.file_name());
+        fs::rename(entry.path(), &destination).map_err(|_| unable_to_move(&destination))?;
+    }
+    Ok(())
+}
+
+fn trimmed(values: &[String]) -> Vec<&str> {
+    values.iter().map(|value| value.trim()).collect()
+}
+
+/// Checks that the tracks in the folder of an album belong together. Every
+/// track must have the same album and album artists, and the tracks of each
+/// disc must be numbered from 1 without gaps.
+pub fn check_album(folder: &Path, tracks: &[Track]) -> Result<()> {
+    let invalid = |reason: String| Err(Error::InvalidAlbum(folder.to_owned(), reason));
+    let first = match tracks.first() {
+        Some(first) => first,
+        None => return Ok(()),
+    };
+    for track in tracks {
+        if track.album.trim() != first.album.trim() {
+            return invalid(format!(
+                "{:?} is from the album {}, not {}",
+                track.file_path,
+                track.album.trim(),
+                first.album.trim()
+            ));
+        }
+        if trimmed(&track.album_artists) != trimmed(&first.album_artists) {
+            return invalid(format!(
+                "{:?} is by {}, not {}",
+                track.file_path,
+                trimmed(&track.album_artists).join(", "),
+                trimmed(&first.album_artists).join(", ")
+            ));
+        }
+        if track.track_number <= 0 {
+            return invalid(format!("{:?} has no track number", track.file_path));
+        }
+    }
+
+    let mut discs: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+    for track in tracks {
+        discs.entry(track.disc_number).or_default().push(track.track_number);
+    }
+    for (disc, track_numbers) in &mut discs {
+        track_numbers.sort();
+        let disc = if *disc > 0 {
+            format!("disc {}", disc)
+        } else {
+            "the album".to_owned()
+        };
+        for (expected, &track_number) in (1..).zip(track_numbers.iter()) {
+            if track_number < expected {
+                return invalid(format!("{} has more than one track {}", disc, track_number));
+            }
+            if track_number > expected {
+                return invalid(format!("{} is missing track {}", disc, expected));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_lossy(file_type: &TrackFileType) -> bool {
+    matches!(
+        file_type,
+        TrackFileType::MP3CBR
+            | TrackFileType::MP3VBR
+            | TrackFileType::AAC
+            | TrackFileType::Vorbis
+            | TrackFileType::Opus
+    )
+}
+
+/// Checks that the format of the track is accepted by the import policy of its source.
+pub fn check_import_policy(track: Track, policy: &ImportPolicy) -> Result<Track> {
+    if policy.reject_lossy && is_lossy(&track.file_type) {
+        return Err(Error::RejectedBySource(track.file_path, policy.source.to_owned()));
+    }
+    Ok(track)
 }
 
 fn track_warrants_move(track_as_saved: &Track, track_as_read: &Track) -> bool {
@@ -195,13 +484,13 @@ fn track_warrants_move(track_as_saved: &Track, track_as_read: &Track) -> bool {
 /// If the file is gone or deleted, returns Ok(None).
 /// Otherwise, returns a new Track that has a new
 /// or same location, depending if its properties have changed.
-pub fn reconsider_track(track: &Track, library_path: &Path) -> Result<Option<Track>> {
+pub fn reconsider_track(track: &Track, library_path: &Path, policy: &ImportPolicy) -> Result<Option<Track>> {
     let track_file_path = Path::new(&track.file_path);
     if !track_file_path.exists() {
         return Ok(None);
     }
 
-    match new_track_checked(track_file_path, Some(&track.source)) {
+    match new_track_checked(track_file_path, Some(&track.source), &policy.tags) {
         Ok(track_as_read) => {
             if !track_warrants_move(track, &track_as_read) {
                 return Ok(Some(track_as_read));
@@ -210,8 +499,7 @@ pub fn reconsider_track(track: &Track, library_path: &Path) -> Result<Option<Tra
                 file_path: track.file_path.to_owned(),
                 ..track_as_read
             };
-            println!("{:?}", track_as_read);
-            match move_track(&track_as_read, library_path, &track_as_read.source) {
+            match move_track(&track_as_read, library_path, policy) {
                 Ok(track) => {
                     //  Cleanup
                     if let Some(old_dir) = &track_file_path.parent() {
@@ -231,22 +519,17 @@ pub fn reconsider_track(track: &Track, library_path: &Path) -> Result<Option<Tra
     }
 }
 
-/// Moves the given track to its proper destination in the library, relative
-/// to the Automatically Add to Library path.
-pub fn move_new_track(track: &Track, library_path: &Path, auto_add_path: &Path) -> Result<Track> {
-    // The original path where the track was found.
-    let original_path = Path::new(&track.file_path);
-
-    // The name of the first subfolder from the Automatically Add to Library path
-    // and marks it as the source.
-    let source = get_source(original_path, auto_add_path);
-
-    move_track(track, library_path, &source)
+/// Moves the given track to its proper destination in the library, with the
+/// source of the given policy.
+pub fn move_new_track(track: &Track, library_path: &Path, policy: &ImportPolicy) -> Result<Track> {
+    move_track(track, library_path, policy)
 }
 
-/// Moves a track to its proper position in the library, with the given source.
-pub fn move_track(track: &Track, library_path: &Path, source: &str) -> Result<Track> {
+/// Moves a track to its proper position in the library, with the source of
+/// the given policy. The moved track is read again with the tag policy.
+pub fn move_track(track: &Track, library_path: &Path, policy: &ImportPolicy) -> Result<Track> {
     let track_file_path = Path::new(&track.file_path);
+    let source = &policy.source;
 
     // get the track file extension
     let track_ext = {
@@ -272,11 +555,23 @@ pub fn move_track(track: &Track, library_path: &Path, source: &str) -> Result<Tr
         }
     };
 
-    // The new filename of the track, from the track metadata.
-    let track_file_name = get_track_filename(&track);
-
-    // The new directory of the track in the library, from track metadata
-    let track_folder = get_track_directory(&track, &library_path);
+    // The new directory and filename of the track in the library, from the
+    // path template of the source or the track metadata.
+    let templated_path = policy
+        .path_template
+        .as_ref()
+        .and_then(|template| render_path_template(template, track, source));
+    let (track_folder, track_file_name) = match templated_path {
+        Some(templated_path) => {
+            let file_name = templated_path.file_name().unwrap().to_string_lossy().into_owned();
+            let mut track_folder = PathBuf::from(library_path);
+            if let Some(parent) = templated_path.parent() {
+                track_folder.push(parent);
+            }
+            (track_folder, file_name)
+        }
+        None => (get_track_directory(track, library_path), get_track_filename(track)),
+    };
 
     // Ensure the new directory
     if let Err(_) = fs::create_dir_all(&track_folder) {
@@ -289,13 +584,11 @@ pub fn move_track(track: &Track, library_path: &Path, source: &str) -> Result<Tr
     let new_file_name = get_iterative_filename(&track_file_name, &track_ext, &track_folder);
 
     // Do the move.
-    if let Err(err) = fs::rename(track_file_path, &new_file_name) {
-        println!("{}", err);
-        println!("{:?}", track_file_path);
+    if fs::rename(track_file_path, &new_file_name).is_err() {
         Err(Error::UnableToMove(
             new_file_name.to_string_lossy().into_owned(),
         ))
     } else {
-        new_track_checked(&new_file_name, Some(&source))
+        new_track_checked(&new_file_name, Some(source), &policy.tags)
     }
 }
@@ -0,0 +1,48 @@
+//! Refreshing of tracks in the library whose files may have changed.
+
+#[cfg(feature = "analysis")]
+use crate::analysis;
+use crate::config::{ImportPolicy, Library};
+use crate::covers;
+use crate::database;
+use crate::database::Connection;
+use crate::error::Result;
+use crate::paths;
+use katatsuki::{ToPrimitive, Track};
+use std::path::Path;
+
+/// Whether the audio of the track is unchanged, so that what was learned by
+/// analyzing it still holds.
+fn has_same_audio(track: &Track, new_track: &Track) -> bool {
+    track.file_type.to_i32() == new_track.file_type.to_i32()
+        && track.sample_rate == new_track.sample_rate
+        && track.channels == new_track.channels
+        && track.sample_count == new_track.sample_count
+        && track.duration == new_track.duration
+}
+
+/// Reads the tags of the track again, moving it if they changed, and replaces
+/// it in the database, along with its labels, cover and spectral cutoff.
+/// Returns None if the file of the track no longer exists, in which case it is
+/// removed from the database.
+pub fn refresh_track(track: &Track, library: &Library, policy: &ImportPolicy, conn: &Connection) -> Result<Option<Track>> {
+    let new_track = match paths::reconsider_track(track, Path::new(&library.music_folder), policy)? {
+        Some(new_track) => new_track,
+        None => {
+            database::remove_track(track, conn);
+            return Ok(None);
+        }
+    };
+    let spectral_cutoff = database::get_spectral_cutoff(track, conn).ok().flatten();
+    database::remove_track(track, conn);
+    database::add_track(&new_track, conn);
+    database::set_labels(&new_track, &policy.labels, conn);
+    covers::update_cover_cache(&new_track, conn).ok();
+    if has_same_audio(track, &new_track) {
+        database::set_spectral_cutoff(&new_track, spectral_cutoff, conn);
+    } else {
+        #[cfg(feature = "analysis")]
+        analysis::update_spectral_cutoff(&new_track, conn).ok();
+    }
+    Ok(Some(new_track))
+}
@@ -0,0 +1,93 @@
+//! Runs bangs against tracks in an in-memory database.
+
+use std::path::PathBuf;
+
+use seiri::database;
+use seiri::database::Connection;
+use seiri::{Bang, BitrateMode, CodecProfile, Track, TrackFileType};
+
+fn track(name: &str, file_type: TrackFileType, sample_rate: i32) -> Track {
+    Track {
+        file_path: PathBuf::from(format!("/music/{}.flac", name)),
+        file_type,
+        title: name.to_owned(),
+        artist: "Artist".to_owned(),
+        album_artists: vec!["Artist".to_owned()],
+        album: "Album".to_owned(),
+        year: 2020,
+        track_number: 1,
+        musicbrainz_track_id: None,
+        has_front_cover: false,
+        front_cover_height: 0,
+        front_cover_width: 0,
+        front_cover_mime_type: None,
+        front_cover_size: 0,
+        bitrate: 0,
+        bitrate_mode: BitrateMode::Unknown,
+        codec_profile: CodecProfile::Unknown,
+        encoder: None,
+        channels: 2,
+        sample_rate,
+        source: "Automatically Add to Library".to_owned(),
+        disc_number: 1,
+        duration: 180_000,
+        sample_count: 180 * i64::from(sample_rate),
+        updated: "2020-01-01".to_owned(),
+    }
+}
+
+/// Adds the track with the given spectral cutoff to the database.
+fn add_analyzed(track: &Track, spectral_cutoff: i32, conn: &Connection) {
+    database::add_track(track, conn);
+    database::set_spectral_cutoff(track, Some(spectral_cutoff), conn);
+}
+
+fn titles(query: &str, conn: &Connection) -> Vec<String> {
+    let mut titles = database::query_tracks(Bang::new(query).unwrap(), conn, None, None)
+        .unwrap()
+        .into_iter()
+        .map(|track| track.title)
+        .collect::<Vec<String>>();
+    titles.sort();
+    titles
+}
+
+#[test]
+fn fake_finds_lossy_cutoffs() {
+    let conn = database::get_in_memory_connection("bangs-fake");
+    add_analyzed(&track("mp3-320k", TrackFileType::FLAC16, 44100), 20000, &conn);
+    add_analyzed(&track("mp3-128k", TrackFileType::FLAC16, 44100), 16000, &conn);
+    add_analyzed(&track("cd", TrackFileType::FLAC16, 44100), 21900, &conn);
+    assert_eq!(titles("!fake{true}", &conn), vec!["mp3-128k", "mp3-320k"]);
+    assert_eq!(titles("!fake{false}", &conn), vec!["cd"]);
+}
+
+#[test]
+fn fake_does_not_find_hi_res_masters() {
+    let conn = database::get_in_memory_connection("bangs-fake-hi-res");
+    // Hi-res masters often have nothing above the range of CD audio.
+    add_analyzed(&track("hi-res-96k", TrackFileType::FLAC24, 96000), 22000, &conn);
+    add_analyzed(&track("hi-res-192k", TrackFileType::FLAC24, 192000), 48000, &conn);
+    add_analyzed(&track("upsampled-mp3", TrackFileType::FLAC24, 96000), 19000, &conn);
+    assert_eq!(titles("!fake{true}", &conn), vec!["upsampled-mp3"]);
+    assert_eq!(titles("!fake{false}", &conn), vec!["hi-res-192k", "hi-res-96k"]);
+}
+
+#[test]
+fn duration_uses_sample_count() {
+    let conn = database::get_in_memory_connection("bangs-duration");
+    // 3m 0.68s, with its duration in milliseconds truncated to 3m.
+    let sample_accurate = Track {
+        sample_count: 180 * 44100 + 30000,
+        ..track("sample-accurate", TrackFileType::FLAC16, 44100)
+    };
+    let unknown_samples = Track {
+        duration: 181_000,
+        sample_count: 0,
+        ..track("unknown-samples", TrackFileType::FLAC16, 44100)
+    };
+    database::add_track(&sample_accurate, &conn);
+    database::add_track(&unknown_samples, &conn);
+    assert_eq!(titles("!dgt{3m 500ms}", &conn), vec!["sample-accurate", "unknown-samples"]);
+    assert_eq!(titles("!dlt{3m 900ms}", &conn), vec!["sample-accurate"]);
+}
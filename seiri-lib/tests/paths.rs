@@ -0,0 +1,64 @@
+//! Renders path templates for tracks.
+
+use std::path::PathBuf;
+
+use seiri::paths::render_path_template;
+use seiri::{BitrateMode, CodecProfile, Track, TrackFileType};
+
+fn track(artist: &str, album: &str, title: &str) -> Track {
+    Track {
+        file_path: PathBuf::from("/music/track.flac"),
+        file_type: TrackFileType::FLAC16,
+        title: title.to_owned(),
+        artist: artist.to_owned(),
+        album_artists: vec![artist.to_owned()],
+        album: album.to_owned(),
+        year: 2020,
+        track_number: 1,
+        musicbrainz_track_id: None,
+        has_front_cover: false,
+        front_cover_height: 0,
+        front_cover_width: 0,
+        front_cover_mime_type: None,
+        front_cover_size: 0,
+        bitrate: 0,
+        bitrate_mode: BitrateMode::Unknown,
+        codec_profile: CodecProfile::Unknown,
+        encoder: None,
+        channels: 2,
+        sample_rate: 44100,
+        source: "Bandcamp".to_owned(),
+        disc_number: 1,
+        duration: 180_000,
+        sample_count: 180 * 44100,
+        updated: "2020-01-01".to_owned(),
+    }
+}
+
+#[test]
+fn placeholders_are_replaced() {
+    let track = track("Artist", "Album", "Song");
+    assert_eq!(
+        render_path_template("{albumartists}/{year} - {album}/{disc}-{track} {title}", &track, "Bandcamp"),
+        Some(PathBuf::from("Artist/2020 - Album/1-01 Song"))
+    );
+    assert_eq!(
+        render_path_template("{source}/{unknown} {title", &track, "Bandcamp"),
+        Some(PathBuf::from("Bandcamp/{unknown} {title"))
+    );
+}
+
+#[test]
+fn placeholders_in_tags_are_not_replaced() {
+    let track = track("{title}", "{source} Hits", "Song {track}");
+    assert_eq!(
+        render_path_template("{artist}/{album}/{track} {title}", &track, "Bandcamp"),
+        Some(PathBuf::from("{title}/{source} Hits/01 Song {track}"))
+    );
+}
+
+#[test]
+fn empty_paths_are_not_rendered() {
+    let track = track("Artist", "", "");
+    assert_eq!(render_path_template("{album}/{title}", &track, "Bandcamp"), None);
+}
@@ -2,5 +2,7 @@ const addon = require('../native');
 
 module.exports = {
     queryTracks: addon.queryTracks,
-    refreshTracks: addon.refreshTracks
+    refreshTracks: addon.refreshTracks,
+    getCoverThumbnail: addon.getCoverThumbnail,
+    getLibraries: addon.getLibraries
 };
@@ -1,17 +1,32 @@
 use neon::prelude::*;
 use num_traits::cast::ToPrimitive;
-use seiri::config::get_config;
+use seiri::config::{get_config, Config, Library};
+use seiri::covers;
 use seiri::database;
-use seiri::paths;
+use seiri::refresh;
 use seiri::Bang;
 use seiri::Track;
-use std::path::Path;
+
+/// Gets the library named by the optional argument at the given index, or the default library.
+fn get_library(ctx: &mut FunctionContext, i: i32, config: &Config) -> NeonResult<Library> {
+    let name = match ctx.argument_opt(i) {
+        Some(name) => match name.downcast::<JsString, _>(ctx) {
+            Ok(name) => name.value(ctx),
+            Err(_) => return Ok(config.default_library()),
+        },
+        None => return Ok(config.default_library()),
+    };
+    match config.library(&name) {
+        Some(library) => Ok(library),
+        None => ctx.throw_error(format!("Unknown library {}", name)),
+    }
+}
 
 #[allow(non_snake_case)]
 fn refresh_tracks(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
     let config = get_config().unwrap();
-    let conn = database::get_database_connection();
-    let library_path = Path::new(&config.music_folder);
+    let library = get_library(&mut ctx, 1, &config)?;
+    let conn = database::get_library_connection(&library);
 
     let args = ctx.argument::<JsArray>(0)?;
 
@@ -29,15 +44,13 @@ fn refresh_tracks(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
         let tracks = database::query_tracks(Bang::FilePath(file.clone()), &conn, None, None);
         if let Ok(tracks) = tracks {
             if let Some(track) = tracks.into_iter().next() {
-                match paths::reconsider_track(&track, &library_path) {
+                let policy = config.import_policy(&track.source);
+                match refresh::refresh_track(&track, &library, &policy, &conn) {
                     Ok(Some(new_track)) => {
                         println!("RECONSIDERED OK {:?}", new_track);
-                        database::remove_track(&track, &conn);
-                        database::add_track(&new_track, &conn);
                     }
                     Ok(None) => {
                         println!("RECONSIDERED NOT FOUND {:?}", track);
-                        database::remove_track(&track, &conn);
                     }
                     Err(_) => {
                         println!(
@@ -57,9 +70,11 @@ fn query_tracks(mut ctx: FunctionContext) -> JsResult<JsObject> {
     let ret = ctx.empty_object();
 
     let query = ctx.argument::<JsString>(0)?.value(&mut ctx);
+    let config = get_config().unwrap();
+    let library = get_library(&mut ctx, 1, &config)?;
 
     let bang = Bang::new(&query).unwrap();
-    let conn = database::get_database_connection();
+    let conn = database::get_library_connection(&library);
     let results = database::query_tracks(bang, &conn, None, None);
 
     let result: JsResult<JsObject> = match results {
@@ -111,9 +126,43 @@ fn query_tracks(mut ctx: FunctionContext) -> JsResult<JsObject> {
                 let frontCoverWidth = ctx.number(track.front_cover_width);
                 jsTrack.set(&mut ctx, "frontCoverWidth", frontCoverWidth)?;
         
+                match &track.front_cover_mime_type {
+                    Some(mime_type) => {
+                        let mimeType = ctx.string(mime_type);
+                        jsTrack.set(&mut ctx, "frontCoverMimeType", mimeType)
+                    }
+                    None => {
+                        let null = ctx.null();
+                        jsTrack.set(&mut ctx, "frontCoverMimeType", null)
+                    }
+                }?;
+        
+                let frontCoverSize = ctx.number(track.front_cover_size);
+                jsTrack.set(&mut ctx, "frontCoverSize", frontCoverSize)?;
+        
                 let bitrate = ctx.number(track.bitrate);
                 jsTrack.set(&mut ctx, "bitrate", bitrate)?;
         
+                let bitrateMode = ctx.number(track.bitrate_mode.to_i32().unwrap());
+                jsTrack.set(&mut ctx, "bitrateMode", bitrateMode)?;
+        
+                let codecProfile = ctx.number(track.codec_profile.to_i32().unwrap());
+                jsTrack.set(&mut ctx, "codecProfile", codecProfile)?;
+        
+                match &track.encoder {
+                    Some(encoder) => {
+                        let encoder = ctx.string(encoder);
+                        jsTrack.set(&mut ctx, "encoder", encoder)
+                    }
+                    None => {
+                        let null = ctx.null();
+                        jsTrack.set(&mut ctx, "encoder", null)
+                    }
+                }?;
+        
+                let channels = ctx.number(track.channels);
+                jsTrack.set(&mut ctx, "channels", channels)?;
+        
                 let sampleRate = ctx.number(track.sample_rate);
                 jsTrack.set(&mut ctx, "sampleRate", sampleRate)?;
         
@@ -123,9 +172,12 @@ fn query_tracks(mut ctx: FunctionContext) -> JsResult<JsObject> {
                 let discNumber = ctx.number(track.disc_number);
                 jsTrack.set(&mut ctx, "discNumber", discNumber)?;
         
-                let duration = ctx.number(track.duration);
+                let duration = ctx.number(track.duration as f64);
                 jsTrack.set(&mut ctx, "duration", duration)?;
         
+                let sampleCount = ctx.number(track.sample_count as f64);
+                jsTrack.set(&mut ctx, "sampleCount", sampleCount)?;
+        
                 let fileType = ctx.number(track.file_type.to_i32().unwrap());
                 jsTrack.set(&mut ctx, "fileType", fileType)?;
         
@@ -144,8 +196,41 @@ fn query_tracks(mut ctx: FunctionContext) -> JsResult<JsObject> {
     result
 }
 
+fn get_cover_thumbnail(mut ctx: FunctionContext) -> JsResult<JsValue> {
+    let file_path = ctx.argument::<JsString>(0)?.value(&mut ctx);
+    let config = get_config().unwrap();
+    let library = get_library(&mut ctx, 1, &config)?;
+    let conn = database::get_library_connection(&library);
+    let tracks = database::query_tracks(Bang::FilePath(file_path), &conn, None, None);
+
+    let thumbnail = match tracks {
+        Ok(tracks) => match tracks.into_iter().next() {
+            Some(track) => covers::get_cover_thumbnail(&track, &conn).unwrap_or(None),
+            None => None,
+        },
+        Err(e) => return ctx.throw_error(e.to_string()),
+    };
+
+    match thumbnail {
+        Some(thumbnail) => Ok(ctx.string(thumbnail.to_string_lossy()).upcast()),
+        None => Ok(ctx.null().upcast()),
+    }
+}
+
+fn get_libraries(mut ctx: FunctionContext) -> JsResult<JsArray> {
+    let config = get_config().unwrap();
+    let libraries = ctx.empty_array();
+    for (i, library) in config.libraries().into_iter().enumerate() {
+        let name = ctx.string(&library.name);
+        libraries.set(&mut ctx, i as u32, name)?;
+    }
+    Ok(libraries)
+}
+
 register_module!(mut m, {
     m.export_function("queryTracks", query_tracks)?;
     m.export_function("refreshTracks", refresh_tracks)?;
+    m.export_function("getCoverThumbnail", get_cover_thumbnail)?;
+    m.export_function("getLibraries", get_libraries)?;
     Ok(())
 });
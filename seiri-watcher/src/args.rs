@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Usage: seiri-watcher [OPTIONS]
+
+Options:
+    --config <PATH>              Read the configuration from PATH instead of config.toml
+    --data-dir <PATH>            Keep the databases and cover cache in PATH
+    --music-folder <PATH>        Override music_folder
+    --auto-add-folder <PATH>     Override auto_add_folder
+    --not-added-folder <PATH>    Override not_added_folder
+    --database-path <PATH>       Override database_path, or :memory: to keep the
+                                 database in memory
+    --verify-imports [BOOL]      Override verify_imports
+    --required-tags <TAGS>       Override required_tags, separated by commas
+    --album-artist-fallback [BOOL]
+                                 Override album_artist_fallback
+    --http-port <PORT>           Override http_port, serving the HTTP API on
+                                 PORT of localhost
+    --import-concurrency <N>     Override import_concurrency
+    --import-queue-size <N>      Override import_queue_size
+    --settle-time <SECONDS>      Override settle_time
+    --watch-library [BOOL]       Override watch_library
+    --watch-mode <MODE>          Override watch_mode, one of auto, events or poll
+    --poll-interval <SECONDS>    Override poll_interval
+    --album-staging [BOOL]       Override album_staging
+    --album-quiet-time <SECONDS> Override album_quiet_time
+    --command <COMMAND>          Send COMMAND to the running watcher and print
+                                 its reply, such as --command 'query !f{flac}'.
+                                 Can be given more than once
+    --legacy-events              Write events to stderr as CODE::payload lines
+                                 instead of JSON lines to stdout
+    --help                       Print this message
+
+Settings can also be overridden with environment variables, such as
+SEIRI_MUSIC_FOLDER. SEIRI_CONFIG and SEIRI_DATA_DIR set the configuration
+path and the data folder. Command line arguments take precedence.";
+
+/// The command line arguments of seiri-watcher.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub config_path: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+    /// Settings that override the configuration file, by name.
+    pub overrides: Vec<(String, String)>,
+    /// Commands to send to the running watcher.
+    pub commands: Vec<String>,
+    /// Whether events are written in the legacy `CODE::payload` format.
+    pub legacy_events: bool,
+    pub help: bool,
+}
+
+/// Parses the arguments, not including the name of the program. Options are
+/// given as `--name value` or `--name=value`, and options without a value
+/// are set to `true`.
+pub fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        let option = match arg.strip_prefix("--") {
+            Some(option) => option,
+            None => return Err(format!("Unexpected argument {}", arg)),
+        };
+        let (name, value) = match option.find('=') {
+            Some(index) => (option[..index].to_owned(), option[index + 1..].to_owned()),
+            None => {
+                let value = match args.peek() {
+                    Some(value) if !value.starts_with("--") => args.next().unwrap(),
+                    _ => "true".to_owned(),
+                };
+                (option.to_owned(), value)
+            }
+        };
+        match name.as_str() {
+            "help" => parsed.help = true,
+            "config" => parsed.config_path = Some(PathBuf::from(value)),
+            "data-dir" => parsed.data_dir = Some(PathBuf::from(value)),
+            "command" => parsed.commands.push(value),
+            "legacy-events" => parsed.legacy_events = value != "false",
+            _ => parsed.overrides.push((name.replace('-', "_"), value)),
+        }
+    }
+    Ok(parsed)
+}
@@ -0,0 +1,315 @@
+//! The command protocol of the watcher.
+//!
+//! Commands are read one per line from stdin, or from connections to the control
+//! endpoint. A request is a JSON object with the name of the `command`, an optional
+//! `id` that is echoed in the response, and the arguments of the command. For use
+//! from a terminal, a request can also be given as the name of the command followed
+//! by its argument, such as `query !f{flac}`.
+//!
+//! Every request is answered with one line of JSON, with `type` set to `response`,
+//! the `id` of the request, whether the command succeeded in `ok`, and either its
+//! `result` or an `error` with a `message`.
+use crate::events::{ErrorDetails, PROTOCOL_VERSION};
+use crate::library;
+use crate::pools::LibraryPools;
+use crate::reload::SharedConfig;
+use crate::watcher::WatcherControl;
+use num_traits::cast::ToPrimitive;
+use seiri::config::Library;
+use seiri::database;
+use seiri::database::{AlbumSummary, ArtistSummary, PooledConnection};
+use seiri::paths;
+use seiri::Bang;
+use seiri::Track;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::process;
+use std::sync::Arc;
+
+/// The state of the watcher that commands act on.
+#[derive(Clone)]
+pub struct CommandContext {
+    pub config: Arc<SharedConfig>,
+    pub control: Arc<WatcherControl>,
+    pub pools: Arc<LibraryPools>,
+}
+
+impl CommandContext {
+    fn connection(&self, library: &Library) -> Result<PooledConnection, String> {
+        self.pools.get(library).get().map_err(|err| err.to_string())
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Request {
+    pub id: Option<Value>,
+    pub command: String,
+    /// The library to run the command on, instead of the library of the session.
+    pub library: Option<String>,
+    pub query: Option<String>,
+    pub path: Option<String>,
+    pub limit: Option<i32>,
+    pub offset: Option<i32>,
+}
+
+impl Request {
+    /// Parses a request from a JSON object, or from the name of a command
+    /// followed by its argument.
+    pub fn parse(line: &str) -> Result<Request, String> {
+        let line = line.trim();
+        if line.starts_with('{') {
+            return serde_json::from_str(line).map_err(|err| format!("Invalid request: {}", err));
+        }
+        let mut parts = line.splitn(2, ' ');
+        let command = parts.next().unwrap_or("").to_lowercase();
+        let argument = parts.next().map(|argument| argument.trim().to_owned());
+        let mut request = Request {
+            command,
+            ..Request::default()
+        };
+        match request.command.as_str() {
+            "query" | "count" | "albums" | "artists" => request.query = argument,
+            "refresh" => request.path = argument,
+            "rescan" | "library" => request.library = argument,
+            _ => (),
+        }
+        Ok(request)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Response {
+    version: u32,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    id: Option<Value>,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorDetails>,
+}
+
+impl Response {
+    pub fn new(id: Option<Value>, result: Result<Value, String>) -> Response {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(message) => (None, Some(ErrorDetails { message })),
+        };
+        Response {
+            version: PROTOCOL_VERSION,
+            kind: "response",
+            id,
+            ok: error.is_none(),
+            result,
+            error,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// The state of one connection, or of stdin.
+pub struct Session {
+    /// The library that commands run on when the request does not name one.
+    library: Library,
+}
+
+impl Session {
+    pub fn new(context: &CommandContext) -> Session {
+        Session {
+            library: context.config.get().default_library(),
+        }
+    }
+}
+
+/// Whether the watcher should exit after the command.
+pub fn is_shutdown(request: &Request) -> bool {
+    request.command == "shutdown" || request.command == "exit"
+}
+
+/// Runs the command, returning its result.
+pub fn handle(request: &Request, session: &mut Session, context: &CommandContext) -> Result<Value, String> {
+    let library = match &request.library {
+        Some(name) => context
+            .config
+            .get()
+            .library(name)
+            .ok_or_else(|| format!("Unknown library {}", name))?,
+        None => session.library.clone(),
+    };
+    match request.command.as_str() {
+        "query" => {
+            let bang = Bang::new(request.query.as_deref().unwrap_or("")).map_err(|err| err.to_string())?;
+            let conn = context.connection(&library)?;
+            let tracks = database::query_tracks(bang, &conn, request.limit, request.offset)
+                .map_err(|err| err.to_string())?;
+            Ok(json!({
+                "library": library.name,
+                "tracks": tracks.iter().map(track_to_json).collect::<Vec<Value>>(),
+            }))
+        }
+        "count" => {
+            let bang = Bang::new(request.query.as_deref().unwrap_or("")).map_err(|err| err.to_string())?;
+            let conn = context.connection(&library)?;
+            let count = database::count_tracks(bang, &conn).map_err(|err| err.to_string())?;
+            Ok(json!({ "library": library.name, "count": count }))
+        }
+        "albums" => {
+            let bang = Bang::new(request.query.as_deref().unwrap_or("")).map_err(|err| err.to_string())?;
+            let conn = context.connection(&library)?;
+            let albums = database::query_albums(bang, &conn).map_err(|err| err.to_string())?;
+            Ok(json!({
+                "library": library.name,
+                "albums": albums.iter().map(album_to_json).collect::<Vec<Value>>(),
+            }))
+        }
+        "artists" => {
+            let bang = Bang::new(request.query.as_deref().unwrap_or("")).map_err(|err| err.to_string())?;
+            let conn = context.connection(&library)?;
+            let artists = database::query_artists(bang, &conn).map_err(|err| err.to_string())?;
+            Ok(json!({
+                "library": library.name,
+                "artists": artists.iter().map(artist_to_json).collect::<Vec<Value>>(),
+            }))
+        }
+        "stats" => {
+            let conn = context.connection(&library)?;
+            let stats = database::get_library_stats(&conn).map_err(|err| err.to_string())?;
+            Ok(json!({
+                "library": library.name,
+                "tracks": stats.track_count,
+                "albums": stats.album_count,
+                "artists": stats.artist_count,
+                "duration": stats.duration,
+            }))
+        }
+        "refresh" => {
+            let path = request.path.as_deref().ok_or("refresh requires a path")?;
+            refresh(path, &library, context)
+        }
+        "rescan" => {
+            context.control.rescan(request.library.clone());
+            Ok(json!({ "library": request.library }))
+        }
+        "pause" => {
+            context.control.pause();
+            Ok(json!({ "paused": true }))
+        }
+        "resume" => {
+            context.control.resume();
+            Ok(json!({ "paused": false }))
+        }
+        "status" => Ok(status(context)),
+        "library" => {
+            session.library = library;
+            Ok(json!({ "library": session.library.name }))
+        }
+        "shutdown" | "exit" => Ok(Value::Null),
+        "" => Err("No command was given".to_owned()),
+        command => Err(format!("Unknown command {}", command)),
+    }
+}
+
+/// Reads the tags of the track at the path again, moving it if its tags changed.
+fn refresh(path: &str, library: &Library, context: &CommandContext) -> Result<Value, String> {
+    let config = context.config.get();
+    let conn = context.connection(library)?;
+    let tracks = database::query_tracks(Bang::FilePath(path.to_owned()), &conn, None, None)
+        .map_err(|err| err.to_string())?;
+    let track = tracks
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("The track {} is not in the library", path))?;
+    match library::reconsider(&track, library, &config, &conn) {
+        Ok(Some(new_track)) => Ok(json!({ "track": track_to_json(&new_track) })),
+        // The track no longer exists, so it was removed from the library.
+        Ok(None) => Ok(json!({ "track": Value::Null })),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn status(context: &CommandContext) -> Value {
+    let libraries = context
+        .config
+        .get()
+        .libraries()
+        .into_iter()
+        .map(|library| {
+            let folders = paths::ensure_library_folders(&library).ok();
+            let tracks = context
+                .connection(&library)
+                .ok()
+                .and_then(|conn| database::count_tracks(Bang::All, &conn).ok());
+            json!({
+                "name": library.name,
+                "music_folder": library.music_folder,
+                "auto_add_folder": folders.as_ref().map(|folders| folders.auto_add_folder.display().to_string()),
+                "available": folders.is_some(),
+                "tracks": tracks,
+            })
+        })
+        .collect::<Vec<Value>>();
+    let (queued, active) = context.control.queue.depth();
+    json!({
+        "pid": process::id(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "protocol_version": PROTOCOL_VERSION,
+        "paused": context.control.is_paused(),
+        "queued": queued,
+        "importing": active,
+        "libraries": libraries,
+    })
+}
+
+/// Converts the track to JSON, with the same fields as the tracks returned by seiri-neon.
+fn track_to_json(track: &Track) -> Value {
+    json!({
+        "filePath": track.file_path.display().to_string(),
+        "title": track.title,
+        "artist": track.artist,
+        "albumArtists": track.album_artists,
+        "album": track.album,
+        "year": track.year,
+        "trackNumber": track.track_number,
+        "musicbrainzTrackId": track.musicbrainz_track_id,
+        "hasFrontCover": track.has_front_cover,
+        "frontCoverHeight": track.front_cover_height,
+        "frontCoverWidth": track.front_cover_width,
+        "frontCoverMimeType": track.front_cover_mime_type,
+        "frontCoverSize": track.front_cover_size,
+        "bitrate": track.bitrate,
+        "bitrateMode": track.bitrate_mode.to_i32(),
+        "codecProfile": track.codec_profile.to_i32(),
+        "encoder": track.encoder,
+        "channels": track.channels,
+        "sampleRate": track.sample_rate,
+        "source": track.source,
+        "discNumber": track.disc_number,
+        "duration": track.duration,
+        "sampleCount": track.sample_count,
+        "fileType": track.file_type.to_i32(),
+        "updated": track.updated,
+    })
+}
+
+fn album_to_json(album: &AlbumSummary) -> Value {
+    json!({
+        "album": album.album,
+        "albumArtists": album.album_artists,
+        "year": album.year,
+        "trackCount": album.track_count,
+        "duration": album.duration,
+    })
+}
+
+fn artist_to_json(artist: &ArtistSummary) -> Value {
+    json!({
+        "albumArtists": artist.album_artists,
+        "albumCount": artist.album_count,
+        "trackCount": artist.track_count,
+    })
+}
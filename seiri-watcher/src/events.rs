@@ -0,0 +1,191 @@
+//! Events reported by the watcher.
+//!
+//! By default, events are written to stdout as JSON lines, one object per event.
+//! Every object carries the `version` of the protocol, its `type`, the legacy
+//! `code` of the event if it has one, and a `timestamp`, along with the `path`
+//! and `library` it concerns, an `error` with a `message` for errors, and other
+//! `details` of the event. With `--legacy-events`, events are written to stderr
+//! in the `CODE::payload` format described in events.md instead.
+use chrono::{SecondsFormat, Utc};
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The version of the JSON event protocol.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+static LEGACY_FORMAT: AtomicBool = AtomicBool::new(false);
+
+/// Writes events in the legacy `CODE::payload` format instead of JSON.
+pub fn use_legacy_format(legacy: bool) {
+    LEGACY_FORMAT.store(legacy, Ordering::SeqCst);
+}
+
+pub fn is_legacy_format() -> bool {
+    LEGACY_FORMAT.load(Ordering::SeqCst)
+}
+
+/// Writes a message that is not an event. Messages are written to stdout in
+/// the legacy format, and to stderr otherwise, so that stdout only has events.
+pub fn log(message: &str) {
+    if is_legacy_format() {
+        println!("{}", message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+    TrackAdded,
+    TrackUpdated,
+    TrackRemoved,
+    TrackError,
+    TrackMoveError,
+    CreateDirectoryError,
+    NonTrack,
+    MissingTag,
+    CoverCacheError,
+    AnalysisError,
+    Corrupt,
+    Rejected,
+    AlbumAdded,
+    AlbumRejected,
+    LibraryNotFound,
+    Watching,
+    WatcherError,
+    WatcherDied,
+    WatcherRestart,
+    WatcherNoAccess,
+    ConfigReloaded,
+    ConfigInvalid,
+    ConfigIoError,
+    ConfigNoHome,
+    NoLock,
+    ControlError,
+    HttpListening,
+    HttpError,
+    ImportQueue,
+}
+
+impl EventType {
+    /// The code of the event in the legacy format. Events without a code
+    /// are written to stdout as plain messages in the legacy format.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            EventType::TrackAdded => Some("TRACKADDED"),
+            EventType::TrackUpdated => Some("TRACKUPDATED"),
+            EventType::TrackRemoved => Some("TRACKREMOVED"),
+            EventType::TrackError => Some("ETRACK"),
+            EventType::TrackMoveError => Some("ETRACKMOVE"),
+            EventType::CreateDirectoryError => Some("ECREATEDIRECTORY"),
+            EventType::NonTrack => Some("ENONTRACK"),
+            EventType::MissingTag => Some("EMISSINGTAG"),
+            EventType::CoverCacheError => Some("ECOVERCACHE"),
+            EventType::AnalysisError => Some("EANALYSIS"),
+            EventType::Corrupt => Some("ECORRUPT"),
+            EventType::Rejected => Some("EREJECTED"),
+            EventType::AlbumAdded => Some("ALBUMADDED"),
+            EventType::AlbumRejected => Some("EALBUMREJECTED"),
+            EventType::LibraryNotFound => Some("ELIBRARYNOTFOUND"),
+            EventType::Watching => None,
+            EventType::WatcherError => Some("EWATCHER"),
+            EventType::WatcherDied => Some("EWATCHERDIED"),
+            EventType::WatcherRestart => Some("EWATCHERRESTART"),
+            EventType::WatcherNoAccess => Some("EWATCHERNOACCESS"),
+            EventType::ConfigReloaded => None,
+            EventType::ConfigInvalid => Some("ECONFIGINVALID"),
+            EventType::ConfigIoError => Some("ECONFIGIO"),
+            EventType::ConfigNoHome => Some("ECONFIGNOHOME"),
+            EventType::NoLock => Some("ENOLOCK"),
+            EventType::ControlError => Some("ECONTROL"),
+            EventType::HttpListening => None,
+            EventType::HttpError => Some("EHTTP"),
+            EventType::ImportQueue => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ErrorDetails {
+    pub message: String,
+}
+
+/// An event, built with the path, library, error and details it concerns,
+/// then written with `emit`.
+#[derive(Serialize, Debug)]
+pub struct Event {
+    version: u32,
+    #[serde(rename = "type")]
+    kind: EventType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    library: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorDetails>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    details: BTreeMap<&'static str, String>,
+    /// The payload of the event in the legacy format.
+    #[serde(skip)]
+    legacy_payload: String,
+}
+
+impl Event {
+    pub fn new<S: Into<String>>(kind: EventType, legacy_payload: S) -> Event {
+        Event {
+            version: PROTOCOL_VERSION,
+            kind,
+            code: kind.code(),
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            path: None,
+            library: None,
+            error: None,
+            details: BTreeMap::new(),
+            legacy_payload: legacy_payload.into(),
+        }
+    }
+
+    pub fn path(mut self, path: &Path) -> Event {
+        self.path = Some(path.to_owned());
+        self
+    }
+
+    pub fn library(mut self, library: &str) -> Event {
+        self.library = Some(library.to_owned());
+        self
+    }
+
+    pub fn error<S: ToString>(mut self, message: S) -> Event {
+        self.error = Some(ErrorDetails {
+            message: message.to_string(),
+        });
+        self
+    }
+
+    pub fn detail<S: Into<String>>(mut self, key: &'static str, value: S) -> Event {
+        self.details.insert(key, value.into());
+        self
+    }
+
+    pub fn emit(self) {
+        if is_legacy_format() {
+            match self.code {
+                Some(code) => eprintln!("{}::{}", code, self.legacy_payload),
+                None => println!("{}", self.legacy_payload),
+            }
+        } else if let Ok(line) = serde_json::to_string(&self) {
+            // Lock stdout so that events from different threads are not interleaved.
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            writeln!(stdout, "{}", line).ok();
+            stdout.flush().ok();
+        }
+    }
+}
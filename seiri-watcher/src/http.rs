@@ -0,0 +1,248 @@
+//! The HTTP API of the watcher, bound to localhost.
+//!
+//! Every endpoint runs the command of the same name from the command protocol,
+//! with its arguments taken from the query string, or from a JSON object in the
+//! body of POST requests. The query bang is given as `q`. Successful commands
+//! respond with their result, and failed commands with a 400 status and an
+//! `error` with a `message`.
+//!
+//! So that web pages in a browser can not use the API, requests must be
+//! addressed to localhost by their `Host` header, which defeats DNS rebinding,
+//! requests that browsers mark with an `Origin` must come from a page on
+//! localhost, and POST requests must be sent as `application/json` even
+//! without a body, which pages can not send to another origin without the API
+//! allowing it.
+use crate::commands;
+use crate::commands::{CommandContext, Request, Session};
+use serde_json::{json, Value};
+use std::thread;
+use tiny_http::{Header, Method, Response, Server};
+
+/// Gets the command of the endpoint, or the status to respond with if there is none.
+fn route(method: &Method, path: &str) -> Result<&'static str, u16> {
+    let (command, expected) = match path.trim_end_matches('/') {
+        "/tracks" => ("query", Method::Get),
+        "/count" => ("count", Method::Get),
+        "/albums" => ("albums", Method::Get),
+        "/artists" => ("artists", Method::Get),
+        "/stats" => ("stats", Method::Get),
+        "/status" => ("status", Method::Get),
+        "/refresh" => ("refresh", Method::Post),
+        "/rescan" => ("rescan", Method::Post),
+        "/pause" => ("pause", Method::Post),
+        "/resume" => ("resume", Method::Post),
+        _ => return Err(404),
+    };
+    if *method == expected {
+        Ok(command)
+    } else {
+        Err(405)
+    }
+}
+
+fn parse_request(command: &str, query_string: &str, body: &str) -> Result<Request, String> {
+    let mut request = if body.trim().is_empty() {
+        Request::default()
+    } else {
+        serde_json::from_str(body).map_err(|err| format!("Invalid request: {}", err))?
+    };
+    request.command = command.to_owned();
+    for (name, value) in form_urlencoded::parse(query_string.as_bytes()) {
+        let value = value.into_owned();
+        let invalid = || format!("Invalid value for {}", name);
+        match name.as_ref() {
+            "q" => request.query = Some(value),
+            "library" => request.library = Some(value),
+            "path" => request.path = Some(value),
+            "limit" => request.limit = Some(value.parse().map_err(|_| invalid())?),
+            "offset" => request.offset = Some(value.parse().map_err(|_| invalid())?),
+            _ => (),
+        }
+    }
+    Ok(request)
+}
+
+fn error(message: impl Into<String>) -> Value {
+    json!({ "error": { "message": message.into() } })
+}
+
+/// Gets the value of the header with the given name.
+fn header<'a>(headers: &'a [Header], name: &'static str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Gets whether the request is addressed to the API on localhost.
+fn is_local_host(headers: &[Header], port: u16) -> bool {
+    match header(headers, "Host") {
+        Some(host) => {
+            host.eq_ignore_ascii_case(&format!("127.0.0.1:{}", port))
+                || host.eq_ignore_ascii_case(&format!("localhost:{}", port))
+        }
+        None => false,
+    }
+}
+
+/// Gets whether the request was not sent by a page, or was sent by a page on
+/// localhost. Pages in sandboxed frames and local files send an `Origin` of
+/// `null`, which is not on localhost.
+fn is_local_origin(headers: &[Header]) -> bool {
+    let origin = match header(headers, "Origin") {
+        Some(origin) => origin,
+        None => return true,
+    };
+    let authority = match origin.split_once("://") {
+        Some((_, authority)) => authority,
+        None => return false,
+    };
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) if port.bytes().all(|byte| byte.is_ascii_digit()) => host,
+        _ => authority,
+    };
+    ["localhost", "127.0.0.1", "[::1]"]
+        .iter()
+        .any(|local| host.eq_ignore_ascii_case(local))
+}
+
+fn is_json(headers: &[Header]) -> bool {
+    header(headers, "Content-Type")
+        .and_then(|content_type| content_type.split(';').next())
+        .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case("application/json"))
+}
+
+/// Checks that the request did not come from a web page on another origin,
+/// returning the status and message to respond with if it might have.
+fn check(method: &Method, headers: &[Header], port: u16) -> Result<(), (u16, String)> {
+    if !is_local_host(headers, port) {
+        return Err((403, format!("Requests must be addressed to localhost:{}", port)));
+    }
+    if !is_local_origin(headers) {
+        return Err((403, "Requests from web pages must come from localhost".to_owned()));
+    }
+    if *method == Method::Post && !is_json(headers) {
+        return Err((415, "POST requests must be sent as application/json".to_owned()));
+    }
+    Ok(())
+}
+
+fn respond(mut http_request: tiny_http::Request, port: u16, context: &CommandContext) {
+    let (path, query_string) = {
+        let url = http_request.url();
+        match url.find('?') {
+            Some(index) => (url[..index].to_owned(), url[index + 1..].to_owned()),
+            None => (url.to_owned(), String::new()),
+        }
+    };
+    let checked = check(http_request.method(), http_request.headers(), port);
+    let (status, body) = match checked.map(|_| route(http_request.method(), &path)) {
+        Err((status, message)) => (status, error(message)),
+        Ok(Ok(command)) => {
+            let mut body = String::new();
+            match http_request.as_reader().read_to_string(&mut body) {
+                Err(err) => (400, error(err.to_string())),
+                Ok(_) if !body.is_empty() && !is_json(http_request.headers()) => {
+                    (415, error("The body must be sent as application/json"))
+                }
+                Ok(_) => {
+                    let result = parse_request(command, &query_string, &body)
+                        .and_then(|request| commands::handle(&request, &mut Session::new(context), context));
+                    match result {
+                        Ok(result) => (200, result),
+                        Err(message) => (400, error(message)),
+                    }
+                }
+            }
+        }
+        Ok(Err(404)) => (404, error(format!("Unknown endpoint {}", path))),
+        Ok(Err(status)) => (status, error("Method not allowed")),
+    };
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(Value::to_string(&body))
+        .with_status_code(status)
+        .with_header(content_type);
+    http_request.respond(response).ok();
+}
+
+/// Binds the HTTP API to the port of localhost.
+pub fn listen(port: u16) -> Result<Server, String> {
+    Server::http(("127.0.0.1", port)).map_err(|err| err.to_string())
+}
+
+/// Serves the HTTP API, handling each request on its own thread.
+pub fn serve(server: Server, context: CommandContext) {
+    let port = server.server_addr().to_ip().map_or(0, |addr| addr.port());
+    for request in server.incoming_requests() {
+        let context = context.clone();
+        thread::spawn(move || respond(request, port, &context));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(headers: &[(&str, &str)]) -> Vec<Header> {
+        headers
+            .iter()
+            .map(|(field, value)| Header::from_bytes(field.as_bytes(), value.as_bytes()).unwrap())
+            .collect()
+    }
+
+    fn status(method: Method, request_headers: &[(&str, &str)]) -> u16 {
+        match check(&method, &headers(request_headers), 9090) {
+            Ok(()) => 200,
+            Err((status, _)) => status,
+        }
+    }
+
+    #[test]
+    fn requests_must_be_addressed_to_localhost() {
+        assert_eq!(status(Method::Get, &[("Host", "127.0.0.1:9090")]), 200);
+        assert_eq!(status(Method::Get, &[("Host", "LOCALHOST:9090")]), 200);
+        assert_eq!(status(Method::Get, &[("Host", "evil.example:9090")]), 403);
+        assert_eq!(status(Method::Get, &[("Host", "localhost:9091")]), 403);
+        assert_eq!(status(Method::Get, &[]), 403);
+    }
+
+    #[test]
+    fn post_without_a_body_must_be_json() {
+        // A page can send a POST without a body or a Content-Type to any origin.
+        assert_eq!(status(Method::Post, &[("Host", "127.0.0.1:9090")]), 415);
+        assert_eq!(
+            status(Method::Post, &[("Host", "127.0.0.1:9090"), ("Content-Type", "text/plain")]),
+            415
+        );
+        assert_eq!(
+            status(
+                Method::Post,
+                &[("Host", "127.0.0.1:9090"), ("Content-Type", "application/json; charset=utf-8")]
+            ),
+            200
+        );
+    }
+
+    #[test]
+    fn requests_from_pages_must_come_from_localhost() {
+        let from = |origin| status(Method::Get, &[("Host", "127.0.0.1:9090"), ("Origin", origin)]);
+        assert_eq!(from("http://localhost:3000"), 200);
+        assert_eq!(from("http://127.0.0.1"), 200);
+        assert_eq!(from("http://[::1]:8080"), 200);
+        assert_eq!(from("https://evil.example"), 403);
+        assert_eq!(from("http://localhost.evil.example"), 403);
+        assert_eq!(from("http://127.0.0.1.evil.example:9090"), 403);
+        assert_eq!(from("null"), 403);
+        assert_eq!(
+            status(
+                Method::Post,
+                &[
+                    ("Host", "127.0.0.1:9090"),
+                    ("Content-Type", "application/json"),
+                    ("Origin", "https://evil.example"),
+                ]
+            ),
+            403
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Ensures that only one watcher runs for a data folder, and provides a control
+//! endpoint that other invocations of seiri-watcher can send commands to.
+//!
+//! The endpoint is a Unix socket on Unix, and a TCP socket bound to localhost on
+//! Windows, whose port is written to the data folder.
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+#[cfg(unix)]
+pub use std::os::unix::net::UnixListener as ControlListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream as ControlStream;
+#[cfg(windows)]
+pub use std::net::TcpListener as ControlListener;
+#[cfg(windows)]
+use std::net::TcpStream as ControlStream;
+
+const LOCK_FILE: &str = "seiri.lock";
+#[cfg(unix)]
+const CONTROL_SOCKET: &str = "seiri.sock";
+#[cfg(windows)]
+const CONTROL_PORT_FILE: &str = "seiri.port";
+
+/// How long a lock is held by a watcher that has taken it, but has not bound
+/// its control endpoint yet.
+const STARTUP_TIME: Duration = Duration::from_secs(10);
+
+/// The lock held by the running watcher. The lock file, and the control endpoint,
+/// are removed when the lock is dropped.
+pub struct InstanceLock {
+    data_dir: PathBuf,
+    listener: Option<ControlListener>,
+}
+
+impl InstanceLock {
+    /// Takes the control endpoint, which is bound when the lock is acquired so
+    /// that other watchers can tell that the lock is held.
+    pub fn take_listener(&mut self) -> Option<ControlListener> {
+        self.listener.take()
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        remove_endpoint(&self.data_dir);
+        fs::remove_file(self.data_dir.join(LOCK_FILE)).ok();
+    }
+}
+
+pub enum LockError {
+    /// Another watcher is running, with the given process ID if it could be read.
+    AlreadyRunning(Option<u32>),
+    /// The control endpoint could not be bound.
+    Endpoint(io::Error),
+    Io(io::Error),
+}
+
+fn read_lock_pid(lock_path: &Path) -> Option<u32> {
+    fs::read_to_string(lock_path).ok()?.trim().parse().ok()
+}
+
+/// Gets whether the lock is held by a running watcher, which is when its control
+/// endpoint accepts connections, or the lock was taken too recently for the
+/// endpoint to be bound. Process IDs are not checked, since the ID of a watcher
+/// that crashed can be reused by another process.
+fn is_held(lock_path: &Path, data_dir: &Path) -> bool {
+    if connect(data_dir).is_ok() {
+        return true;
+    }
+    fs::metadata(lock_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < STARTUP_TIME)
+}
+
+/// Creates the lock file with the ID of this process. The ID is written to a
+/// file of its own, which is then linked as the lock file, so that the lock
+/// file is never seen without it.
+fn create_lock(lock_path: &Path, data_dir: &Path) -> io::Result<()> {
+    let pid_path = data_dir.join(format!("{}.{}", LOCK_FILE, process::id()));
+    fs::write(&pid_path, format!("{}\n", process::id()))?;
+    let result = fs::hard_link(&pid_path, lock_path);
+    fs::remove_file(&pid_path).ok();
+    result
+}
+
+/// Acquires the lock on the data folder, writing the ID of this process to the
+/// lock file and binding the control endpoint. A lock file left behind by a
+/// watcher that is no longer running is taken over.
+pub fn acquire_lock(data_dir: &Path) -> Result<InstanceLock, LockError> {
+    let lock_path = data_dir.join(LOCK_FILE);
+    loop {
+        match create_lock(&lock_path, data_dir) {
+            Ok(()) => {
+                // The lock is created first, so that the lock file is removed if
+                // the endpoint can not be bound.
+                let mut lock = InstanceLock {
+                    data_dir: data_dir.to_owned(),
+                    listener: None,
+                };
+                lock.listener = Some(listen(data_dir).map_err(LockError::Endpoint)?);
+                return Ok(lock);
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                if is_held(&lock_path, data_dir) {
+                    return Err(LockError::AlreadyRunning(read_lock_pid(&lock_path)));
+                }
+                // The lock is stale, so remove it and try again.
+                fs::remove_file(&lock_path).map_err(LockError::Io)?
+            }
+            Err(err) => return Err(LockError::Io(err)),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn listen(data_dir: &Path) -> io::Result<ControlListener> {
+    let socket_path = data_dir.join(CONTROL_SOCKET);
+    // A socket left behind by a watcher that is no longer running can not be bound to.
+    remove_endpoint(data_dir);
+    ControlListener::bind(socket_path)
+}
+
+#[cfg(windows)]
+fn listen(data_dir: &Path) -> io::Result<ControlListener> {
+    let listener = ControlListener::bind(("127.0.0.1", 0))?;
+    fs::write(data_dir.join(CONTROL_PORT_FILE), listener.local_addr()?.port().to_string())?;
+    Ok(listener)
+}
+
+#[cfg(unix)]
+fn connect(data_dir: &Path) -> io::Result<ControlStream> {
+    ControlStream::connect(data_dir.join(CONTROL_SOCKET))
+}
+
+#[cfg(windows)]
+fn connect(data_dir: &Path) -> io::Result<ControlStream> {
+    let port = fs::read_to_string(data_dir.join(CONTROL_PORT_FILE))?;
+    let port: u16 = port
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid control port"))?;
+    ControlStream::connect(("127.0.0.1", port))
+}
+
+#[cfg(unix)]
+fn remove_endpoint(data_dir: &Path) {
+    fs::remove_file(data_dir.join(CONTROL_SOCKET)).ok();
+}
+
+#[cfg(windows)]
+fn remove_endpoint(data_dir: &Path) {
+    fs::remove_file(data_dir.join(CONTROL_PORT_FILE)).ok();
+}
+
+/// Accepts connections to the control endpoint, calling the handler with the
+/// commands read from each connection, and a writer for the replies.
+pub fn serve<F>(listener: ControlListener, handler: F)
+where
+    F: Fn(&mut dyn BufRead, &mut dyn Write) + Send + Clone + 'static,
+{
+    for stream in listener.incoming().flatten() {
+        let handler = handler.clone();
+        std::thread::spawn(move || {
+            if let Ok(reader) = stream.try_clone() {
+                let mut reader = BufReader::new(reader);
+                let mut writer = stream;
+                handler(&mut reader, &mut writer);
+            }
+        });
+    }
+}
+
+/// Sends the commands to the running watcher, returning its replies.
+pub fn send_commands(data_dir: &Path, commands: &[String]) -> io::Result<String> {
+    let mut stream = connect(data_dir)?;
+    for command in commands {
+        writeln!(stream, "{}", command)?;
+    }
+    stream.shutdown(Shutdown::Write)?;
+    let mut replies = String::new();
+    stream.read_to_string(&mut replies)?;
+    Ok(replies)
+}
@@ -0,0 +1,213 @@
+//! Watching of the music folders of libraries, so that the database follows
+//! changes that other programs make to tracks.
+//!
+//! When a track in a music folder is written to, its tags are read again, and it
+//! is moved if they changed, as with the `refresh` command. Tracks that are moved
+//! within the music folder keep their new path, and tracks that are deleted are
+//! removed from the database. Files that seiri moves into the music folders
+//! itself are noted with `note_own_change`, and changes to them are ignored for
+//! as long as they are as seiri left them.
+use crate::events::{Event, EventType};
+use crate::watcher::{report_watch_error, WatchedLibrary};
+use notify::DebouncedEvent;
+use seiri::config::{Config, Library};
+use seiri::database;
+use seiri::database::Connection;
+use seiri::refresh;
+use seiri::{Bang, Error, Track};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long a file that seiri moved is remembered. This must be longer than the
+/// watcher takes to report the move.
+const OWN_CHANGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A file that seiri recently moved into a music folder.
+struct OwnChange {
+    path: PathBuf,
+    /// The modification time of the file when it was moved, which changes if
+    /// another program writes to the file.
+    modified: Option<SystemTime>,
+    changed_at: Instant,
+}
+
+static OWN_CHANGES: Mutex<Vec<OwnChange>> = Mutex::new(Vec::new());
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Notes that seiri moved the file at the path into a music folder, so that the
+/// watcher of the music folder does not handle the move.
+pub fn note_own_change(path: &Path) {
+    let mut changes = OWN_CHANGES.lock().unwrap();
+    changes.retain(|change| change.changed_at.elapsed() < OWN_CHANGE_TIMEOUT);
+    changes.push(OwnChange {
+        path: path.to_owned(),
+        modified: modified(path),
+        changed_at: Instant::now(),
+    });
+}
+
+/// Gets whether the file at the path is as seiri left it after moving it.
+fn is_own_change(path: &Path) -> bool {
+    let mut changes = OWN_CHANGES.lock().unwrap();
+    changes.retain(|change| change.changed_at.elapsed() < OWN_CHANGE_TIMEOUT);
+    let modified = match modified(path) {
+        Some(modified) => modified,
+        None => return false,
+    };
+    changes
+        .iter()
+        .any(|change| change.path == path && change.modified == Some(modified))
+}
+
+/// Reads the tags of the track again, moving it if they changed, and updates it
+/// in the database. Returns None if the file of the track no longer exists, in
+/// which case it is removed from the database.
+pub fn reconsider(track: &Track, library: &Library, config: &Config, conn: &Connection) -> Result<Option<Track>, Error> {
+    let policy = config.import_policy(&track.source);
+    let new_track = refresh::refresh_track(track, library, &policy, conn)?;
+    if let Some(new_track) = &new_track {
+        if new_track.file_path != track.file_path {
+            note_own_change(&new_track.file_path);
+        }
+    }
+    Ok(new_track)
+}
+
+/// Handles a change to a music folder that seiri did not make.
+pub fn handle_event(event: DebouncedEvent, libraries: &[WatchedLibrary], config: &Config) {
+    match event {
+        DebouncedEvent::Write(path) | DebouncedEvent::Create(path) => {
+            if let Some(watched) = music_folder_of(&path, libraries) {
+                if path.is_file() && !is_own_change(&path) {
+                    changed(&path, watched, config);
+                }
+            }
+        }
+        DebouncedEvent::Rename(from, to) => {
+            if is_own_change(&to) {
+                return;
+            }
+            if let Some(watched) = music_folder_of(&from, libraries) {
+                match music_folder_of(&to, libraries) {
+                    Some(_) => moved(&from, &to, watched, config),
+                    None => removed(&from, watched),
+                }
+            }
+        }
+        DebouncedEvent::Remove(path) => {
+            // The old paths of tracks that seiri moved are no longer in the
+            // database, so they need not be told apart.
+            if let Some(watched) = music_folder_of(&path, libraries) {
+                removed(&path, watched);
+            }
+        }
+        DebouncedEvent::Error(err, path) => report_watch_error(err, path, libraries),
+        // Changes that were missed are picked up when the tracks are refreshed.
+        DebouncedEvent::Rescan
+        | DebouncedEvent::NoticeWrite(_)
+        | DebouncedEvent::NoticeRemove(_)
+        | DebouncedEvent::Chmod(_) => (),
+    }
+}
+
+/// Gets the library whose music folder the path is in, unless the path is also
+/// in an Automatically Add folder, whose changes are handled by the watcher.
+fn music_folder_of<'a>(path: &Path, libraries: &'a [WatchedLibrary]) -> Option<&'a WatchedLibrary> {
+    if libraries.iter().any(|library| path.starts_with(&library.watch_dir)) {
+        return None;
+    }
+    libraries
+        .iter()
+        .find(|library| path.starts_with(&library.library.music_folder))
+}
+
+/// Gets the tracks at the path, or in it if it is a folder.
+fn tracks_at(path: &Path, conn: &Connection) -> Vec<Track> {
+    let mut tracks = database::query_tracks(Bang::FilePath(path.to_string_lossy().into_owned()), conn, None, None)
+        .unwrap_or_default();
+    tracks.extend(database::query_tracks_in_folder(path, conn).unwrap_or_default());
+    tracks
+}
+
+/// Reads the tags of a track that was written to again.
+fn changed(path: &Path, watched: &WatchedLibrary, config: &Config) {
+    let conn = match watched.pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return,
+    };
+    // Files that are not in the library are left alone.
+    for track in tracks_at(path, &conn) {
+        update(&track, path, watched, config, &conn);
+    }
+}
+
+/// Follows tracks that were moved within the music folder to their new path.
+fn moved(from: &Path, to: &Path, watched: &WatchedLibrary, config: &Config) {
+    let conn = match watched.pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return,
+    };
+    for track in tracks_at(from, &conn) {
+        database::remove_track(&track, &conn);
+        let previous_path = track.file_path.clone();
+        let file_path = match track.file_path.strip_prefix(from) {
+            Ok(relative) if !relative.as_os_str().is_empty() => to.join(relative),
+            _ => to.to_owned(),
+        };
+        let moved_track = Track { file_path, ..track };
+        update(&moved_track, &previous_path, watched, config, &conn);
+    }
+}
+
+/// Removes the tracks that were deleted from the music folder.
+fn removed(path: &Path, watched: &WatchedLibrary) {
+    let conn = match watched.pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return,
+    };
+    for track in tracks_at(path, &conn) {
+        database::remove_track(&track, &conn);
+        report_removed(&track, &watched.library);
+    }
+}
+
+/// Reconsiders the track, which was at the previous path before it was changed.
+fn update(track: &Track, previous_path: &Path, watched: &WatchedLibrary, config: &Config, conn: &Connection) {
+    match reconsider(track, &watched.library, config, conn) {
+        Ok(Some(new_track)) => Event::new(
+            EventType::TrackUpdated,
+            format!("{}||{}", new_track.artist.trim(), new_track.title.trim()),
+        )
+        .path(&new_track.file_path)
+        .library(&watched.library.name)
+        .detail("previous_path", previous_path.display().to_string())
+        .detail("artist", new_track.artist.trim())
+        .detail("title", new_track.title.trim())
+        .detail("album", new_track.album.trim())
+        .emit(),
+        Ok(None) => report_removed(track, &watched.library),
+        Err(err) => Event::new(EventType::TrackError, track.file_path.display().to_string())
+            .path(&track.file_path)
+            .library(&watched.library.name)
+            .error(err)
+            .emit(),
+    }
+}
+
+fn report_removed(track: &Track, library: &Library) {
+    Event::new(
+        EventType::TrackRemoved,
+        format!("{}||{}", track.artist.trim(), track.title.trim()),
+    )
+    .path(&track.file_path)
+    .library(&library.name)
+    .detail("artist", track.artist.trim())
+    .detail("title", track.title.trim())
+    .detail("album", track.album.trim())
+    .emit();
+}
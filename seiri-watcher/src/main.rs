@@ -1,154 +1,473 @@
 use crossbeam::channel::{select, unbounded, Receiver, Sender};
-use leak::Leak;
 
 use std::borrow::Cow;
+use std::env;
 use std::ffi::OsStr;
+use std::fs;
 use std::io;
-use std::net::TcpListener;
 use std::path::{Path, PathBuf};
+use std::process;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+mod args;
+mod commands;
+mod events;
+mod http;
+mod instance;
+mod library;
+mod poll;
+mod pools;
+mod queue;
+mod reload;
+mod settle;
+mod staging;
 mod utils;
 mod watcher;
 
-use self::watcher::WatchStatus;
+use self::commands::CommandContext;
+use self::events::{Event, EventType};
+use self::instance::{ControlListener, LockError};
+use self::pools::LibraryPools;
+use self::queue::ImportJob;
+use self::reload::SharedConfig;
+use self::watcher::{WatchStatus, WatchedLibrary, WatcherControl};
+#[cfg(feature = "analysis")]
+use seiri::analysis;
 use seiri::config;
-use seiri::config::Config;
+use seiri::config::{Config, ImportPolicy, Library};
+use seiri::covers;
 use seiri::database;
 use seiri::database::Connection;
 use seiri::database::ConnectionPool;
 use seiri::paths;
-use seiri::ConfigErrorType;
 use seiri::Error;
 
-fn osstr_to_string(osstr: Option<&OsStr>) -> Cow<str> {
-    osstr
-        .and_then(|s| Some(s.to_string_lossy()))
-        .unwrap_or(Cow::Borrowed(""))
-}
-
-fn process(path: &Path, config: &Config, conn: &Connection, retry: bool) {
-    let track = paths::new_track_checked(path, None);
-    match paths::ensure_music_folder(&config.music_folder) {
-        Ok(library_path) => match track {
-            Ok(track) => match paths::move_new_track(&track, &library_path.0, &library_path.1) {
-                Ok(track) => {
-                    database::add_track(&track, conn);
-                    eprintln!(
-                        "TRACKADDED::{}||{}",
-                        track.artist.trim(),
-                        track.title.trim()
-                    );
-                }
-                Err(_) if retry => process(path, config, conn, false),
-                Err(Error::UnableToMove(_)) => {
-                    eprintln!("ETRACKMOVE::{}", track.file_path.display())
-                }
-                Err(Error::UnableToCreateDirectory(new_directory)) => {
-                    eprintln!("ECREATEDIRECTORY::{}", new_directory)
-                }
-                Err(_) => eprintln!("ETRACK::{}", track.file_path.display()),
-            },
-            Err(_) if retry => process(path, config, conn, false),
-            Err(err) => match err {
-                Error::UnsupportedFile(file_name) => {
-                    match paths::move_non_track(&file_name, &library_path.1) {
-                        Ok(()) => {
-                            eprintln!("ENONTRACK::{}", osstr_to_string(file_name.file_name()))
-                        }
-                        Err(_) => {
-                            eprintln!("ETRACKMOVE::{}", osstr_to_string(file_name.file_name()))
+fn osstr_to_string(osstr: Option<&OsStr>) -> Cow<'_, str> {
+    osstr.map(|s| s.to_string_lossy()).unwrap_or(Cow::Borrowed(""))
+}
+
+/// Decodes the track in full if enabled, to catch corrupt files before they are moved into the library.
+#[cfg(feature = "analysis")]
+fn verify(track: seiri::Track, config: &Config) -> Result<seiri::Track, Error> {
+    if config.verify_imports {
+        analysis::verify_track(&track)?;
+    }
+    Ok(track)
+}
+
+#[cfg(not(feature = "analysis"))]
+fn verify(track: seiri::Track, _config: &Config) -> Result<seiri::Track, Error> {
+    Ok(track)
+}
+
+/// Reads the track at the path, checking it against the import policy of its source.
+fn read_new_track(path: &Path, config: &Config, folders: &paths::LibraryFolders) -> Result<(seiri::Track, ImportPolicy), Error> {
+    let policy = config.import_policy(&paths::get_source(path, &folders.auto_add_folder));
+    paths::new_track_checked(path, None, &policy.tags)
+        .and_then(|track| paths::check_import_policy(track, &policy))
+        .and_then(|track| verify(track, config))
+        .map(|track| (track, policy))
+}
+
+/// Adds a track that was moved into the library from the source path to the database.
+fn add_to_library(track: &seiri::Track, source_path: &Path, library: &Library, policy: &ImportPolicy, conn: &Connection) {
+    database::add_track(track, conn);
+    database::set_labels(track, &policy.labels, conn);
+    if let Err(err) = covers::update_cover_cache(track, conn) {
+        Event::new(EventType::CoverCacheError, osstr_to_string(track.file_path.file_name()))
+            .path(&track.file_path)
+            .library(&library.name)
+            .error(err)
+            .emit();
+    }
+    #[cfg(feature = "analysis")]
+    {
+        if let Err(err) = analysis::update_spectral_cutoff(track, conn) {
+            Event::new(EventType::AnalysisError, osstr_to_string(track.file_path.file_name()))
+                .path(&track.file_path)
+                .library(&library.name)
+                .error(err)
+                .emit();
+        }
+    }
+    Event::new(
+        EventType::TrackAdded,
+        format!("{}||{}", track.artist.trim(), track.title.trim()),
+    )
+    .path(&track.file_path)
+    .library(&library.name)
+    .detail("source", source_path.display().to_string())
+    .detail("artist", track.artist.trim())
+    .detail("title", track.title.trim())
+    .detail("album", track.album.trim())
+    .emit();
+}
+
+/// Reports a track that could not be moved into the library.
+fn report_move_error(err: Error, track: &seiri::Track, library: &Library) {
+    let (kind, path) = match err {
+        Error::UnableToMove(_) => (EventType::TrackMoveError, track.file_path.clone()),
+        Error::UnableToCreateDirectory(ref new_directory) => {
+            (EventType::CreateDirectoryError, PathBuf::from(new_directory))
+        }
+        _ => (EventType::TrackError, track.file_path.clone()),
+    };
+    Event::new(kind, path.display().to_string())
+        .path(&path)
+        .library(&library.name)
+        .error(err)
+        .emit();
+}
+
+fn process(path: &Path, library: &Library, config: &Config, conn: &Connection, retry: bool) {
+    match paths::ensure_library_folders(library) {
+        Ok(folders) => {
+            match read_new_track(path, config, &folders) {
+                Ok((track, policy)) => match paths::move_new_track(&track, &folders.music_folder, &policy) {
+                    Ok(track) => {
+                        library::note_own_change(&track.file_path);
+                        add_to_library(&track, path, library, &policy, conn)
+                    }
+                    Err(_) if retry => process(path, library, config, conn, false),
+                    Err(err) => report_move_error(err, &track, library),
+                },
+                Err(_) if retry => process(path, library, config, conn, false),
+                Err(err) => process_error(err, library, &folders),
+            }
+        }
+        Err(err) => Event::new(EventType::LibraryNotFound, path.display().to_string())
+            .path(path)
+            .library(&library.name)
+            .error(err)
+            .emit(),
+    }
+}
+
+/// Adds the tracks in the folder of an album together. If any track can not be
+/// added, or the tracks do not belong together, the folder is rejected as a whole.
+/// Files that are not tracks are moved out of the way as usual.
+fn process_album(folder: &Path, library: &Library, config: &Config, conn: &Connection) {
+    let folders = match paths::ensure_library_folders(library) {
+        Ok(folders) => folders,
+        Err(err) => {
+            return Event::new(EventType::LibraryNotFound, folder.display().to_string())
+                .path(folder)
+                .library(&library.name)
+                .error(err)
+                .emit()
+        }
+    };
+    let mut files = match fs::read_dir(folder) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && !osstr_to_string(path.file_name()).starts_with('.'))
+            .collect::<Vec<PathBuf>>(),
+        // The folder was removed after it was queued.
+        Err(_) => return,
+    };
+    files.sort();
+
+    let mut tracks = Vec::new();
+    let mut policies = Vec::new();
+    let mut non_tracks = Vec::new();
+    for file in files {
+        match read_new_track(&file, config, &folders) {
+            Ok((track, policy)) => {
+                tracks.push(track);
+                policies.push(policy);
+            }
+            Err(Error::UnsupportedFile(file)) => non_tracks.push(file),
+            Err(err) => return reject_album(folder, err, library, &folders),
+        }
+    }
+    if let Err(err) = paths::check_album(folder, &tracks) {
+        return reject_album(folder, err, library, &folders);
+    }
+
+    let album = tracks
+        .first()
+        .map(|track| (track.album.trim().to_owned(), track.album_artists.join(", ")));
+    let track_count = tracks.len();
+    // The album is moved together, so if a track can not be moved, the tracks
+    // moved before it are put back and the album is rejected.
+    let mut moved = Vec::new();
+    for (track, policy) in tracks.into_iter().zip(policies) {
+        let source_path = track.file_path.clone();
+        match paths::move_new_track(&track, &folders.music_folder, &policy) {
+            Ok(track) => {
+                library::note_own_change(&track.file_path);
+                moved.push((track, source_path, policy));
+            }
+            Err(err) => {
+                for (track, source_path, policy) in moved {
+                    if fs::rename(&track.file_path, &source_path).is_err() {
+                        // A track that can not be put back is kept in the library.
+                        add_to_library(&track, &source_path, library, &policy, conn);
+                        continue;
+                    }
+                    // Remove the folders that were created for the track.
+                    for dir in track.file_path.ancestors().skip(1) {
+                        if dir == folders.music_folder || fs::remove_dir(dir).is_err() {
+                            break;
                         }
                     }
                 }
-                Error::FileIOError(file_name) => {
-                    eprintln!("ETRACK::{}", osstr_to_string(file_name.file_name()))
-                }
-                Error::MissingRequiredTag(file_name, tag) => eprintln!(
-                    "EMISSINGTAG::{}||{}",
-                    osstr_to_string(Path::new(&file_name).file_name()),
-                    tag
-                ),
-                _ => eprintln!("ETRACK::Unknown Error"),
-            },
-        },
-        Err(_) => eprintln!("ELIBRARYNOTFOUND::{}", path.display()),
+                return reject_album(folder, err, library, &folders);
+            }
+        }
     }
+    for (track, source_path, policy) in moved {
+        add_to_library(&track, &source_path, library, &policy, conn);
+    }
+    for file in non_tracks {
+        process_error(Error::UnsupportedFile(file), library, &folders);
+    }
+    if let Some((album, album_artists)) = album {
+        Event::new(EventType::AlbumAdded, format!("{}||{}", album_artists, album))
+            .path(folder)
+            .library(&library.name)
+            .detail("album", album)
+            .detail("album_artists", album_artists)
+            .detail("tracks", track_count.to_string())
+            .emit();
+    }
+    // The folder is removed if everything in it was moved, and is kept if it
+    // has subfolders, which may hold other albums.
+    fs::remove_dir(folder).ok();
 }
 
-fn wait_for_watch_root_available(folder: &str) -> (PathBuf, PathBuf) {
-    println!("Waiting for folder {}...", folder);
-    let wait_time = Duration::from_secs(5);
-    while let Err(_) = paths::ensure_music_folder(folder) {
-        thread::park_timeout(wait_time);
+/// Moves the folder of an album that could not be added to `.rejected`, reporting why.
+fn reject_album(folder: &Path, err: Error, library: &Library, folders: &paths::LibraryFolders) {
+    let reason = match err {
+        Error::InvalidAlbum(_, ref reason) => reason.clone(),
+        ref err => err.to_string(),
+    };
+    match paths::move_rejected_album(folder, &folders.auto_add_folder) {
+        Ok(()) => Event::new(
+            EventType::AlbumRejected,
+            format!("{}||{}", osstr_to_string(folder.file_name()), reason),
+        )
+        .path(folder)
+        .library(&library.name)
+        .error(&err)
+        .detail("reason", reason)
+        .emit(),
+        Err(move_err) => Event::new(EventType::TrackMoveError, folder.display().to_string())
+            .path(folder)
+            .library(&library.name)
+            .error(move_err)
+            .emit(),
+    }
+}
+
+/// Adds the file or album of the job to its library.
+fn import(job: &ImportJob, config: &Config, conn: &Connection) {
+    if job.album {
+        process_album(&job.path, &job.library, config, conn);
+    } else {
+        process(&job.path, &job.library, config, conn, true);
+    }
+}
+
+/// Reports a track that could not be added, moving it out of the way if it will never be added.
+fn process_error(err: Error, library: &Library, folders: &paths::LibraryFolders) {
+    let move_error = |file_name: &Path, err: Error| {
+        Event::new(EventType::TrackMoveError, osstr_to_string(file_name.file_name()))
+            .path(file_name)
+            .library(&library.name)
+            .error(err)
+            .emit()
+    };
+    match err {
+        Error::UnsupportedFile(ref file_name) => match paths::move_non_track(file_name, &folders.not_added_folder) {
+            Ok(()) => Event::new(EventType::NonTrack, osstr_to_string(file_name.file_name()))
+                .path(file_name)
+                .library(&library.name)
+                .detail("moved_to", folders.not_added_folder.display().to_string())
+                .emit(),
+            Err(move_err) => move_error(file_name, move_err),
+        },
+        Error::RejectedBySource(ref file_name, ref source) => {
+            match paths::move_rejected_track(file_name, &folders.auto_add_folder) {
+                Ok(()) => Event::new(
+                    EventType::Rejected,
+                    format!("{}||{}", osstr_to_string(file_name.file_name()), source),
+                )
+                .path(file_name)
+                .library(&library.name)
+                .error(&err)
+                .detail("source", source.as_str())
+                .emit(),
+                Err(move_err) => move_error(file_name, move_err),
+            }
+        }
+        Error::CorruptFile(ref file_name, ref defect) => {
+            match paths::move_corrupt_track(file_name, &folders.auto_add_folder) {
+                Ok(()) => Event::new(
+                    EventType::Corrupt,
+                    format!("{}||{}", osstr_to_string(file_name.file_name()), defect),
+                )
+                .path(file_name)
+                .library(&library.name)
+                .error(&err)
+                .detail("defect", defect.as_str())
+                .emit(),
+                Err(move_err) => move_error(file_name, move_err),
+            }
+        }
+        Error::FileIOError(ref file_name) => {
+            Event::new(EventType::TrackError, osstr_to_string(file_name.file_name()))
+                .path(file_name)
+                .library(&library.name)
+                .error(&err)
+                .emit()
+        }
+        Error::MissingRequiredTag(ref file_name, ref tag) => Event::new(
+            EventType::MissingTag,
+            format!("{}||{}", osstr_to_string(Path::new(file_name).file_name()), tag),
+        )
+        .path(Path::new(file_name))
+        .library(&library.name)
+        .error(&err)
+        .detail("tag", *tag)
+        .emit(),
+        _ => Event::new(EventType::TrackError, "Unknown Error")
+            .library(&library.name)
+            .error(&err)
+            .emit(),
     }
-    println!("Successfully ensured folder {}", folder);
-    paths::ensure_music_folder(folder).unwrap()
 }
 
-fn begin_watch(config: &'static Config, pool: Arc<ConnectionPool>, rx: &Receiver<WatchStatus>) {
-    let auto_paths = wait_for_watch_root_available(&config.music_folder);
-    let watch_path = &auto_paths.1.to_str().unwrap();
-    println!("Watching {}", watch_path);
-    watcher::list(&watch_path, config, pool.as_ref(), process);
-    // Create a channel to receive the events.
-    if let Err(e) = watcher::watch(&watch_path, config, pool, process, &rx) {
-        eprintln!("EWATCHER::{}", e);
+fn begin_watch(
+    control: Arc<WatcherControl>,
+    pools: Vec<(Library, Arc<ConnectionPool>)>,
+    rx: &Receiver<WatchStatus>,
+) {
+    let mut libraries = Vec::new();
+    for (library, pool) in pools {
+        // Libraries that are not available are watched once the watchdog restarts the thread.
+        if let Ok(folders) = paths::ensure_library_folders(&library) {
+            Event::new(EventType::Watching, format!("Watching {}", folders.auto_add_folder.display()))
+                .path(&folders.auto_add_folder)
+                .library(&library.name)
+                .detail("mode", poll::watch_mode(&folders.auto_add_folder, &control.config.get()).name())
+                .emit();
+            libraries.push(WatchedLibrary {
+                library,
+                watch_dir: folders.auto_add_folder,
+                pool,
+            });
+        }
+    }
+    if let Err(e) = watcher::watch(libraries, control, rx) {
+        Event::new(EventType::WatcherError, e.to_string()).error(e).emit();
     }
 }
 
 fn get_watcher_thread(
     rx: Receiver<WatchStatus>,
-    config: &'static Config,
-    pool: Arc<ConnectionPool>,
+    control: &Arc<WatcherControl>,
+    pools: &[(Library, Arc<ConnectionPool>)],
 ) -> io::Result<thread::JoinHandle<()>> {
+    let control = Arc::clone(control);
+    let pools = pools.to_vec();
     thread::Builder::new()
         .name("WatchThread".to_string())
-        .spawn(move || begin_watch(config, pool, &rx))
+        .spawn(move || begin_watch(control, pools, &rx))
 }
 
-fn start_watcher_watchdog(wait_time: Duration, config: &'static Config, pool: Arc<ConnectionPool>) -> Sender<()> {
+/// Gets the connection pool of each library.
+fn get_pools(libraries: Vec<Library>, library_pools: &LibraryPools) -> Vec<(Library, Arc<ConnectionPool>)> {
+    libraries
+        .into_iter()
+        .map(|library| {
+            let pool = library_pools.get(&library);
+            (library, pool)
+        })
+        .collect()
+}
+
+/// Gets whether the folders of each library are available.
+fn get_available_libraries(pools: &[(Library, Arc<ConnectionPool>)]) -> Vec<bool> {
+    pools
+        .iter()
+        .map(|(library, _)| paths::ensure_library_folders(library).is_ok())
+        .collect()
+}
+
+fn start_watcher_watchdog(
+    wait_time: Duration,
+    config: Arc<SharedConfig>,
+    control: Arc<WatcherControl>,
+    library_pools: Arc<LibraryPools>,
+    folders_changed: Receiver<()>,
+) -> Sender<()> {
     let (qtx, qrx) = unbounded::<()>();
 
     thread::spawn(move || {
         let (tx, rx) = unbounded();
         let mut tx = tx;
 
-        wait_for_watch_root_available(&config.music_folder);
-        let mut _watch_thread = get_watcher_thread(rx, config, Arc::clone(&pool)).unwrap();
+        let mut pools = get_pools(config.get().libraries(), &library_pools);
+        let mut available = get_available_libraries(&pools);
+        let mut _watch_thread = get_watcher_thread(rx, &control, &pools).unwrap();
         loop {
             select! {
                 recv(qrx) -> _ => {
                     // do quit stuff
                     if tx.send(WatchStatus::Exit).is_ok() {
-                        match _watch_thread.join() {
-                            _ => ()
-                        }
+                        _watch_thread.join().ok();
                     }
-                    drop(pool);
+                    drop(pools);
                     break;
                 },
+                recv(folders_changed) -> _ => {
+                    // The libraries in the configuration changed, so watch the new folders instead.
+                    pools = get_pools(config.get().libraries(), &library_pools);
+                    available = get_available_libraries(&pools);
+                    let (new_tx, rx) = unbounded();
+                    tx.send(WatchStatus::Exit).ok();
+                    Event::new(EventType::WatcherRestart, "Configuration changed. Restarting Watcher Thread...")
+                        .detail("reason", "configuration_changed")
+                        .emit();
+                    tx = new_tx.clone();
+                    _watch_thread = get_watcher_thread(rx, &control, &pools).unwrap();
+                },
                 default(wait_time) => {
                     if tx.send(WatchStatus::KeepAlive).is_err() {
-                        eprintln!("EWATCHERDIED::Keep-alive failed. Watcher thread probably panicked. Restarting Watcher Thread...");
+                        Event::new(EventType::WatcherDied, "Keep-alive failed. Watcher thread probably panicked. Restarting Watcher Thread...")
+                            .error("Keep-alive failed. Watcher thread probably panicked.")
+                            .emit();
                         let (new_tx, rx) = unbounded();
                         tx = new_tx.clone();
-                        _watch_thread = get_watcher_thread(rx, config, Arc::clone(&pool)).unwrap();
+                        _watch_thread = get_watcher_thread(rx, &control, &pools).unwrap();
                     }
 
-                    let music_folder = paths::ensure_music_folder(&config.music_folder);
-                    if music_folder.is_err() {
-                        eprintln!("EWATCHERNOACCESS::{}", &config.music_folder);
-                        wait_for_watch_root_available(&config.music_folder);
+                    // Restart the watcher thread whenever a library goes missing or comes back,
+                    // so that only the libraries that are available are watched.
+                    let now_available = get_available_libraries(&pools);
+                    if now_available != available {
+                        for ((library, _), _) in pools
+                            .iter()
+                            .zip(&now_available)
+                            .filter(|(_, is_available)| !**is_available)
+                        {
+                            Event::new(EventType::WatcherNoAccess, library.music_folder.as_str())
+                                .path(Path::new(&library.music_folder))
+                                .library(&library.name)
+                                .emit();
+                        }
+                        available = now_available;
                         let (new_tx, rx) = unbounded();
-                        tx.send(WatchStatus::Exit).unwrap();
-                        eprintln!(
-                            "EWATCHERRESTART::Requested watcher thread exit. Restarting Watcher Thread..."
-                        );
+                        tx.send(WatchStatus::Exit).ok();
+                        Event::new(EventType::WatcherRestart, "Requested watcher thread exit. Restarting Watcher Thread...")
+                            .detail("reason", "availability_changed")
+                            .emit();
                         tx = new_tx.clone();
-                        _watch_thread = get_watcher_thread(rx, config, Arc::clone(&pool)).unwrap();
+                        _watch_thread = get_watcher_thread(rx, &control, &pools).unwrap();
                     }
                 }
             }
@@ -158,43 +477,164 @@ fn start_watcher_watchdog(wait_time: Duration, config: &'static Config, pool: Ar
     qtx
 }
 
-fn ensure_port(port: u16) -> Result<TcpListener, io::Error> {
-    match TcpListener::bind(("localhost", port)) {
-        Ok(socket) => Ok(socket),
-        Err(err) => Err(err),
-    }
+/// Accepts commands from other invocations of seiri-watcher, signalling `exit_tx`
+/// when one of them asks the watcher to exit.
+fn start_control_server(listener: ControlListener, context: CommandContext, exit_tx: Sender<()>) {
+    thread::Builder::new()
+        .name("ControlThread".to_string())
+        .spawn(move || {
+            instance::serve(listener, move |input, output| {
+                if utils::run_commands(input, output, &context) {
+                    exit_tx.send(()).ok();
+                }
+            })
+        })
+        .unwrap();
+}
+
+/// Serves the HTTP API if a port is set.
+fn start_http_server(context: CommandContext) {
+    let port = match context.config.get().http_port {
+        Some(port) => port,
+        None => return,
+    };
+    let server = match http::listen(port) {
+        Ok(server) => server,
+        Err(e) => {
+            Event::new(EventType::HttpError, e.as_str()).error(e).emit();
+            return;
+        }
+    };
+    let url = format!("http://127.0.0.1:{}", port);
+    Event::new(EventType::HttpListening, format!("Serving the HTTP API at {}", url))
+        .detail("url", url)
+        .emit();
+    thread::Builder::new()
+        .name("HttpThread".to_string())
+        .spawn(move || http::serve(server, context))
+        .unwrap();
 }
 
 fn main() {
-    let _lock = ensure_port(9235).expect("ENOLOCK::Unable to acquire lock. Only have one instance of seiri running.");
+    let args = match args::parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, args::USAGE);
+            process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", args::USAGE);
+        return;
+    }
+    events::use_legacy_format(args.legacy_events);
+    if let Some(data_dir) = args.data_dir {
+        paths::set_appdata_path(data_dir);
+    }
 
-    let wait_time = Duration::from_secs(5);
-    match config::get_config() {
-        Ok(config) => {
-            // Config will stay for lifetime of the program.
-            let config = Box::new(config).leak();
-            // so will db_pool but we want to be able to drop it later.
-            let pool = database::get_connection_pool();
-            let db_pool = Arc::new(pool);
-            //let config = Arc::new(config);
-            let quit_handle = start_watcher_watchdog(wait_time, config, Arc::clone(&db_pool));
-            let conn = database::get_database_connection();
-            utils::wait_for_exit(&conn, config);
-            quit_handle.send(()).unwrap();
-            drop(conn);
-            drop(db_pool);
+    let data_dir = match paths::try_get_appdata_path() {
+        Ok(data_dir) => data_dir,
+        Err(_) if !args.commands.is_empty() => {
+            eprintln!("Unable to find the data folder. Set SEIRI_DATA_DIR or pass --data-dir.");
+            process::exit(1);
         }
         Err(err) => {
-            if let Error::ConfigError(err) = err {
-                match err {
-                    ConfigErrorType::Invalid => {
-                        eprintln!("ECONFIGINVALID::The configuration file is invalid");
-                    }
-                    ConfigErrorType::IOError(path) => {
-                        eprintln!("ECONFIGIO::{}", path);
+            reload::report_config_error(err);
+            return;
+        }
+    };
+    if !args.commands.is_empty() {
+        match instance::send_commands(&data_dir, &args.commands) {
+            Ok(replies) => print!("{}", replies),
+            Err(e) => {
+                eprintln!("Unable to reach the running watcher: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut lock = match instance::acquire_lock(&data_dir) {
+        Ok(lock) => lock,
+        Err(LockError::AlreadyRunning(Some(pid))) => {
+            let message = format!("Unable to acquire lock. seiri is already running as process {}.", pid);
+            Event::new(EventType::NoLock, message.as_str())
+                .error(message)
+                .detail("pid", pid.to_string())
+                .emit();
+            return;
+        }
+        Err(LockError::AlreadyRunning(None)) => {
+            let message = "Unable to acquire lock. seiri is already running.";
+            Event::new(EventType::NoLock, message).error(message).emit();
+            return;
+        }
+        Err(LockError::Endpoint(e)) => {
+            Event::new(EventType::ControlError, e.to_string()).error(e).emit();
+            return;
+        }
+        Err(LockError::Io(e)) => {
+            Event::new(EventType::NoLock, format!("Unable to acquire lock. {}", e))
+                .error(e)
+                .emit();
+            return;
+        }
+    };
+
+    let wait_time = Duration::from_secs(5);
+    let config_path = match args.config_path {
+        Some(config_path) => Ok(config_path),
+        None => config::get_config_path(),
+    };
+    let overrides = args.overrides;
+    match config_path.and_then(|config_path| config::load_config(&config_path, &overrides).map(|config| (config_path, config))) {
+        Ok((config_path, config)) => {
+            let config_path = config_path.canonicalize().unwrap_or(config_path);
+            // The config is swapped whenever config.toml changes.
+            let config = Arc::new(SharedConfig::new(config));
+            let (folders_changed_tx, folders_changed_rx) = unbounded::<()>();
+            let config_watcher = Arc::clone(&config);
+            thread::Builder::new()
+                .name("ConfigThread".to_string())
+                .spawn(move || {
+                    if let Err(e) = reload::watch_config(&config_path, &overrides, &config_watcher, folders_changed_tx) {
+                        Event::new(EventType::WatcherError, e.to_string()).error(e).emit();
                     }
-                }
+                })
+                .unwrap();
+            let control = Arc::new(WatcherControl::new(Arc::clone(&config)));
+            control.queue.start(import);
+            let library_pools = Arc::new(LibraryPools::new());
+            let quit_handle = start_watcher_watchdog(
+                wait_time,
+                Arc::clone(&config),
+                Arc::clone(&control),
+                Arc::clone(&library_pools),
+                folders_changed_rx,
+            );
+
+            // Exit when either stdin or a control connection asks to.
+            let (exit_tx, exit_rx) = unbounded::<()>();
+            let context = CommandContext {
+                config,
+                control,
+                pools: library_pools,
+            };
+            if let Some(listener) = lock.take_listener() {
+                start_control_server(listener, context.clone(), exit_tx.clone());
             }
+            start_http_server(context.clone());
+            thread::Builder::new()
+                .name("StdinThread".to_string())
+                .spawn(move || {
+                    if utils::wait_for_exit(&context) {
+                        exit_tx.send(()).ok();
+                    }
+                })
+                .unwrap();
+            exit_rx.recv().ok();
+            quit_handle.send(()).unwrap();
         }
+        Err(err) => reload::report_config_error(err),
     }
 }
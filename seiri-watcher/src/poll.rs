@@ -0,0 +1,312 @@
+//! Polling of folders whose changes the operating system does not report, such
+//! as folders on network filesystems.
+//!
+//! A polled folder is walked every poll interval, and the size and modification
+//! time of each file are compared with the last walk. Changes are sent as the
+//! same events that the watcher reports, so they are handled the same way. A
+//! file that disappears while a file of the same size and modification time
+//! appears is reported as renamed.
+use crate::watcher::walk;
+use crossbeam::channel::{bounded, RecvTimeoutError, Sender};
+use notify::DebouncedEvent;
+use seiri::config::{Config, WatchMode};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// The size and modification time of a file.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct FileState {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+/// The files in the polled folders, with their state at the last walk.
+type Index = HashMap<PathBuf, FileState>;
+
+/// Walks the folders, returning the files in them, and the folders that could
+/// not be read.
+fn index(folders: &[PathBuf]) -> (Index, Vec<PathBuf>) {
+    let mut files = Index::new();
+    let mut unreadable = Vec::new();
+    for folder in folders {
+        for entry in walk(folder) {
+            match entry {
+                Ok(entry) => {
+                    if !entry.file_type().is_file() {
+                        continue;
+                    }
+                    if let Ok(metadata) = entry.metadata() {
+                        let state = FileState {
+                            size: metadata.len(),
+                            modified: metadata.modified().ok(),
+                        };
+                        files.insert(entry.into_path(), state);
+                    }
+                }
+                Err(err) => {
+                    if let Some(path) = err.path() {
+                        unreadable.push(path.to_owned());
+                    }
+                }
+            }
+        }
+    }
+    (files, unreadable)
+}
+
+/// Gets the changes between the last walk and this one.
+fn diff(old: &Index, new: &Index) -> Vec<DebouncedEvent> {
+    let mut events = Vec::new();
+    let mut created: HashMap<&FileState, Vec<&PathBuf>> = HashMap::new();
+    let mut removed: HashMap<&FileState, Vec<&PathBuf>> = HashMap::new();
+    for (path, state) in new {
+        match old.get(path) {
+            None => created.entry(state).or_default().push(path),
+            Some(old_state) if old_state != state => events.push(DebouncedEvent::Write(path.clone())),
+            Some(_) => (),
+        }
+    }
+    for (path, state) in old {
+        if !new.contains_key(path) {
+            removed.entry(state).or_default().push(path);
+        }
+    }
+    // Renames keep the size and modification time, so a file that disappeared
+    // is only paired with the one file that appeared in the same state.
+    for (state, from) in removed {
+        match created.get(state) {
+            Some(to) if from.len() == 1 && to.len() == 1 => {
+                events.push(DebouncedEvent::Rename(from[0].clone(), to[0].clone()));
+                created.remove(state);
+            }
+            _ => events.extend(from.into_iter().map(|path| DebouncedEvent::Remove(path.clone()))),
+        }
+    }
+    events.extend(
+        created
+            .into_values()
+            .flatten()
+            .map(|path| DebouncedEvent::Create(path.clone())),
+    );
+    events
+}
+
+/// Keeps the files in folders that could not be read as they were, so that a
+/// folder that is briefly unreachable does not look deleted.
+fn keep_unreadable(old: &Index, new: &mut Index, unreadable: &[PathBuf]) {
+    for (path, state) in old {
+        if unreadable.iter().any(|folder| path.starts_with(folder)) {
+            new.insert(path.clone(), state.clone());
+        }
+    }
+}
+
+/// Polls folders for changes until it is dropped.
+pub struct PollWatcher {
+    _stop_tx: Sender<()>,
+}
+
+impl PollWatcher {
+    /// Polls the folders every interval, sending their changes to `tx`. The
+    /// folders are walked once before this returns, so that changes made after
+    /// it returns are reported.
+    pub fn new(folders: Vec<PathBuf>, tx: Sender<DebouncedEvent>, interval: Duration) -> PollWatcher {
+        let (stop_tx, stop_rx) = bounded::<()>(0);
+        let (mut files, _) = index(&folders);
+        // The thread stops once the watcher is dropped and its sender with it.
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                let (mut new_files, unreadable) = index(&folders);
+                keep_unreadable(&files, &mut new_files, &unreadable);
+                for event in diff(&files, &new_files) {
+                    if tx.send(event).is_err() {
+                        return;
+                    }
+                }
+                files = new_files;
+            }
+        });
+        PollWatcher { _stop_tx: stop_tx }
+    }
+}
+
+/// Gets how the folder is watched, resolving `WatchMode::Auto` by whether the
+/// folder is on a network filesystem.
+pub fn watch_mode(folder: &Path, config: &Config) -> WatchMode {
+    match config.watch_mode {
+        WatchMode::Auto if is_network_folder(folder) => WatchMode::Poll,
+        WatchMode::Auto => WatchMode::Events,
+        mode => mode,
+    }
+}
+
+/// Gets the interval between walks of polled folders.
+pub fn poll_interval(config: &Config) -> Duration {
+    Duration::from_secs(config.poll_interval.max(1))
+}
+
+#[cfg(target_os = "linux")]
+fn is_network_folder(folder: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    // The magic numbers of NFS, SMB, SMB2, CIFS, Coda, AFS and 9P, which is how
+    // WSL 2 mounts Windows drives.
+    const NETWORK_FILESYSTEMS: &[u32] = &[
+        0x6969, 0x517b, 0xfe53_4d42, 0xff53_4d42, 0x7375_7245, 0x5346_414f, 0x0102_1997,
+    ];
+    let path = match CString::new(folder.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return false;
+    }
+    NETWORK_FILESYSTEMS.contains(&(stat.f_type as u32))
+}
+
+#[cfg(target_os = "macos")]
+fn is_network_folder(folder: &Path) -> bool {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    const NETWORK_FILESYSTEMS: &[&str] = &["nfs", "smbfs", "afpfs", "webdav", "cifs"];
+    let path = match CString::new(folder.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return false;
+    }
+    let name = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    NETWORK_FILESYSTEMS.contains(&name.to_string_lossy().as_ref())
+}
+
+/// Only UNC paths are known to be on a network share. Folders on mapped drives
+/// need `watch_mode = "poll"`.
+#[cfg(windows)]
+fn is_network_folder(folder: &Path) -> bool {
+    use std::path::{Component, Prefix};
+
+    match folder.components().next() {
+        Some(Component::Prefix(prefix)) => matches!(prefix.kind(), Prefix::UNC(..) | Prefix::VerbatimUNC(..)),
+        _ => false,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn is_network_folder(_folder: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    fn state(size: u64, secs: u64) -> FileState {
+        FileState {
+            size,
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+        }
+    }
+
+    fn files(entries: &[(&str, FileState)]) -> Index {
+        entries
+            .iter()
+            .map(|(path, state)| (PathBuf::from(path), state.clone()))
+            .collect()
+    }
+
+    /// Describes the events, sorted so that they can be compared.
+    fn describe(events: Vec<DebouncedEvent>) -> Vec<String> {
+        let mut events = events
+            .into_iter()
+            .map(|event| match event {
+                DebouncedEvent::Create(path) => format!("create {}", path.display()),
+                DebouncedEvent::Write(path) => format!("write {}", path.display()),
+                DebouncedEvent::Remove(path) => format!("remove {}", path.display()),
+                DebouncedEvent::Rename(from, to) => format!("rename {} {}", from.display(), to.display()),
+                event => format!("{:?}", event),
+            })
+            .collect::<Vec<String>>();
+        events.sort();
+        events
+    }
+
+    #[test]
+    fn diff_reports_created_written_and_removed_files() {
+        let old = files(&[("/m/a.flac", state(1, 1)), ("/m/b.flac", state(2, 2))]);
+        let new = files(&[("/m/a.flac", state(1, 5)), ("/m/c.flac", state(3, 3))]);
+        assert_eq!(
+            describe(diff(&old, &new)),
+            vec!["create /m/c.flac", "remove /m/b.flac", "write /m/a.flac"]
+        );
+    }
+
+    #[test]
+    fn diff_reports_nothing_for_unchanged_files() {
+        let old = files(&[("/m/a.flac", state(1, 1))]);
+        assert!(diff(&old, &old.clone()).is_empty());
+    }
+
+    #[test]
+    fn diff_pairs_a_rename_by_its_state() {
+        let old = files(&[("/m/a.flac", state(1, 1)), ("/m/b.flac", state(2, 2))]);
+        let new = files(&[("/m/x/a.flac", state(1, 1)), ("/m/c.flac", state(2, 9))]);
+        assert_eq!(
+            describe(diff(&old, &new)),
+            vec!["create /m/c.flac", "remove /m/b.flac", "rename /m/a.flac /m/x/a.flac"]
+        );
+    }
+
+    #[test]
+    fn diff_does_not_pair_ambiguous_states() {
+        // Two files copied from the same source can share their size and
+        // modification time, so neither can be told apart from the other.
+        let old = files(&[("/m/a.flac", state(1, 1)), ("/m/b.flac", state(1, 1))]);
+        let new = files(&[("/m/x/a.flac", state(1, 1)), ("/m/x/b.flac", state(1, 1))]);
+        assert_eq!(
+            describe(diff(&old, &new)),
+            vec![
+                "create /m/x/a.flac",
+                "create /m/x/b.flac",
+                "remove /m/a.flac",
+                "remove /m/b.flac",
+            ]
+        );
+
+        let old = files(&[("/m/a.flac", state(1, 1))]);
+        let new = files(&[("/m/x/a.flac", state(1, 1)), ("/m/y/a.flac", state(1, 1))]);
+        assert_eq!(
+            describe(diff(&old, &new)),
+            vec!["create /m/x/a.flac", "create /m/y/a.flac", "remove /m/a.flac"]
+        );
+    }
+
+    #[test]
+    fn files_in_unreadable_folders_are_kept() {
+        let old = files(&[("/m/a/1.flac", state(1, 1)), ("/m/b/2.flac", state(2, 2))]);
+        let mut new = Index::new();
+        keep_unreadable(&old, &mut new, &[PathBuf::from("/m/a")]);
+        assert_eq!(describe(diff(&old, &new)), vec!["remove /m/b/2.flac"]);
+    }
+
+    #[test]
+    fn index_finds_files_in_subfolders() {
+        // Hidden folders are not walked, so the folder must not start with a dot.
+        let dir = Builder::new().prefix("seiri-poll-").tempdir().unwrap();
+        let root = dir.path().to_owned();
+        fs::create_dir_all(root.join("album")).unwrap();
+        fs::write(root.join("album").join("track.flac"), b"track").unwrap();
+        let (files, unreadable) = index(std::slice::from_ref(&root));
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[&root.join("album").join("track.flac")].size, 5);
+        assert!(unreadable.is_empty());
+    }
+}
@@ -0,0 +1,31 @@
+use seiri::config::Library;
+use seiri::database;
+use seiri::database::ConnectionPool;
+use std::sync::{Arc, Mutex};
+
+/// The connection pools of the libraries, shared by the watcher thread, commands
+/// and the HTTP server, so that each library has one pool.
+pub struct LibraryPools {
+    pools: Mutex<Vec<(Library, Arc<ConnectionPool>)>>,
+}
+
+impl LibraryPools {
+    pub fn new() -> LibraryPools {
+        LibraryPools {
+            pools: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Gets the pool of the library, opening a new pool if the library was not
+    /// open yet, or if its folders or database changed.
+    pub fn get(&self, library: &Library) -> Arc<ConnectionPool> {
+        let mut pools = self.pools.lock().unwrap();
+        if let Some((_, pool)) = pools.iter().find(|(open, _)| open == library) {
+            return Arc::clone(pool);
+        }
+        let pool = Arc::new(database::get_library_connection_pool(library));
+        pools.retain(|(open, _)| open.name != library.name);
+        pools.push((library.clone(), Arc::clone(&pool)));
+        pool
+    }
+}
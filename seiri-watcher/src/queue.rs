@@ -0,0 +1,308 @@
+//! The queue of files waiting to be added to the library.
+//!
+//! Files are queued in batches. The files found by a scan of an Automatically Add
+//! folder form one batch, and the files the watcher reports together form another.
+//! The next file is taken from the smallest batch, so that an album dropped in while
+//! a large import is running is added first. The queue is bounded. Files that do not
+//! fit stay in the Automatically Add folder, and are queued by a rescan once the
+//! queue has drained.
+use crate::events::{Event, EventType};
+use crate::reload::SharedConfig;
+use crossbeam::channel::Sender;
+use seiri::config::{Config, Library};
+use seiri::database::{Connection, ConnectionPool};
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use threadpool::ThreadPool;
+
+/// How often the depth of the queue is reported while files are being added.
+const REPORT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A file to add to a library, or the folder of an album to add as a whole.
+pub struct ImportJob {
+    pub path: PathBuf,
+    pub library: Library,
+    pub pool: Arc<ConnectionPool>,
+    /// Whether the path is the folder of an album.
+    pub album: bool,
+}
+
+struct Batch {
+    id: u64,
+    jobs: VecDeque<ImportJob>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    batches: Vec<Batch>,
+    /// The paths that are queued or being added, so that a file is only queued once.
+    paths: HashSet<PathBuf>,
+    queued: usize,
+    active: usize,
+    paused: bool,
+    /// Whether files were left out of the queue because it was full.
+    overflowed: bool,
+    /// Incremented whenever the queue is cleared, which stops the scans filling it.
+    generation: u64,
+    next_batch: u64,
+}
+
+impl QueueState {
+    fn new_batch(&mut self) -> u64 {
+        self.next_batch += 1;
+        self.next_batch
+    }
+
+    fn enqueue(&mut self, batch: u64, job: ImportJob) {
+        self.paths.insert(job.path.clone());
+        self.queued += 1;
+        match self.batches.iter_mut().find(|queued| queued.id == batch) {
+            Some(queued) => queued.jobs.push_back(job),
+            None => self.batches.push(Batch {
+                id: batch,
+                jobs: VecDeque::from(vec![job]),
+            }),
+        }
+    }
+
+    /// Takes the next job from the smallest batch, or the oldest of the smallest batches.
+    fn take(&mut self) -> Option<ImportJob> {
+        let index = self
+            .batches
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, batch)| (batch.jobs.len(), batch.id))
+            .map(|(index, _)| index)?;
+        let job = self.batches[index].jobs.pop_front();
+        if self.batches[index].jobs.is_empty() {
+            self.batches.remove(index);
+        }
+        self.queued -= 1;
+        job
+    }
+}
+
+/// A batch being filled by a scan.
+pub struct BatchHandle {
+    id: u64,
+    generation: u64,
+}
+
+pub struct ImportQueue {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+    config: Arc<SharedConfig>,
+    /// Requests a rescan of every library once files that did not fit have room.
+    rescan: Sender<Option<String>>,
+}
+
+/// Marks a job as finished when it is dropped, even if adding the file panicked.
+struct ActiveJob {
+    queue: Arc<ImportQueue>,
+    path: PathBuf,
+}
+
+impl Drop for ActiveJob {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock().unwrap();
+        state.active -= 1;
+        state.paths.remove(&self.path);
+        self.queue.changed.notify_all();
+    }
+}
+
+impl ImportQueue {
+    pub fn new(config: Arc<SharedConfig>, rescan: Sender<Option<String>>) -> ImportQueue {
+        ImportQueue {
+            state: Mutex::new(QueueState::default()),
+            changed: Condvar::new(),
+            config,
+            rescan,
+        }
+    }
+
+    /// Starts a batch to be filled by a scan.
+    pub fn begin_batch(&self) -> BatchHandle {
+        let mut state = self.state.lock().unwrap();
+        BatchHandle {
+            id: state.new_batch(),
+            generation: state.generation,
+        }
+    }
+
+    /// Queues the job in the batch, waiting while the queue is full. Returns false
+    /// if the queue was cleared since the batch began, and the scan should stop.
+    pub fn push(&self, batch: &BatchHandle, job: ImportJob) -> bool {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.generation != batch.generation {
+                return false;
+            }
+            if state.paths.contains(&job.path) {
+                return true;
+            }
+            if state.queued < self.config.get().import_queue_size {
+                break;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+        state.enqueue(batch.id, job);
+        self.changed.notify_all();
+        true
+    }
+
+    /// Queues the jobs as a new batch without waiting. Jobs that do not fit are left
+    /// out, and are queued by a rescan once the queue has drained.
+    pub fn try_push_batch(&self, jobs: Vec<ImportJob>) {
+        if jobs.is_empty() {
+            return;
+        }
+        let capacity = self.config.get().import_queue_size;
+        let mut state = self.state.lock().unwrap();
+        let batch = state.new_batch();
+        for job in jobs {
+            if state.paths.contains(&job.path) {
+                continue;
+            }
+            if state.queued >= capacity {
+                state.overflowed = true;
+                break;
+            }
+            state.enqueue(batch, job);
+        }
+        self.changed.notify_all();
+    }
+
+    /// Removes the queued jobs and stops the scans filling the queue. Files that
+    /// are being added are not affected.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        for batch in std::mem::take(&mut state.batches) {
+            for job in batch.jobs {
+                state.paths.remove(&job.path);
+            }
+        }
+        state.queued = 0;
+        state.overflowed = false;
+        self.changed.notify_all();
+    }
+
+    /// Removes the queued jobs for the path, or for anything in it if it is a folder,
+    /// because it was removed or moved.
+    pub fn remove(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        let QueueState {
+            batches, paths, queued, ..
+        } = &mut *state;
+        for batch in batches.iter_mut() {
+            batch.jobs.retain(|job| {
+                let removed = job.path.starts_with(path);
+                if removed {
+                    paths.remove(&job.path);
+                    *queued -= 1;
+                }
+                !removed
+            });
+        }
+        batches.retain(|batch| !batch.jobs.is_empty());
+        self.changed.notify_all();
+    }
+
+    /// Stops adding files until resumed. Files are still queued while paused.
+    pub fn pause(&self) {
+        self.state.lock().unwrap().paused = true;
+        self.changed.notify_all();
+    }
+
+    pub fn resume(&self) {
+        self.state.lock().unwrap().paused = false;
+        self.changed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    /// Gets the number of files waiting to be added, and being added.
+    pub fn depth(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.queued, state.active)
+    }
+
+    /// Starts adding the queued files, with at most `import_concurrency` files at once.
+    pub fn start<F>(self: &Arc<Self>, process: F)
+    where
+        F: Fn(&ImportJob, &Config, &Connection) + Send + Sync + Copy + 'static,
+    {
+        let queue = Arc::clone(self);
+        thread::Builder::new()
+            .name("ImportThread".to_string())
+            .spawn(move || queue.dispatch(process))
+            .unwrap();
+    }
+
+    fn dispatch<F>(self: Arc<Self>, process: F)
+    where
+        F: Fn(&ImportJob, &Config, &Connection) + Send + Sync + Copy + 'static,
+    {
+        let mut pool = ThreadPool::new(self.config.get().import_concurrency);
+        let mut reported = (0, 0);
+        let mut last_report = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let concurrency = self.config.get().import_concurrency;
+            if pool.max_count() != concurrency {
+                pool.set_num_threads(concurrency);
+            }
+
+            let depth = (state.queued, state.active);
+            if depth != reported
+                && (reported == (0, 0) || depth == (0, 0) || last_report.elapsed() >= REPORT_INTERVAL)
+            {
+                report_depth(depth, state.paused);
+                reported = depth;
+                last_report = Instant::now();
+            }
+
+            if depth == (0, 0) && state.overflowed {
+                state.overflowed = false;
+                self.rescan.send(None).ok();
+            }
+
+            if !state.paused && state.active < concurrency {
+                if let Some(job) = state.take() {
+                    state.active += 1;
+                    let active = ActiveJob {
+                        queue: Arc::clone(&self),
+                        path: job.path.clone(),
+                    };
+                    pool.execute(move || {
+                        let config = active.queue.config.get();
+                        if let Ok(conn) = job.pool.get() {
+                            process(&job, &config, &conn);
+                        }
+                        drop(active);
+                    });
+                    continue;
+                }
+            }
+            // Wake up to report the depth of the queue even if nothing changed.
+            state = self.changed.wait_timeout(state, REPORT_INTERVAL).unwrap().0;
+        }
+    }
+}
+
+fn report_depth((queued, active): (usize, usize), paused: bool) {
+    Event::new(
+        EventType::ImportQueue,
+        format!("{} files waiting to be added, {} being added", queued, active),
+    )
+    .detail("queued", queued.to_string())
+    .detail("active", active.to_string())
+    .detail("paused", paused.to_string())
+    .emit();
+}
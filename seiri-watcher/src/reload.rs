@@ -0,0 +1,122 @@
+use crate::events::{Event, EventType};
+use crossbeam::channel::{unbounded, Sender};
+use notify::DebouncedEvent;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use seiri::config;
+use seiri::config::Config;
+use seiri::ConfigErrorType;
+use seiri::Error;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// The configuration of the watcher, which is swapped whenever config.toml changes.
+pub struct SharedConfig {
+    config: RwLock<Arc<Config>>,
+}
+
+impl SharedConfig {
+    pub fn new(config: Config) -> SharedConfig {
+        SharedConfig {
+            config: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    /// Gets the current configuration.
+    pub fn get(&self) -> Arc<Config> {
+        Arc::clone(&self.config.read().unwrap())
+    }
+
+    /// Replaces the configuration, returning the previous configuration.
+    fn swap(&self, config: Config) -> Arc<Config> {
+        let mut current = self.config.write().unwrap();
+        std::mem::replace(&mut *current, Arc::new(config))
+    }
+}
+
+pub fn report_config_error(err: Error) {
+    if let Error::ConfigError(err) = err {
+        match err {
+            ConfigErrorType::Invalid => {
+                let message = "The configuration file is invalid";
+                Event::new(EventType::ConfigInvalid, message).error(message).emit();
+            }
+            ConfigErrorType::InvalidLibrary(name) => {
+                let message = format!("The library {} has an invalid name or invalid folders, or shares a folder or a database with another library", name);
+                Event::new(EventType::ConfigInvalid, message.as_str())
+                    .library(&name)
+                    .error(message)
+                    .emit();
+            }
+            ConfigErrorType::InvalidSetting(name) => {
+                let message = format!("The setting {} has an invalid value", name);
+                Event::new(EventType::ConfigInvalid, message.as_str())
+                    .error(message)
+                    .detail("setting", name)
+                    .emit();
+            }
+            ConfigErrorType::IOError(path) => {
+                Event::new(EventType::ConfigIoError, path.as_str())
+                    .path(Path::new(&path))
+                    .error("The configuration path can not be accessed")
+                    .emit();
+            }
+            ConfigErrorType::NoHomeDirectory => {
+                let message = "The home directory could not be found";
+                Event::new(EventType::ConfigNoHome, message).error(message).emit();
+            }
+        }
+    }
+}
+
+/// Watches the configuration file, and swaps in the new configuration whenever it
+/// changes, keeping the given overrides. Invalid configurations are reported and ignored.
+/// If the libraries changed, or how their folders are watched changed,
+/// `folders_changed` is notified so that the watcher thread can be restarted.
+pub fn watch_config(
+    config_path: &Path,
+    overrides: &[(String, String)],
+    config: &SharedConfig,
+    folders_changed: Sender<()>,
+) -> notify::Result<()> {
+    let (tx, rx) = unbounded::<DebouncedEvent>();
+    let mut watcher: RecommendedWatcher = Watcher::new(tx, Duration::from_secs(2))?;
+
+    // Editors usually replace the file rather than write to it, so the
+    // folder of the file is watched instead.
+    if let Some(config_folder) = config_path.parent() {
+        watcher.watch(config_folder, RecursiveMode::NonRecursive)?;
+    }
+
+    for event in rx.iter() {
+        let changed = match event {
+            DebouncedEvent::Create(ref path)
+            | DebouncedEvent::Write(ref path)
+            | DebouncedEvent::Rename(_, ref path) => path.file_name() == config_path.file_name(),
+            _ => false,
+        };
+        if !changed {
+            continue;
+        }
+        match config::read_config(config_path, overrides) {
+            Ok(new_config) => {
+                let libraries = new_config.libraries();
+                let watching = (new_config.watch_library, new_config.watch_mode, new_config.poll_interval);
+                let old_config = config.swap(new_config);
+                Event::new(
+                    EventType::ConfigReloaded,
+                    format!("Reloaded configuration from {}", config_path.display()),
+                )
+                .path(config_path)
+                .emit();
+                let restart = old_config.libraries() != libraries
+                    || (old_config.watch_library, old_config.watch_mode, old_config.poll_interval) != watching;
+                if restart && folders_changed.send(()).is_err() {
+                    break;
+                }
+            }
+            Err(err) => report_config_error(err),
+        }
+    }
+    Ok(())
+}
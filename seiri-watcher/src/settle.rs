@@ -0,0 +1,209 @@
+//! Detection of new files that have finished being written.
+//!
+//! A new file is added once its size and modification time have not changed for
+//! the settle time. Where the watcher reports writes to files and files that were
+//! written to being closed, which is on Linux, a file that is still open for
+//! writing is not added, and a closed file only needs to stay unchanged for a
+//! second.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long a file that was closed after being written to must stay unchanged.
+const CLOSED_SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// How long a file that is open for writing can go without being written to
+/// before it is added anyway, in case it being closed was not reported.
+const OPEN_FILE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// The size and modification time of a file.
+#[derive(PartialEq)]
+struct FileState {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+fn file_state(path: &Path) -> Option<FileState> {
+    let metadata = fs::metadata(path).ok().filter(|metadata| metadata.is_file())?;
+    Some(FileState {
+        size: metadata.len(),
+        modified: metadata.modified().ok(),
+    })
+}
+
+struct PendingFile {
+    state: FileState,
+    check_at: Instant,
+}
+
+pub struct SettleTracker {
+    files: HashMap<PathBuf, PendingFile>,
+    /// The files that were written to and not closed yet, with when they were
+    /// last written to.
+    writing: HashMap<PathBuf, Instant>,
+}
+
+impl SettleTracker {
+    pub fn new() -> SettleTracker {
+        SettleTracker {
+            files: HashMap::new(),
+            writing: HashMap::new(),
+        }
+    }
+
+    /// Waits for the changed file to settle.
+    pub fn changed(&mut self, path: PathBuf, settle_time: Duration) {
+        self.track(path, settle_time);
+    }
+
+    /// Notes that the file was written to, so that it is not added until it is closed.
+    pub fn written(&mut self, path: PathBuf) {
+        self.writing.insert(path, Instant::now());
+    }
+
+    /// Waits a shorter time for a file that was closed after being written to.
+    pub fn closed(&mut self, path: PathBuf, settle_time: Duration) {
+        self.writing.remove(&path);
+        self.track(path, settle_time.min(CLOSED_SETTLE_TIME));
+    }
+
+    /// Stops waiting for the file, or for the files in the folder, because it was removed or moved.
+    pub fn forget(&mut self, path: &Path) {
+        self.files.retain(|file, _| !file.starts_with(path));
+        self.writing.retain(|file, _| !file.starts_with(path));
+    }
+
+    fn is_open(&self, path: &Path) -> bool {
+        self.writing
+            .get(path)
+            .is_some_and(|written_at| written_at.elapsed() < OPEN_FILE_TIMEOUT)
+    }
+
+    fn track(&mut self, path: PathBuf, wait: Duration) {
+        let state = match file_state(&path) {
+            Some(state) => state,
+            None => {
+                self.files.remove(&path);
+                return;
+            }
+        };
+        let mut check_at = Instant::now() + wait;
+        // A file that has not changed since it was last seen keeps its place.
+        if let Some(pending) = self.files.get(&path) {
+            if pending.state == state {
+                check_at = check_at.min(pending.check_at);
+            }
+        }
+        self.files.insert(path, PendingFile { state, check_at });
+    }
+
+    /// Takes the files that did not change since they were last seen. Files
+    /// that changed or are still open for writing are checked again after the
+    /// settle time, and files that were removed are forgotten.
+    pub fn take_settled(&mut self, settle_time: Duration) -> Vec<PathBuf> {
+        let now = Instant::now();
+        let due = self
+            .files
+            .iter()
+            .filter(|(_, pending)| pending.check_at <= now)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<PathBuf>>();
+        let mut settled = Vec::new();
+        for path in due {
+            let pending = self.files.remove(&path).unwrap();
+            match file_state(&path) {
+                Some(state) if state == pending.state && !self.is_open(&path) => {
+                    self.writing.remove(&path);
+                    settled.push(path);
+                }
+                Some(state) => {
+                    self.files.insert(
+                        path,
+                        PendingFile {
+                            state,
+                            check_at: now + settle_time,
+                        },
+                    );
+                }
+                None => {
+                    self.writing.remove(&path);
+                }
+            }
+        }
+        settled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{tempdir, TempDir};
+
+    /// Creates a file with the contents in a folder that is removed when the
+    /// returned guard is dropped.
+    fn file(contents: &[u8]) -> (TempDir, PathBuf) {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("01.flac");
+        fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn unchanged_file_settles() {
+        let (_dir, path) = file(b"track");
+        let mut tracker = SettleTracker::new();
+        tracker.changed(path.clone(), Duration::ZERO);
+        assert_eq!(tracker.take_settled(Duration::ZERO), vec![path.clone()]);
+        assert!(tracker.files.is_empty());
+    }
+
+    #[test]
+    fn changed_file_is_checked_again() {
+        let (_dir, path) = file(b"track");
+        let mut tracker = SettleTracker::new();
+        tracker.changed(path.clone(), Duration::ZERO);
+        fs::write(&path, b"track, copied further").unwrap();
+        assert!(tracker.take_settled(Duration::ZERO).is_empty());
+        assert_eq!(tracker.take_settled(Duration::ZERO), vec![path.clone()]);
+    }
+
+    #[test]
+    fn open_file_never_settles() {
+        let (_dir, path) = file(b"track");
+        let mut tracker = SettleTracker::new();
+        tracker.written(path.clone());
+        tracker.changed(path.clone(), Duration::ZERO);
+        for _ in 0..3 {
+            assert!(tracker.take_settled(Duration::ZERO).is_empty());
+        }
+        assert!(tracker.files.contains_key(&path));
+    }
+
+    #[test]
+    fn closed_file_settles() {
+        let (_dir, path) = file(b"track");
+        let mut tracker = SettleTracker::new();
+        tracker.written(path.clone());
+        tracker.changed(path.clone(), Duration::ZERO);
+        assert!(tracker.take_settled(Duration::ZERO).is_empty());
+        tracker.closed(path.clone(), Duration::from_secs(60));
+        // A closed file waits no longer than CLOSED_SETTLE_TIME.
+        assert!(tracker.files[&path].check_at <= Instant::now() + CLOSED_SETTLE_TIME);
+        tracker.files.get_mut(&path).unwrap().check_at = Instant::now();
+        assert_eq!(tracker.take_settled(Duration::ZERO), vec![path.clone()]);
+        assert!(tracker.writing.is_empty());
+    }
+
+    #[test]
+    fn removed_file_is_forgotten() {
+        let (_dir, path) = file(b"track");
+        let mut tracker = SettleTracker::new();
+        tracker.written(path.clone());
+        tracker.changed(path.clone(), Duration::ZERO);
+        fs::remove_file(&path).unwrap();
+        assert!(tracker.take_settled(Duration::ZERO).is_empty());
+        assert!(tracker.files.is_empty());
+        assert!(tracker.writing.is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+//! Staging of album folders, so that an album is only added once it has been
+//! copied in full.
+//!
+//! Every change to a file in a subfolder of an Automatically Add folder stages
+//! the folder. A staged folder is added as an album once it has gone quiet,
+//! which is once no file in it has been modified for the quiet time.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub struct AlbumStager {
+    /// The staged folders, with when they are next checked.
+    folders: HashMap<PathBuf, Instant>,
+}
+
+impl AlbumStager {
+    pub fn new() -> AlbumStager {
+        AlbumStager {
+            folders: HashMap::new(),
+        }
+    }
+
+    /// Stages the folder, checking it once the quiet time has passed.
+    pub fn stage(&mut self, folder: PathBuf, quiet_time: Duration) {
+        self.folders.insert(folder, Instant::now() + quiet_time);
+    }
+
+    /// Stages the folder to be checked right away, such as a folder found by a
+    /// scan. Folders that were already staged are checked when they were due.
+    pub fn stage_now(&mut self, folder: PathBuf) {
+        self.folders.entry(folder).or_insert_with(Instant::now);
+    }
+
+    /// Restarts the quiet time of the folder if it is staged.
+    pub fn restage(&mut self, folder: &Path, quiet_time: Duration) {
+        if let Some(check_at) = self.folders.get_mut(folder) {
+            *check_at = Instant::now() + quiet_time;
+        }
+    }
+
+    /// Unstages the folder, and the folders in it, because it was removed or moved.
+    pub fn forget(&mut self, path: &Path) {
+        self.folders.retain(|folder, _| !folder.starts_with(path));
+    }
+
+    /// Takes the staged folders that have gone quiet. Folders that are due,
+    /// but had a file modified within the quiet time, are checked again later.
+    pub fn take_quiet(&mut self, quiet_time: Duration) -> Vec<PathBuf> {
+        let now = Instant::now();
+        let due = self
+            .folders
+            .iter()
+            .filter(|(_, check_at)| **check_at <= now)
+            .map(|(folder, _)| folder.clone())
+            .collect::<Vec<PathBuf>>();
+        let mut quiet = Vec::new();
+        for folder in due {
+            match time_since_modified(&folder) {
+                Some(age) if age < quiet_time => {
+                    self.folders.insert(folder, now + (quiet_time - age));
+                }
+                _ => {
+                    self.folders.remove(&folder);
+                    if folder.is_dir() {
+                        quiet.push(folder);
+                    }
+                }
+            }
+        }
+        quiet
+    }
+}
+
+/// Gets the time since a file in the folder was last modified, or None if the
+/// folder has no files or can not be read.
+fn time_since_modified(folder: &Path) -> Option<Duration> {
+    let last_modified = fs::read_dir(folder)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .filter_map(|metadata| metadata.modified().ok())
+        .max()?;
+    // A modification time in the future counts as a modification just now.
+    Some(SystemTime::now().duration_since(last_modified).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn folder_with_recent_changes_is_checked_again() {
+        let dir = tempdir().unwrap();
+        let album = dir.path().to_owned();
+        fs::write(album.join("01.flac"), b"track").unwrap();
+        let mut stager = AlbumStager::new();
+        stager.stage_now(album.clone());
+        let quiet_time = Duration::from_secs(60);
+        assert!(stager.take_quiet(quiet_time).is_empty());
+        // The folder is checked again once the file has gone unmodified for
+        // the quiet time, not a whole quiet time from now.
+        let check_at = stager.folders[&album];
+        assert!(check_at > Instant::now());
+        assert!(check_at <= Instant::now() + quiet_time);
+    }
+
+    #[test]
+    fn quiet_folder_is_taken() {
+        let dir = tempdir().unwrap();
+        let album = dir.path().to_owned();
+        fs::write(album.join("01.flac"), b"track").unwrap();
+        let mut stager = AlbumStager::new();
+        stager.stage(album.clone(), Duration::ZERO);
+        assert_eq!(stager.take_quiet(Duration::ZERO), vec![album.clone()]);
+        assert!(stager.folders.is_empty());
+    }
+
+    #[test]
+    fn restaging_restarts_the_quiet_time() {
+        let dir = tempdir().unwrap();
+        let album = dir.path().to_owned();
+        let mut stager = AlbumStager::new();
+        stager.stage(album.clone(), Duration::ZERO);
+        stager.restage(&album, Duration::from_secs(60));
+        assert!(stager.take_quiet(Duration::ZERO).is_empty());
+        assert!(stager.folders.contains_key(&album));
+    }
+
+    #[test]
+    fn removed_folders_are_forgotten() {
+        let dir = tempdir().unwrap();
+        let album = dir.path().to_owned();
+        let mut stager = AlbumStager::new();
+        stager.stage_now(album.join("Disc 1"));
+        stager.stage_now(album.join("Disc 2"));
+        stager.forget(&album.join("Disc 1"));
+        assert_eq!(stager.folders.len(), 1);
+        // Disc 2 was never created, so it is dropped rather than taken.
+        assert!(stager.take_quiet(Duration::ZERO).is_empty());
+        assert!(stager.folders.is_empty());
+    }
+}
@@ -1,53 +1,43 @@
 use std::io;
-use std::path::Path;
-use seiri::Bang;
-use seiri::database::query_tracks;
-use seiri::database::Connection;
-use seiri::paths::reconsider_track;
-use seiri::config::Config;
+use std::io::{BufRead, Write};
+use crate::commands;
+use crate::commands::{CommandContext, Request, Response, Session};
+use crate::events;
 
-pub fn wait_for_exit(conn: &Connection, config: &Config) {
+/// Runs commands from stdin. Returns true once the watcher is asked to shut down, or false if stdin is closed.
+pub fn wait_for_exit(context: &CommandContext) -> bool {
     let stdin = io::stdin();
-    println!("Type 'exit' to exit");
-    let folder = &config.music_folder;
-    let library_path = Path::new(&folder);
+    events::log("Type 'exit' to exit");
+    run_commands(stdin.lock(), io::stdout(), context)
+}
+
+/// Runs the commands read from the input, one per line, writing a response to each.
+/// Returns true once the watcher is asked to shut down, or false when the input ends.
+pub fn run_commands<R: BufRead, W: Write>(mut input_reader: R, mut output: W, context: &CommandContext) -> bool {
+    let mut session = Session::new(context);
     let mut input = String::new();
-    while let Ok(_) = stdin.read_line(&mut input) {
-        if input.trim().eq_ignore_ascii_case("exit") {
-            return;
+    while let Ok(read) = input_reader.read_line(&mut input) {
+        if read == 0 {
+            return false;
         }
-        if input.trim().starts_with("refresh") {
-            let file_name: &str = match input.trim().splitn(2, ' ').nth(1) {
-                Some(query_str) => query_str,
-                None => "",
-            };
-            let track = query_tracks(Bang::FilePath(file_name.to_owned()), conn, None, None).unwrap();
-            match track.into_iter().next() {
-                Some(track) => {
-                    reconsider_track(&track, &library_path).unwrap();
-                }
-                None => {
-                    println!("Some Error")
-                }
-            };
+        if input.trim().is_empty() {
+            input.clear();
+            continue;
         }
-        if input.trim().starts_with("query") {
-            let query_str: &str = match input.trim().splitn(2, " ").nth(1) {
-                Some(query_str) => query_str,
-                None => "",
-            };
-
-            match Bang::new(query_str) {
-                Ok(bang) => {
-                    println!("{:?}", bang);
-                  //  println!("Compiles to... -------------");
-                    let tracks = query_tracks(bang, conn, None, None);
-                    println!("{:?}", tracks)
-                },
-                Err(err) => println!("{:?}", err),
-            }
+        let (response, shutdown) = match Request::parse(&input) {
+            Ok(request) => (
+                Response::new(request.id.clone(), commands::handle(&request, &mut session, context)),
+                commands::is_shutdown(&request),
+            ),
+            Err(message) => (Response::new(None, Err(message)), false),
+        };
+        // Responses are written whole, so that they are not interleaved with events.
+        output.write_all(format!("{}\n", response.to_json()).as_bytes()).ok();
+        output.flush().ok();
+        if shutdown {
+            return true;
         }
         input.clear();
-        continue;
     }
+    false
 }
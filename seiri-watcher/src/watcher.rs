@@ -1,25 +1,27 @@
-use notify;
 use notify::DebouncedEvent;
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
-use seiri::config::Config;
-use seiri::database::{Connection, ConnectionPool};
-use seiri::paths::is_in_hidden_path;
-use std::fs::OpenOptions;
+use crate::events::{Event, EventType};
+use crate::library;
+use crate::poll;
+use crate::poll::PollWatcher;
+use crate::queue::{ImportJob, ImportQueue};
+use crate::reload::SharedConfig;
+use crate::settle::SettleTracker;
+use crate::staging::AlbumStager;
+use seiri::config::{Config, Library, WatchMode};
+use seiri::database::ConnectionPool;
+use seiri::paths::{get_source, is_in_hidden_path};
 use std::path::{Path, PathBuf};
-use crossbeam::channel::{unbounded, Receiver, select};
+use crossbeam::channel::{tick, unbounded, Receiver, Sender, select};
 use std::sync::Arc;
+use std::iter;
+use std::thread;
 use std::time::Duration;
-use threadpool::ThreadPool;
 use walkdir::{DirEntry, WalkDir};
 
-fn check_idle(path: &PathBuf) -> bool {
-    OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(false)
-        .truncate(false)
-        .open(&path).is_ok()
-}
+/// How long changes are collected before the watcher reports them. New files
+/// are then added once they have settled.
+const DEBOUNCE_DELAY: Duration = Duration::from_secs(2);
 
 fn is_hidden(entry: &DirEntry) -> bool {
     entry
@@ -29,7 +31,7 @@ fn is_hidden(entry: &DirEntry) -> bool {
         .unwrap_or(false)
 }
 
-fn is_hidden_file(entry: &PathBuf) -> bool {
+fn is_hidden_file(entry: &Path) -> bool {
     entry
         .file_name()
         .and_then(|s| s.to_str())
@@ -37,73 +39,223 @@ fn is_hidden_file(entry: &PathBuf) -> bool {
         .unwrap_or(false)
 }
 
-pub fn list<F>(watch_dir: &str, config: &Config, pool: &ConnectionPool, process: F)
-where
-    F: Fn(&Path, &Config, &Connection, bool) -> (),
-{
-    let watch_dir = Path::new(watch_dir);
-    let walker = WalkDir::new(watch_dir).into_iter();
-    for entry in walker.filter_entry(|e| !is_hidden(e)) {
-        if let Ok(entry) = entry {
-            if entry.file_type().is_file() {
-                process(entry.path(), config, &pool.get().unwrap(), true);
+/// Walks the folder, leaving out hidden files and folders.
+pub fn walk(folder: &Path) -> impl Iterator<Item = walkdir::Result<DirEntry>> {
+    WalkDir::new(folder).into_iter().filter_entry(|e| !is_hidden(e))
+}
+
+/// Gets the folder of the album that the file is in, if the file is in a
+/// subfolder of the Automatically Add folder. The folder of a source with an
+/// import rule is not an album, so tracks directly in it are added one by one,
+/// like tracks directly in the Automatically Add folder.
+fn album_folder<'a>(path: &'a Path, watch_dir: &Path, config: &Config) -> Option<&'a Path> {
+    let folder = path.parent().filter(|&folder| folder != watch_dir)?;
+    let is_source_folder =
+        folder.parent() == Some(watch_dir) && config.sources.contains_key(&get_source(path, watch_dir));
+    if is_source_folder {
+        None
+    } else {
+        Some(folder)
+    }
+}
+
+/// Queues the files in the Automatically Add folder of the library as one batch.
+/// If albums are staged, the folders of files in subfolders are sent to `albums`
+/// instead. Stops early if the queue is cleared.
+pub fn list(
+    watch_dir: &Path,
+    library: &Library,
+    pool: &Arc<ConnectionPool>,
+    queue: &ImportQueue,
+    albums: Option<&Sender<PathBuf>>,
+    config: &Config,
+) {
+    let batch = queue.begin_batch();
+    for entry in walk(watch_dir).flatten() {
+        if entry.file_type().is_file() {
+            if let Some(albums) = albums {
+                if let Some(folder) = album_folder(entry.path(), watch_dir, config) {
+                    albums.send(folder.to_owned()).ok();
+                    continue;
+                }
+            }
+            let job = ImportJob {
+                path: entry.path().to_owned(),
+                library: library.clone(),
+                pool: Arc::clone(pool),
+                album: false,
+            };
+            if !queue.push(&batch, job) {
+                break;
             }
         }
     }
 }
 
+/// Lists the Automatically Add folders of the libraries, or of the library with
+/// the given name, each on its own thread.
+fn scan(
+    libraries: &[WatchedLibrary],
+    name: Option<&str>,
+    queue: &Arc<ImportQueue>,
+    albums: Option<&Sender<PathBuf>>,
+    config: &Arc<Config>,
+) {
+    for watched in libraries {
+        if name.is_none_or(|name| name == watched.library.name) {
+            let watch_dir = watched.watch_dir.clone();
+            let library = watched.library.clone();
+            let pool = Arc::clone(&watched.pool);
+            let queue = Arc::clone(queue);
+            let albums = albums.cloned();
+            let config = Arc::clone(config);
+            thread::spawn(move || list(&watch_dir, &library, &pool, &queue, albums.as_ref(), &config));
+        }
+    }
+}
+
 pub enum WatchStatus {
     KeepAlive,
     Exit,
 }
 
-pub fn watch<F>(
-    watch_dir: &str,
-    config: &'static Config,
-    pool: Arc<ConnectionPool>,
-    process: F,
+/// Pauses, resumes and rescans the watched libraries. The control and its
+/// queue outlive restarts of the watcher thread.
+pub struct WatcherControl {
+    pub queue: Arc<ImportQueue>,
+    pub config: Arc<SharedConfig>,
+    rescan_tx: Sender<Option<String>>,
+    rescan_rx: Receiver<Option<String>>,
+}
+
+impl WatcherControl {
+    pub fn new(config: Arc<SharedConfig>) -> WatcherControl {
+        let (rescan_tx, rescan_rx) = unbounded();
+        WatcherControl {
+            queue: Arc::new(ImportQueue::new(Arc::clone(&config), rescan_tx.clone())),
+            config,
+            rescan_tx,
+            rescan_rx,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.queue.is_paused()
+    }
+
+    /// Stops adding new tracks until resumed. New tracks are still queued while paused.
+    pub fn pause(&self) {
+        self.queue.pause();
+    }
+
+    pub fn resume(&self) {
+        self.queue.resume();
+    }
+
+    /// Queues the tracks in the Automatically Add folder of the library with the
+    /// given name, or of every library if no name is given.
+    pub fn rescan(&self, library: Option<String>) {
+        self.rescan_tx.send(library).ok();
+    }
+}
+
+/// A library whose Automatically Add folder is watched.
+pub struct WatchedLibrary {
+    pub library: Library,
+    pub watch_dir: PathBuf,
+    pub pool: Arc<ConnectionPool>,
+}
+
+/// Watches folders for changes, by the events of the operating system or by
+/// polling, depending on the watch mode of each folder.
+pub struct FolderWatcher {
+    _events: RecommendedWatcher,
+    _poller: Option<PollWatcher>,
+}
+
+/// Watches the folders, sending their changes to `tx`.
+pub fn watch_folders(folders: &[&Path], tx: Sender<DebouncedEvent>, config: &Config) -> notify::Result<FolderWatcher> {
+    let mut watcher: RecommendedWatcher = Watcher::new(tx.clone(), DEBOUNCE_DELAY)?;
+    let mut polled = Vec::new();
+    for &folder in folders {
+        match poll::watch_mode(folder, config) {
+            WatchMode::Poll => polled.push(folder.to_owned()),
+            _ => watcher.watch(folder, RecursiveMode::Recursive)?,
+        }
+    }
+    let poller = if polled.is_empty() {
+        None
+    } else {
+        Some(PollWatcher::new(polled, tx, poll::poll_interval(config)))
+    };
+    Ok(FolderWatcher {
+        _events: watcher,
+        _poller: poller,
+    })
+}
+
+/// Watches the Automatically Add folders of the libraries, queueing new files,
+/// until the watcher thread is asked to exit.
+pub fn watch(
+    libraries: Vec<WatchedLibrary>,
+    control: Arc<WatcherControl>,
     quit_rx: &Receiver<WatchStatus>,
-) -> notify::Result<()>
-where
-    F: Fn(&Path, &Config, &Connection, bool) -> () + Send + Sync + Copy + 'static,
-{
+) -> notify::Result<()> {
     let (tx, rx) = unbounded::<notify::DebouncedEvent>();
-    
-    let exec_pool = ThreadPool::new(8);
-    // let process = Arc::new(process);
-    // Automatically select the best implementation for your platform.
-    // You can also access each implementation directly e.g. INotifyWatcher.
-    let mut watcher: RecommendedWatcher = Watcher::new(tx, Duration::from_secs(30))?;
 
-    // Add a path to be watched. All files and directories at that path and
-    // below will be monitored for changes.
-    watcher.watch(watch_dir, RecursiveMode::Recursive)?;
+    // All files and directories in the Automatically Add folders are watched
+    // for changes, with folders on network filesystems polled by default.
+    let watch_config = control.config.get();
+    let watch_dirs = libraries
+        .iter()
+        .map(|library| library.watch_dir.as_path())
+        .collect::<Vec<&Path>>();
+    let _watcher = watch_folders(&watch_dirs, tx, &watch_config)?;
+
+    // Writes, and files closed after being written to, are only reported by the
+    // raw watcher, so they are not known for folders that are polled.
+    #[cfg(target_os = "linux")]
+    let (_close_watcher, closed_rx) = watch_closes(&watch_dirs, &watch_config)?;
+    #[cfg(not(target_os = "linux"))]
+    let closed_rx = crossbeam::channel::never::<notify::RawEvent>();
+    let mut settler = SettleTracker::new();
+
+    // The music folders are watched for changes made by other programs if enabled.
+    let (library_tx, library_rx) = unbounded::<notify::DebouncedEvent>();
+    let _library_watcher = if watch_config.watch_library {
+        let music_folders = libraries
+            .iter()
+            .map(|library| Path::new(&library.library.music_folder))
+            .collect::<Vec<&Path>>();
+        Some(watch_folders(&music_folders, library_tx, &watch_config)?)
+    } else {
+        None
+    };
+
+    // Album folders found by scans are staged by this thread.
+    let (album_tx, album_rx) = unbounded::<PathBuf>();
+    let albums = |config: &Config| if config.album_staging { Some(&album_tx) } else { None };
+    let mut stager = AlbumStager::new();
+    let ticker = tick(Duration::from_secs(1));
 
-    // This is a simple loop, but you may want to use more complex logic here,
-    // for example to handle I/O.
-    let watch_dir = Path::new(watch_dir);
+    // Files queued by the previous watcher thread may belong to libraries that
+    // changed, so they are queued again from a fresh scan.
+    let queue = Arc::clone(&control.queue);
+    queue.clear();
+    let config = control.config.get();
+    scan(&libraries, None, &queue, albums(&config), &config);
 
     loop {
         select! {
             recv(rx) -> event => match event {
                 Ok(event) => {
-                    match event {
-                        // We only want to process events when the file is idle.
-                        // However, if the write finishes before the delay, only the create event is fired.
-                        // Otherwise, the write event will be delayed until the latest possible.
-                        DebouncedEvent::Write(ref path) | DebouncedEvent::Create(ref path) => {
-                            if check_idle(path) && path.is_file() && !is_in_hidden_path(path, watch_dir) && !is_hidden_file(path) {
-                                let db_pool = Arc::clone(&pool);
-                                let path = path.clone();
-                                exec_pool.execute(move || {
-                                    let pool_ref = &db_pool;
-                                    let db_conn = pool_ref.get().unwrap();
-                                    let path = path.as_path();
-                                    process(path, config, &db_conn, true);
-                                });
-                            }
-                        }
-                        _ => ()
+                    let config = control.config.get();
+                    let mut rescan = false;
+                    for event in iter::once(event).chain(rx.try_iter()) {
+                        rescan |= handle_event(event, &libraries, &config, &mut settler, &mut stager, &queue);
+                    }
+                    if rescan {
+                        scan(&libraries, None, &queue, albums(&config), &config);
                     }
                 }
                 // If a watch error occurred, break out of the thread
@@ -111,6 +263,59 @@ where
                 Err(_) => break,
             },
 
+            recv(library_rx) -> event => match event {
+                Ok(event) => {
+                    let config = control.config.get();
+                    for event in iter::once(event).chain(library_rx.try_iter()) {
+                        library::handle_event(event, &libraries, &config);
+                    }
+                }
+                Err(_) => break,
+            },
+
+            recv(closed_rx) -> event => if let Ok(notify::RawEvent { path: Some(path), op: Ok(op), .. }) = event {
+                let config = control.config.get();
+                let in_album = config.album_staging
+                    && libraries
+                        .iter()
+                        .any(|library| album_folder(&path, &library.watch_dir, &config).is_some());
+                if !in_album && watched_file(&path, &libraries).is_some() {
+                    if op.contains(notify::op::CLOSE_WRITE) {
+                        settler.closed(path, Duration::from_secs(config.settle_time));
+                    } else if op.contains(notify::op::WRITE) {
+                        settler.written(path);
+                    }
+                }
+            },
+
+            recv(album_rx) -> folder => if let Ok(folder) = folder {
+                stager.stage_now(folder);
+            },
+
+            recv(ticker) -> _ => {
+                let config = control.config.get();
+                // Files that settled together are queued as one batch.
+                let jobs = settler
+                    .take_settled(Duration::from_secs(config.settle_time))
+                    .into_iter()
+                    .filter_map(|path| file_job(path, &libraries))
+                    .collect();
+                queue.try_push_batch(jobs);
+
+                let quiet_time = Duration::from_secs(config.album_quiet_time);
+                let jobs = stager
+                    .take_quiet(quiet_time)
+                    .into_iter()
+                    .filter_map(|folder| album_job(folder, &libraries))
+                    .collect();
+                queue.try_push_batch(jobs);
+            },
+
+            recv(control.rescan_rx) -> rescan => if let Ok(name) = rescan {
+                let config = control.config.get();
+                scan(&libraries, name.as_deref(), &queue, albums(&config), &config);
+            },
+
             recv(quit_rx) -> keepalive => match keepalive {
                 Ok(WatchStatus::KeepAlive) => (),
                 Ok(WatchStatus::Exit) => break,
@@ -118,5 +323,154 @@ where
             }
         }
     }
+    queue.clear();
     Ok(())
 }
+
+/// Gets the library of the file, if it is a file in an Automatically Add folder
+/// that is not hidden.
+fn watched_file<'a>(path: &Path, libraries: &'a [WatchedLibrary]) -> Option<&'a WatchedLibrary> {
+    let watched = libraries.iter().find(|library| path.starts_with(&library.watch_dir))?;
+    if path.is_file() && !is_in_hidden_path(path, &watched.watch_dir) && !is_hidden_file(path) {
+        Some(watched)
+    } else {
+        None
+    }
+}
+
+/// Handles a change reported by the watcher. Returns true if the Automatically
+/// Add folders must be scanned again, because changes were missed.
+fn handle_event(
+    event: DebouncedEvent,
+    libraries: &[WatchedLibrary],
+    config: &Config,
+    settler: &mut SettleTracker,
+    stager: &mut AlbumStager,
+    queue: &ImportQueue,
+) -> bool {
+    match event {
+        // If the write finishes before the delay, only the create event is fired.
+        DebouncedEvent::Write(path) | DebouncedEvent::Create(path) => added(path, libraries, config, settler, stager),
+        // Files moved in from elsewhere are reported as created, and files moved
+        // elsewhere as removed.
+        DebouncedEvent::Rename(from, to) => {
+            removed(&from, libraries, config, settler, stager, queue);
+            added(to, libraries, config, settler, stager);
+        }
+        DebouncedEvent::Remove(path) => removed(&path, libraries, config, settler, stager, queue),
+        DebouncedEvent::Rescan => return true,
+        DebouncedEvent::Error(err, path) => report_watch_error(err, path, libraries),
+        DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) | DebouncedEvent::Chmod(_) => (),
+    }
+    false
+}
+
+/// Waits for a new or changed file, or the files in a new folder, to settle. The
+/// albums of the files are staged instead if albums are staged.
+fn added(
+    path: PathBuf,
+    libraries: &[WatchedLibrary],
+    config: &Config,
+    settler: &mut SettleTracker,
+    stager: &mut AlbumStager,
+) {
+    let watched = match libraries.iter().find(|library| path.starts_with(&library.watch_dir)) {
+        Some(watched) => watched,
+        None => return,
+    };
+    if is_in_hidden_path(&path, &watched.watch_dir) || is_hidden_file(&path) {
+        return;
+    }
+    let files = if path.is_dir() {
+        // A folder moved in whole is reported without the files in it.
+        walk(&path)
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path())
+            .collect()
+    } else if path.is_file() {
+        vec![path]
+    } else {
+        return;
+    };
+    let settle_time = Duration::from_secs(config.settle_time);
+    let quiet_time = Duration::from_secs(config.album_quiet_time);
+    for file in files {
+        match album_folder(&file, &watched.watch_dir, config) {
+            Some(folder) if config.album_staging => stager.stage(folder.to_owned(), quiet_time),
+            _ => settler.changed(file, settle_time),
+        }
+    }
+}
+
+/// Stops waiting for a file or folder that was removed or moved away, and
+/// removes it from the queue.
+fn removed(
+    path: &Path,
+    libraries: &[WatchedLibrary],
+    config: &Config,
+    settler: &mut SettleTracker,
+    stager: &mut AlbumStager,
+    queue: &ImportQueue,
+) {
+    settler.forget(path);
+    stager.forget(path);
+    queue.remove(path);
+    // A staged album that loses a file is still being changed.
+    if let Some(watched) = libraries.iter().find(|library| path.starts_with(&library.watch_dir)) {
+        if let Some(folder) = album_folder(path, &watched.watch_dir, config) {
+            stager.restage(folder, Duration::from_secs(config.album_quiet_time));
+        }
+    }
+}
+
+/// Reports an error of the watcher, with the path and library it concerns if it has one.
+pub fn report_watch_error(err: notify::Error, path: Option<PathBuf>, libraries: &[WatchedLibrary]) {
+    let mut event = Event::new(EventType::WatcherError, err.to_string()).error(&err);
+    if let Some(path) = &path {
+        event = event.path(path);
+        if let Some(watched) = libraries.iter().find(|library| path.starts_with(&library.watch_dir)) {
+            event = event.library(&watched.library.name);
+        }
+    }
+    event.emit();
+}
+
+/// Gets the job for a new file that has settled.
+fn file_job(path: PathBuf, libraries: &[WatchedLibrary]) -> Option<ImportJob> {
+    let watched = watched_file(&path, libraries)?;
+    Some(ImportJob {
+        library: watched.library.clone(),
+        pool: Arc::clone(&watched.pool),
+        path,
+        album: false,
+    })
+}
+
+/// Watches the Automatically Add folders that are not polled for writes, and for
+/// files that are closed after being written to.
+#[cfg(target_os = "linux")]
+fn watch_closes(
+    watch_dirs: &[&Path],
+    config: &Config,
+) -> notify::Result<(RecommendedWatcher, Receiver<notify::RawEvent>)> {
+    let (tx, rx) = unbounded::<notify::RawEvent>();
+    let mut watcher = notify::raw_watcher(tx)?;
+    for &watch_dir in watch_dirs {
+        if poll::watch_mode(watch_dir, config) != WatchMode::Poll {
+            watcher.watch(watch_dir, RecursiveMode::Recursive)?;
+        }
+    }
+    Ok((watcher, rx))
+}
+
+/// Gets the job for an album folder that has gone quiet.
+fn album_job(folder: PathBuf, libraries: &[WatchedLibrary]) -> Option<ImportJob> {
+    let watched = libraries.iter().find(|library| folder.starts_with(&library.watch_dir))?;
+    Some(ImportJob {
+        path: folder,
+        library: watched.library.clone(),
+        pool: Arc::clone(&watched.pool),
+        album: true,
+    })
+}